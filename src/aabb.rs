@@ -0,0 +1,158 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3D, Vec3};
+
+// Planar shapes get this much thickness so their boxes never have zero
+// volume.
+const MIN_EXTENT: f64 = 1e-4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    min: Point3D,
+    max: Point3D,
+}
+
+impl Aabb {
+    // The box spanning two corners, in any order.
+    pub fn new(a: Point3D, b: Point3D) -> Self {
+        let min = Point3D::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3D::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let pad = |min: f64, max: f64| match max - min < MIN_EXTENT {
+            true => (
+                (min + max - MIN_EXTENT) / 2.0,
+                (min + max + MIN_EXTENT) / 2.0,
+            ),
+            false => (min, max),
+        };
+
+        let (x0, x1) = pad(min.x(), max.x());
+        let (y0, y1) = pad(min.y(), max.y());
+        let (z0, z1) = pad(min.z(), max.z());
+        Aabb {
+            min: Point3D::new(x0, y0, z0),
+            max: Point3D::new(x1, y1, z1),
+        }
+    }
+
+    // The smallest box holding every point. There must be at least one.
+    pub fn from_points(points: &[Point3D]) -> Self {
+        assert!(
+            !points.is_empty(),
+            "a bounding box needs at least one point"
+        );
+        let (min, max) = points[1..]
+            .iter()
+            .fold((points[0], points[0]), |(min, max), point| {
+                (
+                    Point3D::new(
+                        min.x().min(point.x()),
+                        min.y().min(point.y()),
+                        min.z().min(point.z()),
+                    ),
+                    Point3D::new(
+                        max.x().max(point.x()),
+                        max.y().max(point.y()),
+                        max.z().max(point.z()),
+                    ),
+                )
+            });
+        Aabb::new(min, max)
+    }
+
+    pub fn min(&self) -> Point3D {
+        self.min
+    }
+
+    pub fn max(&self) -> Point3D {
+        self.max
+    }
+
+    pub fn center(&self) -> Point3D {
+        (self.min + self.max) / 2.0
+    }
+
+    // The box both share. Disjoint boxes overlap in a single padded point.
+    pub fn overlap(&self, other: &Aabb) -> Aabb {
+        let min = Point3D::new(
+            self.min.x().max(other.min.x()),
+            self.min.y().max(other.min.y()),
+            self.min.z().max(other.min.z()),
+        );
+        Aabb::new(
+            min,
+            Point3D::new(
+                self.max.x().min(other.max.x()).max(min.x()),
+                self.max.y().min(other.max.y()).max(min.y()),
+                self.max.z().min(other.max.z()).max(min.z()),
+            ),
+        )
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Point3D::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            Point3D::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        )
+    }
+
+    // Slab test for whether the ray passes through the box within
+    // [t_min, t_max].
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit_span(ray, t_min, t_max).is_some()
+    }
+
+    // The part of [t_min, t_max] the ray spends inside the box.
+    pub fn hit_span(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let axis = |v: Vec3<f64>| [v.x(), v.y(), v.z()];
+        let (origin, direction) = (axis(ray.origin()), axis(ray.direction()));
+        let (min, max) = (axis(self.min), axis(self.max));
+        let (mut t_min, mut t_max) = (t_min, t_max);
+
+        for a in 0..3 {
+            let inverse = 1.0 / direction[a];
+            let mut t0 = (min[a] - origin[a]) * inverse;
+            let mut t1 = (max[a] - origin[a]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[cfg(test)]
+mod aabb_tests {
+    use super::*;
+
+    #[test]
+    fn test_slab_hit_and_padding() {
+        let flat = Aabb::new(Point3D::new(1.0, 0.0, 1.0), Point3D::new(-1.0, 0.0, -1.0));
+        assert!(flat.max().y() - flat.min().y() >= MIN_EXTENT * 0.999);
+
+        let down = Ray::new(Point3D::new(0.5, 2.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(flat.hit(&down, 0.001, f64::INFINITY));
+        assert!(!flat.hit(&down, 0.001, 1.0));
+
+        let past = Ray::new(Point3D::new(2.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(!flat.hit(&past, 0.001, f64::INFINITY));
+    }
+
+    #[test]
+    #[should_panic(expected = "needs at least one point")]
+    fn test_no_points() {
+        Aabb::from_points(&[]);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::vec3::Point3D;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    // Uniform Catmull-Rom splines, which pass through every key with smooth
+    // tangents.
+    CatmullRom,
+    // Cubic Bezier segments with flat handles, so the camera eases out of
+    // and into every key.
+    Bezier,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "catmull-rom" => Some(Interpolation::CatmullRom),
+            "bezier" => Some(Interpolation::Bezier),
+            _ => None,
+        }
+    }
+
+    fn interpolate<T>(&self, p0: T, p1: T, p2: T, p3: T, t: f64) -> T
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    {
+        match self {
+            Interpolation::Linear => p1 + (p2 - p1) * t,
+            Interpolation::CatmullRom => {
+                let (t2, t3) = (t * t, t * t * t);
+                p1 + ((p2 - p0) * t
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+                    + ((p1 - p2) * 3.0 + p3 - p0) * t3)
+                    * 0.5
+            }
+            Interpolation::Bezier => p1 + (p2 - p1) * (t * t * (3.0 - 2.0 * t)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKey {
+    frame: f64,
+    origin: Point3D,
+    target: Point3D,
+    vertical_fov: f64,
+    focus_dist: f64,
+}
+
+impl CameraKey {
+    pub fn new(
+        frame: f64,
+        origin: Point3D,
+        target: Point3D,
+        vertical_fov: f64,
+        focus_dist: f64,
+    ) -> Self {
+        CameraKey {
+            frame,
+            origin,
+            target,
+            vertical_fov,
+            focus_dist,
+        }
+    }
+
+    pub fn frame(&self) -> f64 {
+        self.frame
+    }
+
+    pub fn origin(&self) -> Point3D {
+        self.origin
+    }
+
+    pub fn target(&self) -> Point3D {
+        self.target
+    }
+
+    pub fn vertical_fov(&self) -> f64 {
+        self.vertical_fov
+    }
+
+    pub fn focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraAnimation {
+    keys: Vec<CameraKey>,
+    interpolation: Interpolation,
+}
+
+impl CameraAnimation {
+    pub fn new(interpolation: Interpolation) -> Self {
+        CameraAnimation {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    // Keys are kept in frame order; a key on an existing frame replaces it.
+    pub fn add_key(mut self, key: CameraKey) -> Self {
+        match self.keys.iter().position(|other| other.frame >= key.frame) {
+            Some(index) if self.keys[index].frame == key.frame => self.keys[index] = key,
+            Some(index) => self.keys.insert(index, key),
+            None => self.keys.push(key),
+        }
+        self
+    }
+
+    pub fn set_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn keys(&self) -> &[CameraKey] {
+        &self.keys
+    }
+
+    // The camera at `frame`, held at the first and last keys outside their
+    // range.
+    pub fn at(&self, frame: f64) -> Option<CameraKey> {
+        let last = self.keys.len().checked_sub(1)?;
+        let next = self
+            .keys
+            .iter()
+            .position(|key| key.frame > frame)
+            .unwrap_or(last + 1);
+        if next == 0 || next > last {
+            let key = self.keys[next.min(last)];
+            return Some(CameraKey { frame, ..key });
+        }
+
+        let (k1, k2) = (self.keys[next - 1], self.keys[next]);
+        let k0 = self.keys[next.saturating_sub(2)];
+        let k3 = self.keys[(next + 1).min(last)];
+        let t = (frame - k1.frame) / (k2.frame - k1.frame);
+
+        let interpolation = self.interpolation;
+        Some(CameraKey {
+            frame,
+            origin: interpolation.interpolate(k0.origin, k1.origin, k2.origin, k3.origin, t),
+            target: interpolation.interpolate(k0.target, k1.target, k2.target, k3.target, t),
+            vertical_fov: interpolation.interpolate(
+                k0.vertical_fov,
+                k1.vertical_fov,
+                k2.vertical_fov,
+                k3.vertical_fov,
+                t,
+            ),
+            focus_dist: interpolation.interpolate(
+                k0.focus_dist,
+                k1.focus_dist,
+                k2.focus_dist,
+                k3.focus_dist,
+                t,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod animation_tests {
+    use super::*;
+
+    fn animation(interpolation: Interpolation) -> CameraAnimation {
+        let target = Point3D::new(0.0, 0.0, 0.0);
+        CameraAnimation::new(interpolation)
+            .add_key(CameraKey::new(
+                10.0,
+                Point3D::new(0.0, 0.0, 4.0),
+                target,
+                30.0,
+                4.0,
+            ))
+            .add_key(CameraKey::new(
+                1.0,
+                Point3D::new(0.0, 0.0, 2.0),
+                target,
+                20.0,
+                2.0,
+            ))
+            .add_key(CameraKey::new(
+                20.0,
+                Point3D::new(0.0, 0.0, 8.0),
+                target,
+                30.0,
+                8.0,
+            ))
+    }
+
+    #[test]
+    fn test_interpolation_passes_through_keys() {
+        for interpolation in &[
+            Interpolation::Linear,
+            Interpolation::CatmullRom,
+            Interpolation::Bezier,
+        ] {
+            let animation = animation(*interpolation);
+            let frames: Vec<f64> = animation.keys().iter().map(|key| key.frame()).collect();
+            assert_eq!(vec![1.0, 10.0, 20.0], frames);
+
+            for key in animation.keys() {
+                let at = animation.at(key.frame()).unwrap();
+                assert!((at.origin() - key.origin()).length() < 1e-12);
+                assert!((at.vertical_fov() - key.vertical_fov()).abs() < 1e-12);
+            }
+            assert_eq!(20.0, animation.at(0.0).unwrap().vertical_fov());
+            assert_eq!(8.0, animation.at(25.0).unwrap().focus_dist());
+        }
+
+        let linear = animation(Interpolation::Linear).at(15.0).unwrap();
+        assert!((linear.focus_dist() - 6.0).abs() < 1e-12);
+        let bezier = animation(Interpolation::Bezier).at(12.0).unwrap();
+        assert!(bezier.focus_dist() < 4.0 + 0.2 * 4.0);
+    }
+}
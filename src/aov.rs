@@ -0,0 +1,549 @@
+use crate::framebuffer::{invalid_data, read_f64, read_u32};
+use crate::objects::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::{Colour, Point3D, Vec3};
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    Position,
+    Uv,
+    Background,
+    Direct,
+    Indirect,
+    Emission,
+    // One RGB layer per light, named by `light_layer`.
+    Lights,
+}
+
+impl Aov {
+    // New passes go at the end, as checkpoints store each pass's place here.
+    pub const ALL: [Aov; 12] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Position,
+        Aov::Uv,
+        Aov::Background,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Emission,
+        Aov::Lights,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::Background => "background",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Emission => "emission",
+            Aov::Lights => "lights",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Uv => &["U", "V"],
+            Aov::Albedo
+            | Aov::Background
+            | Aov::Direct
+            | Aov::Indirect
+            | Aov::Emission
+            | Aov::Lights => &["R", "G", "B"],
+        }
+    }
+
+    // IDs can't be meaningfully averaged across samples, so the first sample
+    // to land in a pixel decides its ID.
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+// Planes of values, each named by its channel.
+pub type Channels = Vec<(&'static str, Vec<f32>)>;
+
+// Lights are keyed by the id of the emitting object, with the background
+// as light 0, as ids count from 1.
+pub const BACKGROUND_LIGHT: usize = 0;
+
+// The EXR layer holding a light's contribution.
+pub fn light_layer(light: usize) -> String {
+    match light {
+        BACKGROUND_LIGHT => "light_background".to_string(),
+        id => format!("light_{}", id),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovSample {
+    colour: Colour,
+    depth: f64,
+    normal: Vec3<f64>,
+    albedo: Colour,
+    object_id: usize,
+    material_id: usize,
+    position: Point3D,
+    uv: (f64, f64),
+    background: Colour,
+    direct: Colour,
+    indirect: Colour,
+    emission: Colour,
+    lights: Vec<(usize, Colour)>,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+
+        AovSample {
+            colour: zero,
+            depth: 0.0,
+            normal: zero,
+            albedo: zero,
+            object_id: 0,
+            material_id: 0,
+            position: zero,
+            uv: (0.0, 0.0),
+            background: zero,
+            direct: zero,
+            indirect: zero,
+            emission: zero,
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl AovSample {
+    pub fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    pub fn record_hit(&mut self, hit_record: &HitRecord, ray: &Ray) {
+        self.depth = hit_record.time() * ray.direction().length();
+        self.normal = hit_record.normal();
+        self.albedo = hit_record.material().albedo();
+        self.object_id = hit_record.object_id();
+        self.material_id = hit_record.material().id();
+        self.position = hit_record.point();
+        self.uv = hit_record.uv();
+    }
+
+    // `bounce` is the number of surfaces the path scattered off before
+    // escaping or reaching a light: zero is the sky or a light seen
+    // directly, one is direct lighting.
+    pub fn record_background(&mut self, bounce: usize, contribution: Colour) {
+        match bounce {
+            0 => self.background += contribution,
+            _ => self.record_lighting(bounce, contribution),
+        }
+        self.record_light(BACKGROUND_LIGHT, contribution);
+    }
+
+    // Light from the object `object_id`.
+    pub fn record_emission(&mut self, bounce: usize, object_id: usize, contribution: Colour) {
+        match bounce {
+            0 => self.emission += contribution,
+            _ => self.record_lighting(bounce, contribution),
+        }
+        self.record_light(object_id, contribution);
+    }
+
+    fn record_lighting(&mut self, bounce: usize, contribution: Colour) {
+        match bounce {
+            1 => self.direct += contribution,
+            _ => self.indirect += contribution,
+        }
+    }
+
+    fn record_light(&mut self, light: usize, contribution: Colour) {
+        self.colour += contribution;
+        match self.lights.iter_mut().find(|(other, _)| *other == light) {
+            Some((_, total)) => *total += contribution,
+            None => self.lights.push((light, contribution)),
+        }
+    }
+
+    // The contribution of each light the sample reached.
+    pub fn lights(&self) -> &[(usize, Colour)] {
+        &self.lights
+    }
+
+    pub fn value(&self, aov: Aov) -> [f64; 3] {
+        let vec = |v: Vec3<f64>| [v.x(), v.y(), v.z()];
+
+        match aov {
+            Aov::Depth => [self.depth, 0.0, 0.0],
+            Aov::Normal => vec(self.normal),
+            Aov::Albedo => vec(self.albedo),
+            Aov::ObjectId => [self.object_id as f64, 0.0, 0.0],
+            Aov::MaterialId => [self.material_id as f64, 0.0, 0.0],
+            Aov::Position => vec(self.position),
+            Aov::Uv => [self.uv.0, self.uv.1, 0.0],
+            Aov::Background => vec(self.background),
+            Aov::Direct => vec(self.direct),
+            Aov::Indirect => vec(self.indirect),
+            Aov::Emission => vec(self.emission),
+            // Kept per light instead.
+            Aov::Lights => [0.0; 3],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovBuffer {
+    width: i32,
+    height: i32,
+    aovs: Vec<Aov>,
+    samples: u32,
+    data: Vec<Vec<f64>>,
+    // The sums for each light seen so far, in id order, when the lights
+    // pass is wanted. Its place in `data` is left empty.
+    lights: Vec<(usize, Vec<f64>)>,
+}
+
+impl AovBuffer {
+    pub fn new(width: i32, height: i32, aovs: Vec<Aov>) -> Self {
+        let len = (width * height) as usize;
+        let data = aovs
+            .iter()
+            .map(|aov| match aov {
+                Aov::Lights => Vec::new(),
+                _ => vec![0.0; len * aov.channels().len()],
+            })
+            .collect();
+
+        AovBuffer {
+            width,
+            height,
+            aovs,
+            samples: 0,
+            data,
+            lights: Vec::new(),
+        }
+    }
+
+    pub fn blank(&self) -> Self {
+        AovBuffer::new(self.width, self.height, self.aovs.clone())
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn accumulate(&mut self, pass: &[AovSample]) {
+        for (aov, data) in self.aovs.iter().zip(self.data.iter_mut()) {
+            let channels = aov.channels().len();
+            if aov.is_id() && self.samples > 0 || *aov == Aov::Lights {
+                continue;
+            }
+
+            for (index, sample) in pass.iter().enumerate() {
+                let value = sample.value(*aov);
+                for channel in 0..channels {
+                    data[index * channels + channel] += value[channel];
+                }
+            }
+        }
+        if self.aovs.contains(&Aov::Lights) {
+            for (index, sample) in pass.iter().enumerate() {
+                for (light, colour) in sample.lights() {
+                    let sums = self.light_mut(*light);
+                    sums[index * 3] += colour.x();
+                    sums[index * 3 + 1] += colour.y();
+                    sums[index * 3 + 2] += colour.z();
+                }
+            }
+        }
+        self.samples += 1;
+    }
+
+    // The sums for `light`, starting from zero the first time it's seen.
+    fn light_mut(&mut self, light: usize) -> &mut Vec<f64> {
+        let index = match self.lights.binary_search_by_key(&light, |(id, _)| *id) {
+            Ok(index) => index,
+            Err(index) => {
+                let len = (self.width * self.height) as usize * 3;
+                self.lights.insert(index, (light, vec![0.0; len]));
+                index
+            }
+        };
+        &mut self.lights[index].1
+    }
+
+    pub fn merge(&mut self, other: &AovBuffer) {
+        for ((aov, data), other_data) in self.aovs.iter().zip(self.data.iter_mut()).zip(&other.data)
+        {
+            if aov.is_id() {
+                if self.samples == 0 {
+                    data.copy_from_slice(other_data);
+                }
+                continue;
+            }
+
+            for (value, other_value) in data.iter_mut().zip(other_data) {
+                *value += other_value;
+            }
+        }
+        for (light, other_sums) in &other.lights {
+            for (value, other_value) in self.light_mut(*light).iter_mut().zip(other_sums) {
+                *value += other_value;
+            }
+        }
+        self.samples += other.samples;
+    }
+
+    // As stored in checkpoints: the number of AOVs and each one's place in
+    // `Aov::ALL`, then the sample count and the sums, followed by the number
+    // of lights and each one's id and sums if the lights pass is kept. No
+    // AOVs at all is written as a count of zero.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.aovs.len() as u32).to_le_bytes())?;
+        if self.aovs.is_empty() {
+            return Ok(());
+        }
+        for aov in &self.aovs {
+            let index = Aov::ALL.iter().position(|other| other == aov).unwrap();
+            writer.write_all(&(index as u32).to_le_bytes())?;
+        }
+        writer.write_all(&self.samples.to_le_bytes())?;
+        for value in self.data.iter().flatten() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        if self.aovs.contains(&Aov::Lights) {
+            writer.write_all(&(self.lights.len() as u32).to_le_bytes())?;
+            for (light, sums) in &self.lights {
+                writer.write_all(&(*light as u32).to_le_bytes())?;
+                for value in sums {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `available` is the number of bytes left to read, which the sums must
+    // fit in.
+    pub fn read<R: Read>(
+        reader: &mut R,
+        width: i32,
+        height: i32,
+        available: u64,
+    ) -> io::Result<Option<Self>> {
+        let count = read_u32(reader)? as usize;
+        if count == 0 {
+            return Ok(None);
+        }
+        if count > Aov::ALL.len() {
+            return Err(invalid_data("too many AOVs in checkpoint"));
+        }
+        let mut aovs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let aov = Aov::ALL
+                .get(read_u32(reader)? as usize)
+                .ok_or_else(|| invalid_data("unknown AOV in checkpoint"))?;
+            if aovs.contains(aov) {
+                return Err(invalid_data("repeated AOV in checkpoint"));
+            }
+            aovs.push(*aov);
+        }
+        let pixels = width as u64 * height as u64;
+        let values: u64 = aovs
+            .iter()
+            .filter(|aov| **aov != Aov::Lights)
+            .map(|aov| aov.channels().len() as u64)
+            .sum();
+        if 4 * (1 + count as u64) + 4 + pixels * values * 8 > available {
+            return Err(invalid_data("checkpoint is too short for its AOVs"));
+        }
+
+        let mut aov_buffer = AovBuffer::new(width, height, aovs);
+        aov_buffer.samples = read_u32(reader)?;
+        for value in aov_buffer.data.iter_mut().flatten() {
+            *value = read_f64(reader)?;
+        }
+        if aov_buffer.aovs.contains(&Aov::Lights) {
+            for _ in 0..read_u32(reader)? {
+                // Each light's sums are read before room is made for the
+                // next, so a short file fails before it can use up memory.
+                let light = read_u32(reader)? as usize;
+                if aov_buffer.lights.last().is_some_and(|(id, _)| *id >= light) {
+                    return Err(invalid_data("lights out of order in checkpoint"));
+                }
+                let sums = aov_buffer.light_mut(light);
+                for value in sums.iter_mut() {
+                    *value = read_f64(reader)?;
+                }
+            }
+        }
+        Ok(Some(aov_buffer))
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn vectors(&self, aov: Aov) -> Option<Vec<Vec3<f64>>> {
+        let channels = self.channels(aov);
+        if channels.len() != 3 {
+            return None;
+        }
+
+        Some(
+            (0..channels[0].1.len())
+                .map(|index| {
+                    Vec3::new(
+                        channels[0].1[index] as f64,
+                        channels[1].1[index] as f64,
+                        channels[2].1[index] as f64,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    // Per-channel planes averaged over the accumulated samples, named by the
+    // channel within the AOV's layer (e.g. `normal` yields `X`, `Y`, `Z`).
+    // The lights pass has none of its own; see `light_channels`.
+    pub fn channels(&self, aov: Aov) -> Channels {
+        let position = match self.aovs.iter().position(|a| *a == aov) {
+            Some(position) if aov != Aov::Lights => position,
+            _ => return Vec::new(),
+        };
+        self.planes(&self.data[position], aov)
+    }
+
+    // The ids of the lights seen so far, in order.
+    pub fn lights(&self) -> Vec<usize> {
+        self.lights.iter().map(|(light, _)| *light).collect()
+    }
+
+    pub fn light_channels(&self, light: usize) -> Channels {
+        match self.lights.iter().find(|(id, _)| *id == light) {
+            Some((_, sums)) => self.planes(sums, Aov::Lights),
+            None => Vec::new(),
+        }
+    }
+
+    fn planes(&self, data: &[f64], aov: Aov) -> Channels {
+        let channels = aov.channels();
+        let scale = if aov.is_id() || self.samples == 0 {
+            1.0
+        } else {
+            1.0 / self.samples as f64
+        };
+
+        channels
+            .iter()
+            .enumerate()
+            .map(|(channel, name)| {
+                let plane = data
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels.len())
+                    .map(|value| (value * scale) as f32)
+                    .collect();
+                (*name, plane)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod aov_tests {
+    use super::*;
+
+    fn sample(depth: f64, object_id: usize) -> AovSample {
+        AovSample {
+            depth,
+            object_id,
+            ..AovSample::default()
+        }
+    }
+
+    #[test]
+    fn test_accumulation_and_ids() {
+        let mut buffer = AovBuffer::new(2, 1, vec![Aov::Depth, Aov::ObjectId]);
+        buffer.accumulate(&[sample(1.0, 3), sample(2.0, 0)]);
+        buffer.accumulate(&[sample(3.0, 4), sample(4.0, 5)]);
+        assert_eq!(vec![("Z", vec![2.0, 3.0])], buffer.channels(Aov::Depth));
+        // The first sample in a pixel decides its ID, even if it missed.
+        assert_eq!(vec![("id", vec![3.0, 0.0])], buffer.channels(Aov::ObjectId));
+
+        // Merging passes rendered apart gives the same, with the IDs taken
+        // from whichever buffer was merged first.
+        let mut first = buffer.blank();
+        first.accumulate(&[sample(1.0, 3), sample(2.0, 0)]);
+        let mut second = buffer.blank();
+        second.accumulate(&[sample(3.0, 4), sample(4.0, 5)]);
+        let mut merged = buffer.blank();
+        merged.merge(&first);
+        merged.merge(&second);
+        assert_eq!(buffer, merged);
+
+        let mut bytes = Vec::new();
+        buffer.write(&mut bytes).unwrap();
+        let read = AovBuffer::read(&mut bytes.as_slice(), 2, 1, bytes.len() as u64).unwrap();
+        assert_eq!(Some(buffer), read);
+    }
+
+    #[test]
+    fn test_light_passes() {
+        let (sky, lamp) = (Colour::new(0.5, 0.5, 1.0), Colour::new(4.0, 4.0, 4.0));
+        let mut seen = AovSample::default();
+        seen.record_emission(0, 3, lamp);
+        assert_eq!([4.0; 3], seen.value(Aov::Emission));
+        assert_eq!([0.0; 3], seen.value(Aov::Background));
+
+        let mut lit = AovSample::default();
+        lit.record_emission(1, 3, lamp);
+        lit.record_background(2, sky);
+        lit.record_background(0, sky);
+        assert_eq!([4.0; 3], lit.value(Aov::Direct));
+        assert_eq!([0.5, 0.5, 1.0], lit.value(Aov::Indirect));
+        assert_eq!([0.5, 0.5, 1.0], lit.value(Aov::Background));
+        assert_eq!(lamp + sky + sky, lit.colour());
+
+        // Each light gets its own layer, the sky as light 0.
+        let mut buffer = AovBuffer::new(2, 1, vec![Aov::Emission, Aov::Lights]);
+        buffer.accumulate(&[seen, lit]);
+        assert_eq!(vec![BACKGROUND_LIGHT, 3], buffer.lights());
+        assert_eq!(
+            vec![
+                ("R", vec![0.0, 1.0]),
+                ("G", vec![0.0, 1.0]),
+                ("B", vec![0.0, 2.0])
+            ],
+            buffer.light_channels(BACKGROUND_LIGHT)
+        );
+        assert_eq!(vec![("R", vec![4.0, 4.0])], buffer.light_channels(3)[..1]);
+        assert!(buffer.channels(Aov::Lights).is_empty());
+        assert_eq!("light_3", light_layer(3));
+
+        let mut bytes = Vec::new();
+        buffer.write(&mut bytes).unwrap();
+        let read = AovBuffer::read(&mut bytes.as_slice(), 2, 1, bytes.len() as u64).unwrap();
+        assert_eq!(Some(buffer), read);
+    }
+}
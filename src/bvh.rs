@@ -0,0 +1,144 @@
+use crate::aabb::Aabb;
+use crate::objects::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::Point3D;
+use std::cmp::Ordering;
+
+// Nodes with this many items or fewer aren't split further.
+const LEAF_SIZE: usize = 2;
+
+// A node of the hierarchy covering `items[start..end]`. Inner nodes'
+// children follow in `nodes`.
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    start: usize,
+    end: usize,
+    children: Option<(usize, usize)>,
+}
+
+// A bounding volume hierarchy over things known by their index, such as the
+// objects in a scene. Things without bounds, like planes, can't be placed in
+// it, so they're kept in a list of their own and tried against every ray.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<(usize, Aabb)>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new<I: IntoIterator<Item = (usize, Option<Aabb>)>>(boxes: I) -> Self {
+        let mut bvh = Bvh::default();
+        for (index, bounds) in boxes {
+            match bounds {
+                Some(bounds) => bvh.items.push((index, bounds)),
+                None => bvh.unbounded.push(index),
+            }
+        }
+        if !bvh.items.is_empty() {
+            bvh.build(0, bvh.items.len());
+        }
+        bvh
+    }
+
+    // Splits the items at the median along the longest axis of their
+    // centres until the leaves are small, returning the node's index.
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let bounds = self.items[start + 1..end]
+            .iter()
+            .fold(self.items[start].1, |bounds, (_, other)| {
+                bounds.surrounding(other)
+            });
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            start,
+            end,
+            children: None,
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        let centres: Vec<Point3D> = self.items[start..end]
+            .iter()
+            .map(|(_, bounds)| bounds.center())
+            .collect();
+        let extent = Aabb::from_points(&centres);
+        let size = extent.max() - extent.min();
+        let axis = |(_, bounds): &(usize, Aabb)| {
+            let centre = bounds.center();
+            match (size.x() >= size.y(), size.x() >= size.z()) {
+                (true, true) => centre.x(),
+                _ if size.y() >= size.z() => centre.y(),
+                _ => centre.z(),
+            }
+        };
+        self.items[start..end]
+            .sort_by(|a, b| axis(a).partial_cmp(&axis(b)).unwrap_or(Ordering::Equal));
+
+        let middle = (start + end) / 2;
+        let left = self.build(start, middle);
+        let right = self.build(middle, end);
+        self.nodes[index].children = Some((left, right));
+        index
+    }
+
+    // The closest hit along the ray, where `hit` tries the item at an index
+    // between the given limits.
+    pub fn hit<'a, F>(&self, ray: &Ray, t_min: f64, t_max: f64, hit: F) -> Option<HitRecord<'a>>
+    where
+        F: Fn(usize, f64, f64) -> Option<HitRecord<'a>>,
+    {
+        let mut closest = t_max;
+        let mut record = None;
+        for &index in &self.unbounded {
+            if let Some(other) = hit(index, t_min, closest) {
+                closest = other.time();
+                record = Some(other);
+            }
+        }
+
+        let entry = match self.nodes.first() {
+            Some(root) => root.bounds.hit_span(ray, t_min, closest),
+            None => None,
+        };
+        let mut stack = match entry {
+            Some((entry, _)) => vec![(entry, 0)],
+            None => Vec::new(),
+        };
+
+        // As for meshes, nearer children are pushed last so they're searched
+        // first and the nodes beyond the closest hit are skipped.
+        while let Some((entry, index)) = stack.pop() {
+            let node = &self.nodes[index];
+            if entry > closest {
+                continue;
+            }
+            let (left, right) = match node.children {
+                Some(children) => children,
+                None => {
+                    for (item, _) in &self.items[node.start..node.end] {
+                        if let Some(other) = hit(*item, t_min, closest) {
+                            closest = other.time();
+                            record = Some(other);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let span = |child: usize| {
+                let bounds = self.nodes[child].bounds;
+                Some((bounds.hit_span(ray, t_min, closest)?.0, child))
+            };
+            match (span(left), span(right)) {
+                (Some(near), Some(far)) if far.0 < near.0 => stack.extend([near, far]),
+                (Some(near), Some(far)) => stack.extend([far, near]),
+                (one, other) => stack.extend(one.or(other)),
+            }
+        }
+        record
+    }
+}
@@ -1,7 +1,11 @@
+use crate::lens::{Aperture, Distortion, LensSystem};
+use crate::objects::{Hit, SceneObjects};
 use crate::ray::Ray;
-use crate::utils::randon_unit_disk;
-use crate::vec3::{Point3D, Vec3};
+use crate::utils::{random_double, randon_unit_disk};
+use crate::vec3::{Colour, Point3D, Vec3};
+use std::f64::consts::PI;
 
+#[derive(Debug, Clone)]
 pub struct Viewport<T> {
     height: T,
     width: T,
@@ -21,15 +25,143 @@ impl<T: Copy> Viewport<T> {
     }
 }
 
+impl Viewport<f64> {
+    // The largest centred part of the viewport with the given aspect ratio.
+    pub fn fit(&self, aspect_ratio: f64) -> Viewport<f64> {
+        match aspect_ratio > self.width / self.height {
+            true => Viewport::new(self.width, self.width / aspect_ratio),
+            false => Viewport::new(self.height * aspect_ratio, self.height),
+        }
+    }
+}
+
+pub trait Projection {
+    fn get_ray(&self, u: f64, v: f64) -> Ray;
+
+    // The ray along with the fraction of its radiance that reaches the
+    // image, for lens effects that vignette or split colour channels.
+    fn get_weighted_ray(&self, u: f64, v: f64) -> (Ray, Colour) {
+        (self.get_ray(u, v), Colour::new(1.0, 1.0, 1.0))
+    }
+
+    // Whether the image position maps to a ray at all, e.g. fisheye pixels
+    // outside the image circle don't and are left black.
+    fn covers(&self, _u: f64, _v: f64) -> bool {
+        true
+    }
+
+    // Scale from scene radiance to image values.
+    fn exposure(&self) -> f64 {
+        1.0
+    }
+}
+
+#[derive(Debug)]
+pub enum Cameras {
+    Perspective(Camera<f64>),
+    Orthographic(Orthographic),
+    Equirectangular(Equirectangular),
+    Fisheye(Fisheye),
+    Cubemap(Cubemap),
+    Stereo(Stereo),
+    Ods(Ods),
+    Realistic(Realistic),
+}
+
+impl Projection for Cameras {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        match self {
+            Cameras::Perspective(camera) => camera.get_ray(u, v),
+            Cameras::Orthographic(orthographic) => orthographic.get_ray(u, v),
+            Cameras::Equirectangular(equirectangular) => equirectangular.get_ray(u, v),
+            Cameras::Fisheye(fisheye) => fisheye.get_ray(u, v),
+            Cameras::Cubemap(cubemap) => cubemap.get_ray(u, v),
+            Cameras::Stereo(stereo) => stereo.get_ray(u, v),
+            Cameras::Ods(ods) => ods.get_ray(u, v),
+            Cameras::Realistic(realistic) => realistic.get_ray(u, v),
+        }
+    }
+
+    fn get_weighted_ray(&self, u: f64, v: f64) -> (Ray, Colour) {
+        match self {
+            Cameras::Perspective(camera) => camera.get_weighted_ray(u, v),
+            Cameras::Stereo(stereo) => stereo.get_weighted_ray(u, v),
+            Cameras::Realistic(realistic) => realistic.get_weighted_ray(u, v),
+            _ => (self.get_ray(u, v), Colour::new(1.0, 1.0, 1.0)),
+        }
+    }
+
+    fn covers(&self, u: f64, v: f64) -> bool {
+        match self {
+            Cameras::Fisheye(fisheye) => fisheye.covers(u, v),
+            _ => true,
+        }
+    }
+
+    fn exposure(&self) -> f64 {
+        match self {
+            Cameras::Perspective(camera) => camera.exposure(),
+            Cameras::Stereo(stereo) => stereo.left.exposure(),
+            _ => 1.0,
+        }
+    }
+}
+
+// Where a camera stands and what it looks at, as given to its constructor,
+// so the camera can be described again.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    origin: Point3D,
+    target: Point3D,
+    up: Vec3<f64>,
+}
+
+impl View {
+    pub fn new(origin: Point3D, target: Point3D, up: Vec3<f64>) -> Self {
+        View { origin, target, up }
+    }
+
+    pub fn origin(&self) -> Point3D {
+        self.origin
+    }
+
+    pub fn target(&self) -> Point3D {
+        self.target
+    }
+
+    pub fn up(&self) -> Vec3<f64> {
+        self.up
+    }
+}
+
+fn look_at(origin: Point3D, target: Point3D, up: Vec3<f64>) -> (Vec3<f64>, Vec3<f64>, Vec3<f64>) {
+    let w = (origin - target).unit();
+    let u = up.cross(&w).unit();
+    let v = w.cross(&u);
+
+    (u, v, w)
+}
+
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Camera<T> {
     aspect_ratio: T,
+    vertical_fov: T,
     viewport: Viewport<T>,
+    view: View,
+    focus_dist: T,
     origin: Point3D,
     lower_left_corner: Point3D,
     horizontal: Vec3<T>,
     vertical: Vec3<T>,
     lens_radius: f64,
+    exposure: f64,
+    aperture: Aperture,
+    cat_eye: f64,
+    chromatic_aberration: f64,
+    distortion: Distortion,
+    shift: (f64, f64),
+    tilt: (f64, f64),
     u: Vec3<T>,
     v: Vec3<T>,
     w: Vec3<T>,
@@ -47,10 +179,7 @@ impl Camera<f64> {
     ) -> Self {
         let viewport_height = 2.0 * (vertical_fov.to_radians() / 2.0).tan();
         let viewport = Viewport::new(aspect_ratio * viewport_height, viewport_height);
-
-        let w = (origin - target).unit();
-        let u = up.cross(&w).unit();
-        let v = w.cross(&u);
+        let (u, v, w) = look_at(origin, target, up);
 
         let horizontal = focus_dist * viewport.width * u;
         let vertical = focus_dist * viewport.height * v;
@@ -58,23 +187,1153 @@ impl Camera<f64> {
         Camera {
             viewport,
             aspect_ratio,
+            vertical_fov,
+            view: View::new(origin, target, up),
+            focus_dist,
             origin,
             lower_left_corner: origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist,
             horizontal,
             vertical,
             lens_radius: apeture / 2.0,
+            exposure: 1.0,
+            aperture: Aperture::Circular,
+            cat_eye: 0.0,
+            chromatic_aberration: 0.0,
+            distortion: Distortion::default(),
+            shift: (0.0, 0.0),
+            tilt: (0.0, 0.0),
             u,
             v,
             w,
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = self.lens_radius * randon_unit_disk();
+    // Lens shift as a fraction of the frame width and height. Shifting rather
+    // than tilting the camera keeps vertical lines parallel.
+    pub fn set_shift(mut self, x: f64, y: f64) -> Self {
+        self.lower_left_corner +=
+            (x - self.shift.0) * self.horizontal + (y - self.shift.1) * self.vertical;
+        self.shift = (x, y);
+        self
+    }
+
+    // Scheimpflug tilt, given as the angles in degrees of the plane of sharp
+    // focus about the horizontal and vertical axes. A positive tilt brings
+    // the bottom of the frame nearer; a positive swing pushes the right side
+    // further away. The plane still passes through the focus point on the
+    // optical axis. Both are kept within 80 degrees, short of the plane
+    // turning edge on to the camera.
+    pub fn set_tilt(mut self, tilt: f64, swing: f64) -> Self {
+        self.tilt = (tilt.clamp(-80.0, 80.0), swing.clamp(-80.0, 80.0));
+        self
+    }
+
+    pub fn set_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // How far the lens barrel clips the aperture towards the frame edges, as
+    // a fraction of the aperture radius at the top and bottom of the frame.
+    pub fn set_cat_eye(mut self, cat_eye: f64) -> Self {
+        self.cat_eye = cat_eye;
+        self
+    }
+
+    // Lateral chromatic aberration: red and blue are magnified by
+    // 1 - amount and 1 + amount relative to green.
+    pub fn set_chromatic_aberration(mut self, amount: f64) -> Self {
+        self.chromatic_aberration = amount;
+        self
+    }
+
+    pub fn set_distortion(mut self, distortion: Distortion) -> Self {
+        self.distortion = distortion;
+        self
+    }
+
+    // Scale from scene radiance to image values, as set from photographic
+    // settings.
+    pub fn set_exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    // Scene units are taken to be metres when converting the aperture from
+    // millimetres. The field of view is that of the part of the sensor the
+    // image covers, for images of a different shape to the sensor.
+    pub fn from_photographic(
+        settings: &PhotographicSettings,
+        aspect_ratio: f64,
+        origin: Point3D,
+        target: Point3D,
+        up: Vec3<f64>,
+        focus_dist: f64,
+    ) -> Self {
+        Camera::new(
+            aspect_ratio,
+            settings.vertical_fov(aspect_ratio),
+            origin,
+            target,
+            up,
+            settings.aperture_diameter() / 1000.0,
+            focus_dist,
+        )
+        .set_exposure(settings.exposure())
+    }
+
+    pub fn origin(&self) -> Point3D {
+        self.origin
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    pub fn vertical_fov(&self) -> f64 {
+        self.vertical_fov
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    pub fn aperture_diameter(&self) -> f64 {
+        2.0 * self.lens_radius
+    }
+
+    pub fn aperture(&self) -> &Aperture {
+        &self.aperture
+    }
+
+    pub fn cat_eye(&self) -> f64 {
+        self.cat_eye
+    }
+
+    pub fn chromatic_aberration(&self) -> f64 {
+        self.chromatic_aberration
+    }
+
+    pub fn distortion(&self) -> Distortion {
+        self.distortion
+    }
+
+    pub fn shift(&self) -> (f64, f64) {
+        self.shift
+    }
+
+    // The tilt and swing in degrees.
+    pub fn tilt(&self) -> (f64, f64) {
+        self.tilt
+    }
+
+    pub fn focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
+
+    // Refocuses without changing the field of view, lens shift or tilt.
+    pub fn set_focus_dist(mut self, focus_dist: f64) -> Self {
+        let scale = focus_dist / self.focus_dist;
+        self.focus_dist = focus_dist;
+        self.horizontal = self.horizontal * scale;
+        self.vertical = self.vertical * scale;
+        self.lower_left_corner = self.origin + (self.lower_left_corner - self.origin) * scale;
+        self
+    }
+
+    // The focus distance that brings `point` into focus, measured along the
+    // view axis, or None if the point isn't in front of the camera.
+    pub fn focus_distance_to(&self, point: Point3D) -> Option<f64> {
+        Some((point - self.origin).dot(&-self.w)).filter(|distance| *distance > 0.0)
+    }
+
+    // Casts the ray through the centre of the lens at image position (u, v)
+    // and returns the focus distance of whatever it hits first.
+    pub fn autofocus(&self, world: &SceneObjects, u: f64, v: f64) -> Option<f64> {
+        let direction =
+            self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin;
+        let hit_record = world.hit(&Ray::new(self.origin, direction), 0.001, f64::INFINITY)?;
+
+        self.focus_distance_to(hit_record.point())
+    }
+
+    fn translated(&self, offset: Vec3<f64>) -> Self {
+        let mut camera = self.clone();
+        camera.origin += offset;
+        camera.lower_left_corner += offset;
+        camera
+    }
+
+    fn looking_at(&self, origin: Point3D, target: Point3D) -> Self {
+        Camera::new(
+            self.aspect_ratio,
+            self.vertical_fov,
+            origin,
+            target,
+            self.v,
+            self.aperture_diameter(),
+            self.focus_dist,
+        )
+        .set_exposure(self.exposure)
+        .set_aperture(self.aperture.clone())
+        .set_cat_eye(self.cat_eye)
+        .set_chromatic_aberration(self.chromatic_aberration)
+        .set_distortion(self.distortion)
+        .set_shift(self.shift.0, self.shift.1)
+        .set_tilt(self.tilt.0, self.tilt.1)
+    }
+
+    // Where the chief ray through `point` on the untilted focal plane meets
+    // the tilted one. Rays that only meet it behind the camera, or not at
+    // all, keep the untilted point.
+    fn on_focal_plane(&self, point: Point3D) -> Point3D {
+        let (tilt, swing) = (self.tilt.0.to_radians(), self.tilt.1.to_radians());
+        let normal = self.w + tilt.tan() * self.v + swing.tan() * self.u;
+        let direction = point - self.origin;
+        // The plane faces the camera, so rays that meet it in front head
+        // against its normal; those that barely do are left alone.
+        let facing = -normal.dot(&direction);
+        if facing <= 1e-6 * normal.length() * direction.length() {
+            return point;
+        }
+        let t = normal.dot(&(self.focus_dist * self.w)) / facing;
+
+        self.origin + t * direction
+    }
+}
+
+// Camera settings in photographic terms: sensor dimensions and focal length
+// in millimetres, shutter speed in seconds and ISO sensitivity.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhotographicSettings {
+    sensor_width: f64,
+    sensor_height: f64,
+    focal_length: f64,
+    f_stop: f64,
+    shutter_speed: f64,
+    iso: f64,
+}
+
+impl PhotographicSettings {
+    pub fn new(
+        sensor_width: f64,
+        sensor_height: f64,
+        focal_length: f64,
+        f_stop: f64,
+        shutter_speed: f64,
+        iso: f64,
+    ) -> Self {
+        PhotographicSettings {
+            sensor_width,
+            sensor_height,
+            focal_length,
+            f_stop,
+            shutter_speed,
+            iso,
+        }
+    }
+
+    pub fn full_frame(focal_length: f64, f_stop: f64, shutter_speed: f64, iso: f64) -> Self {
+        PhotographicSettings::new(36.0, 24.0, focal_length, f_stop, shutter_speed, iso)
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.sensor_width / self.sensor_height
+    }
+
+    // The largest part of the sensor with the image's aspect ratio, centred,
+    // as width and height in millimetres.
+    pub fn gate(&self, aspect_ratio: f64) -> (f64, f64) {
+        let gate = Viewport::new(self.sensor_width, self.sensor_height).fit(aspect_ratio);
+        (gate.width(), gate.height())
+    }
+
+    pub fn vertical_fov(&self, aspect_ratio: f64) -> f64 {
+        let height = self.gate(aspect_ratio).1;
+        (2.0 * (height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    pub fn aperture_diameter(&self) -> f64 {
+        self.focal_length / self.f_stop
+    }
+
+    pub fn ev100(&self) -> f64 {
+        (self.f_stop * self.f_stop / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    // Saturation-based sensitivity: scene luminance (in cd/m²) that maps to
+    // 1.0 is 1.2 * 2^EV100, as in Lagarde & de Rousiers, "Moving Frostbite
+    // to Physically Based Rendering".
+    pub fn exposure(&self) -> f64 {
+        1.0 / (1.2 * 2f64.powf(self.ev100()))
+    }
+}
+
+impl Projection for Camera<f64> {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        self.get_weighted_ray(u, v).0
+    }
+
+    fn get_weighted_ray(&self, u: f64, v: f64) -> (Ray, Colour) {
+        let mut weight = Colour::new(1.0, 1.0, 1.0);
+
+        // Image position relative to the centre, in units of half the height.
+        let mut x = (2.0 * u - 1.0) * self.aspect_ratio;
+        let mut y = 2.0 * v - 1.0;
+        let (mut u, mut v) = (u, v);
+
+        if self.distortion != Distortion::default() || self.chromatic_aberration != 0.0 {
+            let (distorted_x, distorted_y) = self.distortion.apply(x, y);
+            x = distorted_x;
+            y = distorted_y;
+
+            if self.chromatic_aberration != 0.0 {
+                let channel = ((random_double() * 3.0) as usize).min(2);
+                let magnification = 1.0 + self.chromatic_aberration * (channel as f64 - 1.0);
+                x *= magnification;
+                y *= magnification;
+                weight = match channel {
+                    0 => Colour::new(3.0, 0.0, 0.0),
+                    1 => Colour::new(0.0, 3.0, 0.0),
+                    _ => Colour::new(0.0, 0.0, 3.0),
+                };
+            }
+
+            u = (x / self.aspect_ratio + 1.0) / 2.0;
+            v = (y + 1.0) / 2.0;
+        }
+
+        let lens_point = self.aperture.sample();
+        if self.cat_eye > 0.0 {
+            let barrel = Vec3::new(x * self.cat_eye, y * self.cat_eye, 0.0);
+            if (lens_point - barrel).length_sqrd() > 1.0 {
+                weight = Colour::new(0.0, 0.0, 0.0);
+            }
+        }
+
+        let mut focus_point = self.lower_left_corner + u * self.horizontal + v * self.vertical;
+        if self.tilt != (0.0, 0.0) {
+            focus_point = self.on_focal_plane(focus_point);
+        }
+
+        let rd = self.lens_radius * lens_point;
         let offset = self.u * rd.x() + self.v * rd.y();
+        let ray = Ray::new(self.origin + offset, focus_point - self.origin - offset);
+
+        (ray, weight)
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+#[derive(Debug)]
+pub struct Orthographic {
+    aspect_ratio: f64,
+    viewport: Viewport<f64>,
+    view: View,
+    lower_left_corner: Point3D,
+    horizontal: Vec3<f64>,
+    vertical: Vec3<f64>,
+    direction: Vec3<f64>,
+}
+
+impl Orthographic {
+    pub fn new(
+        aspect_ratio: f64,
+        view_height: f64,
+        origin: Point3D,
+        target: Point3D,
+        up: Vec3<f64>,
+    ) -> Self {
+        let viewport = Viewport::new(aspect_ratio * view_height, view_height);
+        let (u, v, w) = look_at(origin, target, up);
+
+        let horizontal = viewport.width * u;
+        let vertical = viewport.height * v;
+
+        Orthographic {
+            aspect_ratio,
+            viewport,
+            view: View::new(origin, target, up),
+            lower_left_corner: origin - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+        }
+    }
+
+    pub fn viewport(&self) -> &Viewport<f64> {
+        &self.viewport
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+}
+
+impl Projection for Orthographic {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
         Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
+            self.lower_left_corner + u * self.horizontal + v * self.vertical,
+            self.direction,
         )
     }
 }
+
+#[derive(Debug)]
+pub struct Equirectangular {
+    view: View,
+    origin: Point3D,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+}
+
+impl Equirectangular {
+    pub fn new(origin: Point3D, target: Point3D, up: Vec3<f64>) -> Self {
+        let (u, v, w) = look_at(origin, target, up);
+        Equirectangular {
+            view: View::new(origin, target, up),
+            origin,
+            u,
+            v,
+            w,
+        }
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+}
+
+impl Projection for Equirectangular {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        let longitude = (u - 0.5) * 2.0 * PI;
+        let latitude = (v - 0.5) * PI;
+
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v;
+        Ray::new(self.origin, direction)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FisheyeMapping {
+    Equidistant,
+    Equisolid,
+}
+
+#[derive(Debug)]
+pub struct Fisheye {
+    aspect_ratio: f64,
+    fov: f64,
+    mapping: FisheyeMapping,
+    view: View,
+    origin: Point3D,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+}
+
+impl Fisheye {
+    pub fn new(
+        aspect_ratio: f64,
+        fov: f64,
+        mapping: FisheyeMapping,
+        origin: Point3D,
+        target: Point3D,
+        up: Vec3<f64>,
+    ) -> Self {
+        let (u, v, w) = look_at(origin, target, up);
+        let max_fov = match mapping {
+            FisheyeMapping::Equidistant => f64::INFINITY,
+            FisheyeMapping::Equisolid => 360.0,
+        };
+
+        Fisheye {
+            aspect_ratio,
+            fov: fov.min(max_fov),
+            mapping,
+            view: View::new(origin, target, up),
+            origin,
+            u,
+            v,
+            w,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    // The field of view across the image circle, in degrees.
+    pub fn fov(&self) -> f64 {
+        self.fov
+    }
+
+    pub fn mapping(&self) -> FisheyeMapping {
+        self.mapping
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    // Image position relative to the image circle, which is inscribed in the
+    // image height.
+    fn circle_position(&self, u: f64, v: f64) -> (f64, f64) {
+        ((2.0 * u - 1.0) * self.aspect_ratio, 2.0 * v - 1.0)
+    }
+}
+
+impl Projection for Fisheye {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        let (x, y) = self.circle_position(u, v);
+        let radius = (x * x + y * y).sqrt().min(1.0);
+
+        let fov = self.fov.to_radians();
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * fov / 2.0,
+            FisheyeMapping::Equisolid => 2.0 * (radius * (fov / 4.0).sin()).asin(),
+        };
+        let phi = y.atan2(x);
+
+        let direction =
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+        Ray::new(self.origin, direction)
+    }
+
+    fn covers(&self, u: f64, v: f64) -> bool {
+        let (x, y) = self.circle_position(u, v);
+        x * x + y * y <= 1.0
+    }
+}
+
+// Six 90° faces laid out in a 3x2 grid, reading left to right from the top
+// row: right, left, up, down, back, front, matching the +X, -X, +Y, -Y, +Z,
+// -Z order of the camera's own frame. Side faces keep the camera's up vector.
+#[derive(Debug)]
+pub struct Cubemap {
+    view: View,
+    origin: Point3D,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+}
+
+impl Cubemap {
+    pub fn new(origin: Point3D, target: Point3D, up: Vec3<f64>) -> Self {
+        let (u, v, w) = look_at(origin, target, up);
+        Cubemap {
+            view: View::new(origin, target, up),
+            origin,
+            u,
+            v,
+            w,
+        }
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    fn face(&self, index: usize) -> (Vec3<f64>, Vec3<f64>, Vec3<f64>) {
+        let (u, v, w) = (self.u, self.v, self.w);
+
+        match index {
+            0 => (u, w, v),
+            1 => (-u, -w, v),
+            2 => (v, u, w),
+            3 => (-v, u, -w),
+            4 => (w, -u, v),
+            _ => (-w, u, v),
+        }
+    }
+}
+
+impl Projection for Cubemap {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        let column = ((u * 3.0) as usize).min(2);
+        let row = ((v * 2.0) as usize).min(1);
+        let s = u * 3.0 - column as f64;
+        let t = v * 2.0 - row as f64;
+
+        let (forward, right, up) = self.face((1 - row) * 3 + column);
+        let direction = forward + (2.0 * s - 1.0) * right + (2.0 * t - 1.0) * up;
+        Ray::new(self.origin, direction)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+}
+
+impl StereoLayout {
+    // Which eye an image position belongs to (left first, on the left or top)
+    // and the position within that eye's half of the image.
+    fn split(&self, u: f64, v: f64) -> (Eye, f64, f64) {
+        match self {
+            StereoLayout::SideBySide if u < 0.5 => (Eye::Left, 2.0 * u, v),
+            StereoLayout::SideBySide => (Eye::Right, 2.0 * u - 1.0, v),
+            StereoLayout::TopBottom if v >= 0.5 => (Eye::Left, u, 2.0 * v - 1.0),
+            StereoLayout::TopBottom => (Eye::Right, u, 2.0 * v),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Eye {
+    Left,
+    Right,
+}
+
+// Both modes converge the eyes on the point `distance` in front of the rig.
+// Toe-in rotates each eye to look at it, while off-axis keeps the view axes
+// parallel and shifts each eye's image window instead, which avoids vertical
+// parallax. Off-axis with an infinite distance is a plain parallel rig. The
+// distance must be greater than 0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoConvergence {
+    ToeIn(f64),
+    OffAxis(f64),
+}
+
+#[derive(Debug)]
+pub struct Stereo {
+    camera: Box<Camera<f64>>,
+    interocular: f64,
+    convergence: StereoConvergence,
+    left: Box<Camera<f64>>,
+    right: Box<Camera<f64>>,
+    layout: StereoLayout,
+}
+
+impl Stereo {
+    // `camera` describes the view from between the eyes; each eye gets the
+    // whole of its field of view, so its aspect ratio is that of one half of
+    // the image.
+    pub fn new(
+        camera: Camera<f64>,
+        interocular: f64,
+        convergence: StereoConvergence,
+        layout: StereoLayout,
+    ) -> Self {
+        let distance = match convergence {
+            StereoConvergence::ToeIn(distance) | StereoConvergence::OffAxis(distance) => distance,
+        };
+        assert!(
+            distance > 0.0,
+            "stereo convergence distance must be greater than 0, got {}",
+            distance
+        );
+        let half_offset = camera.u * (interocular / 2.0);
+
+        let eye = |offset: Vec3<f64>| match convergence {
+            StereoConvergence::ToeIn(distance) => {
+                let target = camera.origin - camera.w * distance;
+                camera.looking_at(camera.origin + offset, target)
+            }
+            StereoConvergence::OffAxis(distance) => {
+                let mut eye = camera.translated(offset);
+                eye.lower_left_corner -= offset * (camera.focus_dist() / distance);
+                eye
+            }
+        };
+
+        Stereo {
+            left: Box::new(eye(-half_offset)),
+            right: Box::new(eye(half_offset)),
+            camera: Box::new(camera),
+            interocular,
+            convergence,
+            layout,
+        }
+    }
+
+    // The view from between the eyes.
+    pub fn camera(&self) -> &Camera<f64> {
+        &self.camera
+    }
+
+    pub fn interocular(&self) -> f64 {
+        self.interocular
+    }
+
+    pub fn convergence(&self) -> StereoConvergence {
+        self.convergence
+    }
+
+    pub fn layout(&self) -> StereoLayout {
+        self.layout
+    }
+}
+
+impl Projection for Stereo {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        self.get_weighted_ray(u, v).0
+    }
+
+    fn get_weighted_ray(&self, u: f64, v: f64) -> (Ray, Colour) {
+        match self.layout.split(u, v) {
+            (Eye::Left, u, v) => self.left.get_weighted_ray(u, v),
+            (Eye::Right, u, v) => self.right.get_weighted_ray(u, v),
+        }
+    }
+}
+
+// Omni-directional stereo: an equirectangular panorama per eye, where each
+// ray starts on the circle of eye positions tangent to its direction.
+#[derive(Debug)]
+pub struct Ods {
+    view: View,
+    origin: Point3D,
+    interocular: f64,
+    layout: StereoLayout,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+}
+
+impl Ods {
+    pub fn new(
+        origin: Point3D,
+        target: Point3D,
+        up: Vec3<f64>,
+        interocular: f64,
+        layout: StereoLayout,
+    ) -> Self {
+        let (u, v, w) = look_at(origin, target, up);
+
+        Ods {
+            view: View::new(origin, target, up),
+            origin,
+            interocular,
+            layout,
+            u,
+            v,
+            w,
+        }
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    pub fn interocular(&self) -> f64 {
+        self.interocular
+    }
+
+    pub fn layout(&self) -> StereoLayout {
+        self.layout
+    }
+}
+
+impl Projection for Ods {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        let (eye, u, v) = self.layout.split(u, v);
+        let longitude = (u - 0.5) * 2.0 * PI;
+        let latitude = (v - 0.5) * PI;
+
+        let side = match eye {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        };
+        let offset =
+            side * (self.interocular / 2.0) * (longitude.cos() * self.u + longitude.sin() * self.w);
+
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v;
+        Ray::new(self.origin + offset, direction)
+    }
+}
+
+// A camera that traces rays through every element of a real lens. Rays
+// leave a point on the film towards the rear element and are weighted by the
+// cosine-fourth falloff, so distortion, vignetting and focus breathing all
+// come from the lens itself. Weights are normalised to one at the image
+// centre.
+#[derive(Debug)]
+pub struct Realistic {
+    lens: LensSystem,
+    film: Viewport<f64>,
+    focus_dist: f64,
+    view: View,
+    film_distance: f64,
+    normalisation: f64,
+    origin: Point3D,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+}
+
+impl Realistic {
+    // `film` is the sensor size and `focus_dist` is measured from the film
+    // plane, which sits at `origin`.
+    pub fn new(
+        lens: LensSystem,
+        film: Viewport<f64>,
+        focus_dist: f64,
+        origin: Point3D,
+        target: Point3D,
+        up: Vec3<f64>,
+    ) -> Self {
+        let (u, v, w) = look_at(origin, target, up);
+        let film_distance = lens.film_distance(focus_dist);
+
+        let mut realistic = Realistic {
+            lens,
+            film,
+            focus_dist,
+            view: View::new(origin, target, up),
+            film_distance,
+            normalisation: 1.0,
+            origin,
+            u,
+            v,
+            w,
+        };
+        realistic.normalisation = realistic.centre_normalisation();
+        realistic
+    }
+
+    pub fn film_distance(&self) -> f64 {
+        self.film_distance
+    }
+
+    pub fn lens(&self) -> &LensSystem {
+        &self.lens
+    }
+
+    pub fn film(&self) -> &Viewport<f64> {
+        &self.film
+    }
+
+    pub fn focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    // Traces a ray from a film position through a point on the rear
+    // element, both in lens space, returning the ray leaving the front
+    // element and its unnormalised weight.
+    fn trace_from_film(&self, film: Vec3<f64>, rear: Vec3<f64>) -> Option<(Ray, f64)> {
+        let direction = (rear - film).unit();
+        let exit = self
+            .lens
+            .trace(self.film_distance, &Ray::new(film, direction), true)?;
+
+        Some((exit, direction.z().powi(4)))
+    }
+
+    fn centre_normalisation(&self) -> f64 {
+        const GRID: usize = 64;
+        let rear_radius = self.lens.rear_element().aperture() / 2.0;
+        let film = Vec3::new(0.0, 0.0, 0.0);
+
+        let mut total = 0.0;
+        let mut count = 0;
+        for i in 0..GRID {
+            for j in 0..GRID {
+                let x = 2.0 * (i as f64 + 0.5) / GRID as f64 - 1.0;
+                let y = 2.0 * (j as f64 + 0.5) / GRID as f64 - 1.0;
+                if x * x + y * y > 1.0 {
+                    continue;
+                }
+
+                let rear = Vec3::new(x * rear_radius, y * rear_radius, self.film_distance);
+                if let Some((_, weight)) = self.trace_from_film(film, rear) {
+                    total += weight;
+                }
+                count += 1;
+            }
+        }
+
+        match total > 0.0 {
+            true => count as f64 / total,
+            false => 1.0,
+        }
+    }
+
+    fn to_world(&self, point: Vec3<f64>) -> Vec3<f64> {
+        point.x() * self.u + point.y() * self.v - point.z() * self.w
+    }
+}
+
+impl Projection for Realistic {
+    fn get_ray(&self, u: f64, v: f64) -> Ray {
+        self.get_weighted_ray(u, v).0
+    }
+
+    fn get_weighted_ray(&self, u: f64, v: f64) -> (Ray, Colour) {
+        // The lens inverts the image, so the film is read back to front.
+        let film = Vec3::new(
+            (0.5 - u) * self.film.width,
+            (0.5 - v) * self.film.height,
+            0.0,
+        );
+        let rear = self.lens.rear_element().aperture() / 2.0 * randon_unit_disk()
+            + Vec3::new(0.0, 0.0, self.film_distance);
+
+        match self.trace_from_film(film, rear) {
+            Some((exit, weight)) => {
+                let weight = weight * self.normalisation;
+                (
+                    Ray::new(
+                        self.origin + self.to_world(exit.origin()),
+                        self.to_world(exit.direction()),
+                    ),
+                    Colour::new(weight, weight, weight),
+                )
+            }
+            None => (Ray::new(self.origin, -self.w), Colour::new(0.0, 0.0, 0.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod camera_tests {
+    use super::*;
+    use crate::material::{Lambertian, Materials};
+    use crate::objects::{Object, Sphere};
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let camera = Orthographic::new(
+            2.0,
+            4.0,
+            Point3D::new(0.0, 0.0, 5.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        let centre = camera.get_ray(0.5, 0.5);
+        let corner = camera.get_ray(0.0, 0.0);
+
+        assert_eq!(Vec3::new(0.0, 0.0, -1.0), centre.direction());
+        assert_eq!(centre.direction(), corner.direction());
+        assert_eq!(Point3D::new(0.0, 0.0, 5.0), centre.origin());
+        assert_eq!(Point3D::new(-4.0, -2.0, 5.0), corner.origin());
+    }
+
+    #[test]
+    fn test_panoramas_look_forward_at_centre() {
+        let origin = Point3D::new(1.0, 2.0, 3.0);
+        let target = Point3D::new(1.0, 2.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+
+        let equirectangular = Equirectangular::new(origin, target, up);
+        assert!((equirectangular.get_ray(0.5, 0.5).direction() - forward).length() < 1e-12);
+        let behind = equirectangular.get_ray(0.0, 0.5).direction();
+        assert!((behind - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        for mapping in &[FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Fisheye::new(1.0, 180.0, *mapping, origin, target, up);
+            assert!((fisheye.get_ray(0.5, 0.5).direction() - forward).length() < 1e-12);
+            let edge = fisheye.get_ray(1.0, 0.5).direction();
+            assert!((edge - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+            assert!(!fisheye.covers(1.0, 1.0));
+        }
+
+        let cubemap = Cubemap::new(origin, target, up);
+        let front_centre = cubemap.get_ray(5.0 / 6.0, 0.25);
+        assert_eq!(origin, front_centre.origin());
+        assert_eq!(forward, front_centre.direction());
+    }
+
+    #[test]
+    fn test_stereo_eyes_converge() {
+        let camera = Camera::new(
+            1.0,
+            40.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            5.0,
+        );
+        let convergence_point = Point3D::new(0.0, 0.0, -10.0);
+
+        for convergence in &[
+            StereoConvergence::ToeIn(10.0),
+            StereoConvergence::OffAxis(10.0),
+        ] {
+            let stereo = Stereo::new(camera.clone(), 0.5, *convergence, StereoLayout::SideBySide);
+
+            for u in &[0.25, 0.75] {
+                let ray = stereo.get_ray(*u, 0.5);
+                let t = (convergence_point.z() - ray.origin().z()) / ray.direction().z();
+                assert!((ray.at(t) - convergence_point).length() < 1e-9);
+            }
+            assert!(stereo.get_ray(0.25, 0.5).origin().x() < 0.0);
+            assert!(stereo.get_ray(0.75, 0.5).origin().x() > 0.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "convergence distance must be greater than 0")]
+    fn test_stereo_rejects_zero_convergence() {
+        let camera = Camera::new(
+            1.0,
+            40.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            5.0,
+        );
+        Stereo::new(
+            camera,
+            0.5,
+            StereoConvergence::OffAxis(0.0),
+            StereoLayout::SideBySide,
+        );
+    }
+
+    #[test]
+    fn test_ods_eyes_are_tangent() {
+        let ods = Ods::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.5,
+            StereoLayout::TopBottom,
+        );
+
+        for (u, v) in &[(0.5, 0.75), (0.3, 0.6), (0.9, 0.25)] {
+            let ray = ods.get_ray(*u, *v);
+            assert!((ray.origin().length() - 0.25).abs() < 1e-12);
+            assert!(ray.origin().dot(&ray.direction()).abs() < 1e-12);
+        }
+        assert!(ods.get_ray(0.5, 0.75).origin().x() < 0.0);
+        assert!(ods.get_ray(0.5, 0.25).origin().x() > 0.0);
+    }
+
+    #[test]
+    fn test_photographic_settings() {
+        let settings = PhotographicSettings::full_frame(50.0, 2.0, 0.25, 100.0);
+
+        assert!((settings.aspect_ratio() - 1.5).abs() < 1e-12);
+        assert!((settings.vertical_fov(1.5) - 26.9915).abs() < 1e-4);
+        assert_eq!((36.0, 20.25), settings.gate(16.0 / 9.0));
+        assert_eq!((12.0, 24.0), settings.gate(0.5));
+        assert!((settings.aperture_diameter() - 25.0).abs() < 1e-12);
+        assert!((settings.ev100() - 4.0).abs() < 1e-12);
+        assert!((settings.exposure() - 1.0 / 19.2).abs() < 1e-12);
+
+        let camera = Camera::from_photographic(
+            &settings,
+            16.0 / 9.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+        );
+        assert!((camera.exposure() - settings.exposure()).abs() < 1e-12);
+        assert!((camera.lens_radius - 0.0125).abs() < 1e-12);
+        assert_eq!(16.0 / 9.0, camera.aspect_ratio());
+        assert!((camera.vertical_fov() - settings.vertical_fov(16.0 / 9.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_realistic_centre_is_in_focus() {
+        let lens =
+            LensSystem::from_prescription(include_str!("../lenses/dgauss.50mm.dat")).unwrap();
+        let camera = Realistic::new(
+            lens,
+            Viewport::new(0.036, 0.024).fit(16.0 / 9.0),
+            2.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(0.036 / (16.0 / 9.0), camera.film().height());
+
+        let mut passed = 0;
+        for _ in 0..100 {
+            let (ray, weight) = camera.get_weighted_ray(0.5, 0.5);
+            if weight.x() == 0.0 {
+                continue;
+            }
+            passed += 1;
+
+            let t = (-2.0 - ray.origin().z()) / ray.direction().z();
+            let point = ray.at(t);
+            assert!(point.x().hypot(point.y()) < 5e-3);
+        }
+        assert!(passed > 50);
+    }
+
+    #[test]
+    fn test_shift_and_tilt() {
+        let origin = Point3D::new(0.0, 0.0, 0.0);
+        let target = Point3D::new(0.0, 0.0, -1.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let camera = Camera::new(1.0, 90.0, origin, target, up, 0.0, 1.0);
+
+        let shifted = camera.clone().set_shift(0.0, 0.5);
+        let centre = shifted.get_ray(0.5, 0.5).direction();
+        assert!((centre - Vec3::new(0.0, 1.0, -1.0)).length() < 1e-12);
+        assert_eq!(camera.get_ray(0.5, 1.0).direction(), centre);
+
+        // With a 45 degree tilt the focal plane is nearer at the bottom.
+        let tilted = camera.set_tilt(45.0, 0.0);
+        let bottom = tilted.on_focal_plane(Point3D::new(0.0, -1.0, -1.0));
+        let top = tilted.on_focal_plane(Point3D::new(0.0, 0.5, -1.0));
+        assert!((bottom - Point3D::new(0.0, -0.5, -0.5)).length() < 1e-12);
+        assert!((top - Point3D::new(0.0, 1.0, -2.0)).length() < 1e-12);
+        // The top edge runs parallel to the plane, so never meets it.
+        let edge = Point3D::new(0.0, 1.0, -1.0);
+        assert_eq!(edge, tilted.on_focal_plane(edge));
+
+        let edge_on = Camera::new(1.0, 90.0, origin, target, up, 0.5, 1.0).set_tilt(90.0, -120.0);
+        assert_eq!((80.0, -80.0), edge_on.tilt);
+        for &(s, t) in &[(0.0, 0.0), (0.5, 0.5), (1.0, 1.0), (0.0, 1.0)] {
+            let ray = edge_on.get_ray(s, t);
+            assert!(ray.origin().x().is_finite() && ray.direction().length() > 0.0);
+            assert!(ray.direction().x().is_finite());
+        }
+    }
+
+    #[test]
+    fn test_autofocus() {
+        let mut world = SceneObjects::new();
+        world.add(Object::Sphere(Sphere::new(
+            Point3D::new(0.0, 0.0, -5.0),
+            1.0,
+            Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5))),
+        )));
+        let camera = Camera::new(
+            1.0,
+            40.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.1,
+            10.0,
+        );
+
+        assert_eq!(Some(4.0), camera.autofocus(&world, 0.5, 0.5));
+        assert_eq!(None, camera.autofocus(&world, 0.0, 0.0));
+        let centre = world.object(1).unwrap().center();
+        assert_eq!(Some(5.0), camera.focus_distance_to(centre));
+        assert_eq!(None, camera.focus_distance_to(Point3D::new(0.0, 0.0, 1.0)));
+        assert_eq!(None, camera.focus_distance_to(Point3D::new(1.0, 0.0, 0.0)));
+
+        let refocused = camera.clone().set_focus_dist(4.0);
+        assert!((refocused.focus_dist() - 4.0).abs() < 1e-12);
+        let (before, after) = (camera.get_ray(0.2, 0.7), refocused.get_ray(0.2, 0.7));
+        assert!((before.direction().unit() - after.direction().unit()).length() < 0.1);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::aabb::Aabb;
+use crate::objects::{Hit, HitRecord, Interval, Object};
+use crate::ray::Ray;
+use crate::vec3::Point3D;
+use std::cmp::Ordering;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    // Everything in the left object that is not in the right.
+    Difference,
+}
+
+impl Operation {
+    fn contains(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// A boolean combination of two closed objects. Both are solved along the
+// whole line through a ray and their inside intervals merged, so nodes can be
+// nested and the result still has correct entry and exit hits.
+#[derive(Debug)]
+pub struct Csg {
+    operation: Operation,
+    left: Box<Object>,
+    right: Box<Object>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Object, right: Object) -> Self {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn union(left: Object, right: Object) -> Self {
+        Csg::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(left: Object, right: Object) -> Self {
+        Csg::new(Operation::Intersection, left, right)
+    }
+
+    pub fn difference(left: Object, right: Object) -> Self {
+        Csg::new(Operation::Difference, left, right)
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    pub fn left(&self) -> &Object {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Object {
+        &self.right
+    }
+
+    pub fn center(&self) -> Point3D {
+        match self.bounding_box() {
+            Some(bounds) => bounds.center(),
+            None => self.left.center(),
+        }
+    }
+}
+
+impl Hit for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hits(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.operation {
+            Operation::Union => Some(left?.surrounding(&right?)),
+            Operation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(left.overlap(&right)),
+                (left, right) => left.or(right),
+            },
+            Operation::Difference => left,
+        }
+    }
+
+    fn hits(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        self.intervals(ray)
+            .into_iter()
+            .flat_map(|interval| {
+                let (enter, exit) = interval.into_hits();
+                vec![enter, exit]
+            })
+            .filter(|record| t_min <= record.time() && record.time() <= t_max)
+            .collect()
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let mut crossings = tagged_crossings(&self.left, ray, true);
+        crossings.extend(tagged_crossings(&self.right, ray, false));
+        crossings.sort_by(|a, b| {
+            a.0.time()
+                .partial_cmp(&b.0.time())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        // Each crossing toggles whether the ray is inside that side; the
+        // ones that change whether it's inside the result bound its intervals.
+        let (mut in_left, mut in_right, mut inside) = (false, false, false);
+        let mut enter = None;
+        let mut intervals = Vec::new();
+        for (record, from_left) in crossings {
+            match from_left {
+                true => in_left = !in_left,
+                false => in_right = !in_right,
+            }
+            if self.operation.contains(in_left, in_right) == inside {
+                continue;
+            }
+            inside = !inside;
+
+            // The subtracted object's surface faces into the result.
+            let record = match self.operation == Operation::Difference && !from_left {
+                true => record.flip_face(),
+                false => record,
+            };
+            match enter.take() {
+                Some(previous) => intervals.push(Interval::new(previous, record)),
+                None => enter = Some(record),
+            }
+        }
+        intervals
+    }
+}
+
+// An object's interval ends in order, tagged with which side they came from.
+fn tagged_crossings<'a>(
+    object: &'a Object,
+    ray: &Ray,
+    from_left: bool,
+) -> Vec<(HitRecord<'a>, bool)> {
+    object
+        .intervals(ray)
+        .into_iter()
+        .flat_map(|interval| {
+            let (enter, exit) = interval.into_hits();
+            vec![(enter, from_left), (exit, from_left)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod csg_tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::objects::Sphere;
+    use crate::planar::{Cuboid, Plane};
+    use crate::vec3::Vec3;
+
+    fn sphere(x: f64, radius: f64) -> Object {
+        Object::Sphere(Sphere::new(Point3D::new(x, 0.0, 0.0), radius, grey()))
+    }
+
+    fn times(records: &[HitRecord]) -> Vec<f64> {
+        records.iter().map(|record| record.time()).collect()
+    }
+
+    #[test]
+    fn test_operations_along_a_ray() {
+        let ray = Ray::new(Point3D::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let all = |csg: &Csg| times(&csg.hits(&ray, 0.001, f64::INFINITY));
+
+        let union = Csg::union(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        assert_eq!(vec![3.5, 6.5], all(&union));
+
+        let lens = Csg::intersection(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        assert_eq!(vec![4.5, 5.5], all(&lens));
+        let bounds = lens.bounding_box().unwrap();
+        assert_eq!(-0.5, bounds.min().x());
+        assert_eq!(0.5, bounds.max().x());
+
+        let bite = Csg::difference(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        assert_eq!(vec![3.5, 4.5], all(&bite));
+        let exit = bite.hits(&ray, 0.001, f64::INFINITY).pop().unwrap();
+        assert!(!exit.front_face());
+        assert_eq!(Vec3::new(-1.0, 0.0, 0.0), exit.normal());
+
+        let apart = Csg::intersection(sphere(-2.0, 1.0), sphere(2.0, 1.0));
+        assert!(apart.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_sphere_cut_from_box() {
+        let cube = Object::Cuboid(Cuboid::new(
+            Point3D::new(-1.0, -1.0, -1.0),
+            Point3D::new(1.0, 1.0, 1.0),
+            grey(),
+        ));
+        let hollow = Csg::difference(cube, sphere(0.0, 0.5));
+
+        let through = Ray::new(Point3D::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let records = hollow.hits(&through, 0.001, f64::INFINITY);
+        assert_eq!(vec![4.0, 4.5, 5.5, 6.0], times(&records));
+        let facing: Vec<bool> = records.iter().map(|record| record.front_face()).collect();
+        assert_eq!(vec![true, false, true, false], facing);
+
+        // From inside the cavity, the first surface is the cavity wall, seen
+        // from within the solid.
+        let from_cavity = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = hollow.hit(&from_cavity, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(0.5, record.time());
+        assert!(record.front_face());
+
+        let nested = Csg::union(Object::Csg(hollow), sphere(0.0, 0.25));
+        assert_eq!(
+            vec![0.25, 0.5, 1.0],
+            times(&nested.hits(&from_cavity, 0.001, f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn test_grazing_ray_far_from_plane() {
+        // The plane is crossed about 3e9 along the line, where a fixed step
+        // past each crossing rounds to nothing.
+        let ground = Object::Plane(Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            grey(),
+        ));
+        let union = Csg::union(sphere(0.0, 1.0), ground);
+        let ray = Ray::new(Point3D::new(0.0, 3000.0, 0.0), Vec3::new(1.0, 1e-6, 0.0));
+        assert_eq!(
+            1,
+            union
+                .right()
+                .hits(&ray, f64::NEG_INFINITY, f64::INFINITY)
+                .len()
+        );
+        assert!(union.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}
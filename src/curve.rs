@@ -0,0 +1,405 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::objects::{Hit, HitRecord};
+use crate::planar::tangent_frame;
+use crate::ray::Ray;
+use crate::utils::clamp;
+use crate::vec3::{Point3D, Vec3};
+
+// Subdividing deeper than this stops paying off even for long curves.
+const MAX_DEPTH: i32 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CurveKind {
+    // A round strand, drawn as a flat ribbon that always faces the ray with
+    // the normals of a cylinder across it, as for hair and fur.
+    Cylinder,
+    // A flat strip that turns from the first normal to the second along its
+    // length, as for blades of grass.
+    Ribbon(Vec3<f64>, Vec3<f64>),
+}
+
+// A cubic Bezier curve whose width changes linearly from one end to the
+// other. u runs along the curve and v across it, from 0 to 1, and hits carry
+// the tangent along u for hair.
+#[derive(Debug, Clone)]
+pub struct Curve {
+    points: [Point3D; 4],
+    widths: (f64, f64),
+    kind: CurveKind,
+    material: Materials,
+}
+
+impl Curve {
+    pub fn cylinder(points: [Point3D; 4], width0: f64, width1: f64, material: Materials) -> Self {
+        Curve {
+            points,
+            widths: (width0, width1),
+            kind: CurveKind::Cylinder,
+            material,
+        }
+    }
+
+    pub fn ribbon(
+        points: [Point3D; 4],
+        width0: f64,
+        width1: f64,
+        normal0: Vec3<f64>,
+        normal1: Vec3<f64>,
+        material: Materials,
+    ) -> Self {
+        Curve {
+            points,
+            widths: (width0, width1),
+            kind: CurveKind::Ribbon(normal0.unit(), normal1.unit()),
+            material,
+        }
+    }
+
+    pub fn points(&self) -> [Point3D; 4] {
+        self.points
+    }
+
+    pub fn kind(&self) -> CurveKind {
+        self.kind
+    }
+
+    pub fn widths(&self) -> (f64, f64) {
+        self.widths
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        bezier(&self.points, 0.5).0
+    }
+
+    fn width(&self, u: f64) -> f64 {
+        self.widths.0 + (self.widths.1 - self.widths.0) * u
+    }
+
+    // The ribbon's normal at u, by spherical interpolation.
+    fn ribbon_normal(n0: Vec3<f64>, n1: Vec3<f64>, u: f64) -> Vec3<f64> {
+        let cos = clamp(n0.dot(&n1), -1.0, 1.0);
+        let angle = cos.acos();
+        match angle < 1e-6 {
+            true => n0,
+            false => {
+                let sin = angle.sin();
+                (((1.0 - u) * angle).sin() * n0 + (u * angle).sin() * n1) / sin
+            }
+        }
+    }
+
+    // Looks for the closest hit of a segment of the curve, given in ray
+    // space where the ray runs along +z from the origin. The segment is
+    // split in half until it is nearly straight, and each half skipped as
+    // soon as its bounds miss the ray.
+    #[allow(clippy::too_many_arguments)]
+    fn intersect(
+        &self,
+        cp: &[Vec3<f64>; 4],
+        u0: f64,
+        u1: f64,
+        depth: i32,
+        z_min: f64,
+        z_max: &mut f64,
+        direction: Vec3<f64>,
+    ) -> Option<f64> {
+        let half_width = self.width(u0).max(self.width(u1)) / 2.0;
+        let (low, high) = cp[1..].iter().fold((cp[0], cp[0]), |(low, high), p| {
+            (
+                Vec3::new(low.x().min(p.x()), low.y().min(p.y()), low.z().min(p.z())),
+                Vec3::new(
+                    high.x().max(p.x()),
+                    high.y().max(p.y()),
+                    high.z().max(p.z()),
+                ),
+            )
+        });
+        if low.x() - half_width > 0.0
+            || high.x() + half_width < 0.0
+            || low.y() - half_width > 0.0
+            || high.y() + half_width < 0.0
+            || high.z() + half_width < z_min
+            || low.z() - half_width > *z_max
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(cp);
+            let middle = (u0 + u1) / 2.0;
+            let near = self.intersect(&first, u0, middle, depth - 1, z_min, z_max, direction);
+            let far = self.intersect(&second, middle, u1, depth - 1, z_min, z_max, direction);
+            return far.or(near);
+        }
+
+        // Rays past the ends of the curve, beyond the lines through its end
+        // points perpendicular to it, miss. Inside the curve, segments are
+        // left to overlap at their joins so bends don't open gaps.
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if u0 == 0.0 && edge < 0.0 {
+            return None;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if u1 == 1.0 && edge < 0.0 {
+            return None;
+        }
+
+        // Approximates the segment as straight to find the closest point.
+        let segment = Vec3::new(cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y(), 0.0);
+        let length_sqrd = segment.length_sqrd();
+        if length_sqrd == 0.0 {
+            return None;
+        }
+        let w = clamp(
+            -(cp[0].x() * segment.x() + cp[0].y() * segment.y()) / length_sqrd,
+            0.0,
+            1.0,
+        );
+        let u = clamp(u0 + (u1 - u0) * w, u0, u1);
+
+        let mut width = self.width(u);
+        if let CurveKind::Ribbon(n0, n1) = self.kind {
+            let normal = Curve::ribbon_normal(n0, n1, u);
+            width *= normal.dot(&direction).abs();
+        }
+
+        let (point, _) = bezier(cp, w);
+        let distance_sqrd = point.x() * point.x() + point.y() * point.y();
+        if distance_sqrd > width * width / 4.0 || point.z() < z_min || point.z() > *z_max {
+            return None;
+        }
+
+        *z_max = point.z();
+        Some(u)
+    }
+}
+
+impl Hit for Curve {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let speed = ray.direction().length();
+        let direction = ray.direction() / speed;
+        let (x, y) = tangent_frame(direction);
+        let to_ray_space = |p: Point3D| {
+            let offset = p - ray.origin();
+            Vec3::new(offset.dot(&x), offset.dot(&y), offset.dot(&direction))
+        };
+        let cp = [
+            to_ray_space(self.points[0]),
+            to_ray_space(self.points[1]),
+            to_ray_space(self.points[2]),
+            to_ray_space(self.points[3]),
+        ];
+
+        // Enough splits that the pieces are within 5% of the width of
+        // straight.
+        let mut flatness: f64 = 0.0;
+        for i in 0..2 {
+            let bend = cp[i] - 2.0 * cp[i + 1] + cp[i + 2];
+            flatness = flatness.max(bend.x().abs().max(bend.y().abs()).max(bend.z().abs()));
+        }
+        let epsilon = self.widths.0.max(self.widths.1) * 0.05;
+        let depth = match flatness > 0.0 {
+            true => clamp(
+                (2f64.sqrt() * 6.0 * flatness / (8.0 * epsilon)).log2() as i32 / 2,
+                0,
+                MAX_DEPTH,
+            ),
+            false => 0,
+        };
+
+        let mut z_max = t_max * speed;
+        let u = self.intersect(&cp, 0.0, 1.0, depth, t_min * speed, &mut z_max, direction)?;
+        let t = z_max / speed;
+
+        let point = ray.at(t);
+        let (center, tangent) = bezier(&self.points, u);
+        let tangent = tangent.unit();
+        let facing = match self.kind {
+            CurveKind::Ribbon(n0, n1) => {
+                let normal = Curve::ribbon_normal(n0, n1, u);
+                (normal - tangent * normal.dot(&tangent)).unit()
+            }
+            CurveKind::Cylinder => {
+                let back = -direction - tangent * -direction.dot(&tangent);
+                match back.near_zero() {
+                    true => tangent_frame(tangent).0,
+                    false => back.unit(),
+                }
+            }
+        };
+        let side = tangent.cross(&facing);
+        let h = clamp(
+            (point - center).dot(&side) / (self.width(u) / 2.0),
+            -1.0,
+            1.0,
+        );
+
+        // Across a cylinder the normal turns from one side to the other.
+        let normal = match self.kind {
+            CurveKind::Ribbon(..) => facing,
+            CurveKind::Cylinder => (1.0 - h * h).max(0.0).sqrt() * facing + h * side,
+        };
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(u, (h + 1.0) / 2.0)
+                .set_tangent(tangent)
+                .set_face_normal(ray, &normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The curve stays inside its control points' hull.
+        let pad = self.widths.0.max(self.widths.1) / 2.0;
+        let pad = Vec3::new(pad, pad, pad);
+        let hull = Aabb::from_points(&self.points);
+        Some(Aabb::new(hull.min() - pad, hull.max() + pad))
+    }
+}
+
+// The point and derivative at u.
+fn bezier(cp: &[Vec3<f64>; 4], u: f64) -> (Point3D, Vec3<f64>) {
+    let lerp = |a: Vec3<f64>, b: Vec3<f64>| (1.0 - u) * a + u * b;
+    let (a, b, c) = (lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3]));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    let derivative = match (e - d).near_zero() {
+        // The end tangent is degenerate when a control point repeats.
+        true => cp[3] - cp[0],
+        false => 3.0 * (e - d),
+    };
+    (lerp(d, e), derivative)
+}
+
+// Splits a cubic Bezier curve in half by de Casteljau's algorithm.
+fn split(cp: &[Vec3<f64>; 4]) -> ([Vec3<f64>; 4], [Vec3<f64>; 4]) {
+    let mid = |a: Vec3<f64>, b: Vec3<f64>| (a + b) / 2.0;
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let middle = mid(d, e);
+    ([cp[0], a, d, middle], [middle, e, c, cp[3]])
+}
+
+#[cfg(test)]
+mod curve_tests {
+    use super::*;
+    use crate::hair::Hair;
+    use crate::material::Material;
+    use crate::utils::seed_sampler;
+
+    fn hair() -> Materials {
+        Materials::Hair(Hair::from_melanin(1.3, 0.0))
+    }
+
+    #[test]
+    fn test_straight_cylinder() {
+        let points = [
+            Point3D::new(-1.0, 0.0, 0.0),
+            Point3D::new(-0.3, 0.0, 0.0),
+            Point3D::new(0.3, 0.0, 0.0),
+            Point3D::new(1.0, 0.0, 0.0),
+        ];
+        let curve = Curve::cylinder(points, 0.2, 0.2, hair());
+
+        let centre = Ray::new(Point3D::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = curve.hit(&centre, 0.001, f64::INFINITY).unwrap();
+        assert!((record.time() - 5.0).abs() < 1e-9);
+        assert!((record.uv().0 - 0.5).abs() < 1e-3);
+        assert!((record.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!((record.tangent() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+
+        // Halfway to the edge, the normal leans halfway towards it.
+        let offset = Ray::new(Point3D::new(0.2, 0.05, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = curve.hit(&offset, 0.001, f64::INFINITY).unwrap();
+        let v = record.uv().1;
+        assert!((v - 0.25).abs() < 1e-9 || (v - 0.75).abs() < 1e-9);
+        assert!((record.normal().y().abs() - 0.5).abs() < 1e-9);
+
+        let outside = Ray::new(Point3D::new(0.0, 0.15, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(curve.hit(&outside, 0.001, f64::INFINITY).is_none());
+        let beyond = Ray::new(Point3D::new(1.2, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(curve.hit(&beyond, 0.001, f64::INFINITY).is_none());
+
+        seed_sampler(3);
+        let record = curve.hit(&centre, 0.001, f64::INFINITY).unwrap();
+        let (scattered, weight) = record.material().scatter(&record, &centre).unwrap();
+        assert!(weight.x() >= 0.0 && weight.x().is_finite());
+        assert!(scattered.direction().length() > 0.0);
+    }
+
+    #[test]
+    fn test_matches_sampled_curve() {
+        // A bent, tapering strand, against the closest of many points on it.
+        let points = [
+            Point3D::new(-1.0, -0.5, 0.0),
+            Point3D::new(-0.5, 1.0, 0.3),
+            Point3D::new(0.5, -1.0, -0.2),
+            Point3D::new(1.0, 0.5, 0.1),
+        ];
+        let curve = Curve::cylinder(points, 0.15, 0.05, hair());
+        let ribbon = Curve::ribbon(
+            points,
+            0.15,
+            0.05,
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.3, 1.0),
+            hair(),
+        );
+
+        for shape in &[curve, ribbon] {
+            let (mut hits, mut checked) = (0, 0);
+            for n in 0..400 {
+                let k = n as f64;
+                let along = bezier(&shape.points, (0.618 * k).fract()).0;
+                let target = along + Vec3::new(0.12 * (1.7 * k).sin(), 0.12 * (2.9 * k).cos(), 0.0);
+                let origin = target + Vec3::new(0.3 * (1.3 * k).sin(), 0.2 * k.cos(), 4.0);
+                let ray = Ray::new(origin, target - origin);
+                let direction = ray.direction().unit();
+
+                // The projected distance from the ray to the sampled strand,
+                // relative to its half width there. Rays closest to an end
+                // pass beyond it and are left out, as are near misses.
+                let (closest, u) = (0..=5_000)
+                    .map(|i| {
+                        let u = i as f64 / 5_000.0;
+                        let (point, _) = bezier(&shape.points, u);
+                        let offset = point - origin;
+                        let across = offset - direction * offset.dot(&direction);
+                        let mut width = shape.width(u);
+                        if let CurveKind::Ribbon(n0, n1) = shape.kind {
+                            width *= Curve::ribbon_normal(n0, n1, u).dot(&direction).abs();
+                        }
+                        (across.length() / (width / 2.0), u)
+                    })
+                    .fold((f64::INFINITY, 0.0), |best, sample| {
+                        match sample.0 < best.0 {
+                            true => sample,
+                            false => best,
+                        }
+                    });
+
+                let record = shape.hit(&ray, 0.001, f64::INFINITY);
+                if (closest - 1.0).abs() < 0.1 || u == 0.0 || u == 1.0 {
+                    continue;
+                }
+                checked += 1;
+                assert_eq!(
+                    closest < 1.0,
+                    record.is_some(),
+                    "ray {} {} {:?}",
+                    n,
+                    closest,
+                    shape.kind
+                );
+                hits += record.is_some() as usize;
+            }
+            assert!(checked > 300 && hits > 100);
+        }
+    }
+}
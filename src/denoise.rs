@@ -0,0 +1,207 @@
+use crate::vec3::{Colour, Vec3};
+use rayon::prelude::*;
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const MIN_ALBEDO: f64 = 1e-3;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Each pass
+// widens a 5x5 B3-spline kernel by a power of two, with weights that stop at
+// edges in the colour, normal and albedo buffers. Lighting is filtered with
+// the albedo divided out, so textures and material boundaries stay sharp.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Denoiser {
+    iterations: usize,
+    sigma_colour: f64,
+    sigma_normal: f64,
+    sigma_albedo: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_colour: 0.5,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Denoiser::default()
+    }
+
+    pub fn set_iterations(mut self, iterations: usize) -> Denoiser {
+        self.iterations = iterations;
+        self
+    }
+
+    // The edge-stopping weights divide by each sigma, so they must all be
+    // greater than zero.
+    pub fn set_sigma_colour(mut self, sigma_colour: f64) -> Denoiser {
+        assert_sigma(sigma_colour);
+        self.sigma_colour = sigma_colour;
+        self
+    }
+
+    pub fn set_sigma_normal(mut self, sigma_normal: f64) -> Denoiser {
+        assert_sigma(sigma_normal);
+        self.sigma_normal = sigma_normal;
+        self
+    }
+
+    pub fn set_sigma_albedo(mut self, sigma_albedo: f64) -> Denoiser {
+        assert_sigma(sigma_albedo);
+        self.sigma_albedo = sigma_albedo;
+        self
+    }
+
+    pub fn denoise(
+        &self,
+        width: i32,
+        height: i32,
+        colour: &[Colour],
+        albedo: &[Colour],
+        normal: &[Vec3<f64>],
+    ) -> Vec<Colour> {
+        let len = (width * height) as usize;
+        assert!(colour.len() == len && albedo.len() == len && normal.len() == len);
+
+        let demodulator: Vec<Colour> = albedo.iter().map(demodulator).collect();
+        let mut irradiance: Vec<Colour> = colour
+            .iter()
+            .zip(&demodulator)
+            .map(|(colour, albedo)| divide(colour, albedo))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let sigma_colour = self.sigma_colour / (1 << iteration) as f64;
+
+            irradiance = (0..len)
+                .into_par_iter()
+                .map(|index| {
+                    let (x, y) = ((index as i32) % width, (index as i32) / width);
+                    let mut sum = Colour::new(0.0, 0.0, 0.0);
+                    let mut weight_sum = 0.0;
+
+                    for (ky, ky_weight) in KERNEL.iter().enumerate() {
+                        for (kx, kx_weight) in KERNEL.iter().enumerate() {
+                            let qx = x + (kx as i32 - 2) * step;
+                            let qy = y + (ky as i32 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                                continue;
+                            }
+                            let neighbour = (qy * width + qx) as usize;
+
+                            let weight = ky_weight
+                                * kx_weight
+                                * gaussian(
+                                    (irradiance[index] - irradiance[neighbour]).length_sqrd(),
+                                    sigma_colour,
+                                )
+                                * gaussian(
+                                    (normal[index] - normal[neighbour]).length_sqrd(),
+                                    self.sigma_normal,
+                                )
+                                * gaussian(
+                                    (albedo[index] - albedo[neighbour]).length_sqrd(),
+                                    self.sigma_albedo,
+                                );
+
+                            sum += weight * irradiance[neighbour];
+                            weight_sum += weight;
+                        }
+                    }
+
+                    sum / weight_sum
+                })
+                .collect();
+        }
+
+        irradiance
+            .iter()
+            .zip(&demodulator)
+            .map(|(irradiance, albedo)| *irradiance * *albedo)
+            .collect()
+    }
+}
+
+fn assert_sigma(sigma: f64) {
+    assert!(
+        sigma > 0.0 && sigma.is_finite(),
+        "denoiser sigma must be greater than 0, got {}",
+        sigma
+    );
+}
+
+fn gaussian(distance_sqrd: f64, sigma: f64) -> f64 {
+    (-distance_sqrd / (sigma * sigma)).exp()
+}
+
+// Pixels that missed everything (or hit something black) have no usable
+// albedo, so their colour is filtered as-is.
+fn demodulator(albedo: &Colour) -> Colour {
+    let channel = |value: f64| if value < MIN_ALBEDO { 1.0 } else { value };
+
+    Colour::new(
+        channel(albedo.x()),
+        channel(albedo.y()),
+        channel(albedo.z()),
+    )
+}
+
+fn divide(colour: &Colour, albedo: &Colour) -> Colour {
+    Colour::new(
+        colour.x() / albedo.x(),
+        colour.y() / albedo.y(),
+        colour.z() / albedo.z(),
+    )
+}
+
+#[cfg(test)]
+mod denoise_tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "sigma must be greater than 0")]
+    fn test_rejects_zero_sigma() {
+        Denoiser::new().set_sigma_normal(0.0);
+    }
+
+    #[test]
+    fn test_preserves_flat_image() {
+        let colour = vec![Colour::new(0.25, 0.5, 0.75); 16];
+        let albedo = vec![Colour::new(0.5, 0.5, 0.5); 16];
+        let normal = vec![Vec3::new(0.0, 1.0, 0.0); 16];
+
+        let denoised = Denoiser::new().denoise(4, 4, &colour, &albedo, &normal);
+
+        for pixel in denoised {
+            assert!((pixel - Colour::new(0.25, 0.5, 0.75)).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_keeps_normal_edges() {
+        let colour: Vec<Colour> = (0..16)
+            .map(|index| match index % 4 < 2 {
+                true => Colour::new(0.0, 0.0, 0.0),
+                false => Colour::new(1.0, 1.0, 1.0),
+            })
+            .collect();
+        let normal: Vec<Vec3<f64>> = (0..16)
+            .map(|index| match index % 4 < 2 {
+                true => Vec3::new(1.0, 0.0, 0.0),
+                false => Vec3::new(0.0, 1.0, 0.0),
+            })
+            .collect();
+        let albedo = vec![Colour::new(1.0, 1.0, 1.0); 16];
+
+        let denoised = Denoiser::new().denoise(4, 4, &colour, &albedo, &normal);
+
+        assert!(denoised[0].x() < 1e-6);
+        assert!(denoised[3].x() > 1.0 - 1e-6);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::framebuffer::invalid_data;
+use crate::vec3::Colour;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const EXR_MAGIC: u32 = 20000630;
+const EXR_VERSION: u32 = 2;
+const EXR_TILED_FLAG: u32 = 0x200;
+const EXR_MULTIPART_FLAG: u32 = 0x1000;
+const PIXEL_TYPE_UINT: i32 = 0;
+const PIXEL_TYPE_HALF: i32 = 1;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+// A scanline OpenEXR image with uncompressed 32-bit float channels, which is
+// all the renderer needs for beauty and AOV output. Reading also accepts half
+// and uint channels, but not compressed, tiled or multi-part files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExrImage {
+    width: i32,
+    height: i32,
+    channels: Vec<(String, Vec<f32>)>,
+}
+
+impl ExrImage {
+    pub fn new(width: i32, height: i32) -> Self {
+        ExrImage {
+            width,
+            height,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn add_channel(&mut self, name: &str, values: Vec<f32>) {
+        assert_eq!(values.len(), (self.width * self.height) as usize);
+
+        match self
+            .channels
+            .iter_mut()
+            .find(|(existing, _)| existing == name)
+        {
+            Some((_, existing)) => *existing = values,
+            None => self.channels.push((name.to_string(), values)),
+        }
+    }
+
+    pub fn add_colours(&mut self, layer: Option<&str>, colours: &[Colour]) {
+        let prefix = layer.map(|layer| format!("{}.", layer)).unwrap_or_default();
+
+        self.add_channel(
+            &format!("{}R", prefix),
+            colours.iter().map(|c| c.x() as f32).collect(),
+        );
+        self.add_channel(
+            &format!("{}G", prefix),
+            colours.iter().map(|c| c.y() as f32).collect(),
+        );
+        self.add_channel(
+            &format!("{}B", prefix),
+            colours.iter().map(|c| c.z() as f32).collect(),
+        );
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&[f32]> {
+        self.channels
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    pub fn channel_names(&self) -> Vec<&str> {
+        self.channels
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    pub fn colours(&self, layer: Option<&str>) -> Option<Vec<Colour>> {
+        let prefix = layer.map(|layer| format!("{}.", layer)).unwrap_or_default();
+        let r = self.channel(&format!("{}R", prefix))?;
+        let g = self.channel(&format!("{}G", prefix))?;
+        let b = self.channel(&format!("{}B", prefix))?;
+
+        Some(
+            (0..r.len())
+                .map(|index| Colour::new(r[index] as f64, g[index] as f64, b[index] as f64))
+                .collect(),
+        )
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        ExrImage::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor { bytes, position: 0 };
+
+        if cursor.u32()? != EXR_MAGIC {
+            return Err(invalid_data("not an OpenEXR file"));
+        }
+        let version = cursor.u32()?;
+        if version & (EXR_TILED_FLAG | EXR_MULTIPART_FLAG) != 0 {
+            return Err(invalid_data("only single-part scanline EXRs are supported"));
+        }
+
+        let mut channel_list = Vec::new();
+        let mut compression = None;
+        let mut data_window = None;
+        loop {
+            let name = cursor.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let _kind = cursor.string()?;
+            let size = cursor.i32()? as usize;
+            let value = cursor.take(size)?;
+
+            match name.as_str() {
+                "channels" => channel_list = parse_channel_list(value)?,
+                "compression" => compression = value.first().copied(),
+                "dataWindow" => {
+                    let mut window = Cursor {
+                        bytes: value,
+                        position: 0,
+                    };
+                    data_window =
+                        Some((window.i32()?, window.i32()?, window.i32()?, window.i32()?));
+                }
+                _ => {}
+            }
+        }
+
+        if compression != Some(0) {
+            return Err(invalid_data("only uncompressed EXRs are supported"));
+        }
+        let (x_min, y_min, x_max, y_max) =
+            data_window.ok_or_else(|| invalid_data("missing dataWindow"))?;
+        let span = |min: i32, max: i32| max.checked_sub(min)?.checked_add(1).filter(|n| *n > 0);
+        let (width, height) = match (span(x_min, x_max), span(y_min, y_max)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(invalid_data("invalid dataWindow")),
+        };
+
+        // Every scanline has an offset, a row number and size, and its
+        // pixels, so the file must be at least that long before room is
+        // made for them.
+        let pixel_bytes: usize = channel_list
+            .iter()
+            .map(|(_, pixel_type)| match *pixel_type {
+                PIXEL_TYPE_HALF => 2,
+                _ => 4,
+            })
+            .sum();
+        let available = bytes.len() - cursor.position;
+        (width as usize)
+            .checked_mul(pixel_bytes)
+            .and_then(|row| row.checked_add(16))
+            .and_then(|row| row.checked_mul(height as usize))
+            .filter(|needed| *needed <= available)
+            .ok_or_else(|| invalid_data("EXR file is too short for its dataWindow"))?;
+
+        let mut offsets = Vec::with_capacity(height as usize);
+        for _ in 0..height {
+            offsets.push(cursor.u64()? as usize);
+        }
+
+        let mut image = ExrImage::new(width, height);
+        let mut planes: Vec<Vec<f32>> = channel_list
+            .iter()
+            .map(|_| vec![0.0; (width * height) as usize])
+            .collect();
+
+        for offset in offsets {
+            let mut chunk = Cursor {
+                bytes,
+                position: offset,
+            };
+            let row = chunk.i32()? - y_min;
+            let _size = chunk.i32()?;
+            if row < 0 || row >= height {
+                return Err(invalid_data("scanline outside dataWindow"));
+            }
+
+            for ((_, pixel_type), plane) in channel_list.iter().zip(planes.iter_mut()) {
+                let start = (row * width) as usize;
+                for value in &mut plane[start..start + width as usize] {
+                    *value = match *pixel_type {
+                        PIXEL_TYPE_UINT => chunk.u32()? as f32,
+                        PIXEL_TYPE_HALF => half_to_f32(chunk.u16()?),
+                        _ => f32::from_bits(chunk.u32()?),
+                    };
+                }
+            }
+        }
+
+        for ((name, _), plane) in channel_list.into_iter().zip(planes) {
+            image.channels.push((name, plane));
+        }
+
+        Ok(image)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        // The format requires channels in alphabetical order.
+        let mut channels: Vec<&(String, Vec<f32>)> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut header = Vec::new();
+
+        let mut chlist = Vec::new();
+        for (name, _) in channels.iter() {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+        write_attribute(&mut header, "channels", "chlist", &chlist);
+
+        write_attribute(&mut header, "compression", "compression", &[0]);
+
+        let mut window = Vec::new();
+        for value in &[0, 0, self.width - 1, self.height - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+
+        let mut centre = Vec::new();
+        centre.extend_from_slice(&0f32.to_le_bytes());
+        centre.extend_from_slice(&0f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &centre);
+        write_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        header.push(0);
+
+        writer.write_all(&EXR_MAGIC.to_le_bytes())?;
+        writer.write_all(&EXR_VERSION.to_le_bytes())?;
+        writer.write_all(&header)?;
+
+        let width = self.width as usize;
+        let line_size = (channels.len() * width * 4) as u64;
+        let table_end = 8 + header.len() as u64 + 8 * self.height as u64;
+        for y in 0..self.height as u64 {
+            writer.write_all(&(table_end + y * (8 + line_size)).to_le_bytes())?;
+        }
+
+        for y in 0..self.height as usize {
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(line_size as i32).to_le_bytes())?;
+            for (_, values) in channels.iter() {
+                for value in &values[y * width..(y + 1) * width] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn parse_channel_list(value: &[u8]) -> io::Result<Vec<(String, i32)>> {
+    let mut cursor = Cursor {
+        bytes: value,
+        position: 0,
+    };
+    let mut channels = Vec::new();
+
+    loop {
+        let name = cursor.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = cursor.i32()?;
+        let _linear_and_reserved = cursor.take(4)?;
+        let (x_sampling, y_sampling) = (cursor.i32()?, cursor.i32()?);
+
+        if x_sampling != 1 || y_sampling != 1 {
+            return Err(invalid_data("subsampled channels are not supported"));
+        }
+        if ![PIXEL_TYPE_UINT, PIXEL_TYPE_HALF, PIXEL_TYPE_FLOAT].contains(&pixel_type) {
+            return Err(invalid_data("unknown channel pixel type"));
+        }
+        channels.push((name, pixel_type));
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            let value = mantissa as f32 / 1024.0 * 2f32.powi(-14);
+            return if sign != 0 { -value } else { value };
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let slice = &self.bytes[self.position..end];
+                self.position = end;
+                Ok(slice)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated EXR file",
+            )),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let remaining = &self.bytes[self.position.min(self.bytes.len())..];
+        let len = remaining
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| invalid_data("unterminated string"))?;
+        let value = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.take(1)?;
+        Ok(value)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod exr_tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = ExrImage::new(2, 2);
+        image.add_colours(None, &[Colour::new(0.1, 0.2, 0.3); 4]);
+        image.add_channel("depth.Z", vec![1.0, 2.0, 3.0, 4.0]);
+
+        let mut bytes = Vec::new();
+        image.write_to(&mut bytes).unwrap();
+        let loaded = ExrImage::from_bytes(&bytes).unwrap();
+
+        assert_eq!(vec!["B", "G", "R", "depth.Z"], loaded.channel_names());
+        assert_eq!(Some(&[1.0, 2.0, 3.0, 4.0][..]), loaded.channel("depth.Z"));
+        assert_eq!(Some(&[0.1f32; 4][..]), loaded.channel("R"));
+
+        // Data windows that overflow, or claim more than the file holds,
+        // are rejected before anything is allocated for them.
+        let window = bytes
+            .windows(17)
+            .position(|name| name == b"dataWindow\0box2i\0")
+            .unwrap()
+            + 21;
+        for &(min, max) in &[(i32::MIN, i32::MAX), (0, 1 << 30)] {
+            let mut corrupt = bytes.clone();
+            corrupt[window..window + 4].copy_from_slice(&min.to_le_bytes());
+            corrupt[window + 8..window + 12].copy_from_slice(&max.to_le_bytes());
+            assert!(ExrImage::from_bytes(&corrupt).is_err());
+        }
+    }
+
+    #[test]
+    fn test_half_to_f32() {
+        assert_eq!(1.0, half_to_f32(0x3c00));
+        assert_eq!(-2.0, half_to_f32(0xc000));
+        assert_eq!(0.5, half_to_f32(0x3800));
+        assert!(half_to_f32(0x7c00).is_infinite());
+    }
+}
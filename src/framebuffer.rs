@@ -0,0 +1,312 @@
+use crate::aov::AovBuffer;
+use crate::vec3::Colour;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RTCK";
+// Version 2 added the AOVs after the colours.
+const CHECKPOINT_VERSION: u32 = 2;
+// The magic, version, dimensions, seed, scene hash and pass count.
+const CHECKPOINT_HEADER_BYTES: u64 = 40;
+// Each pixel's sum and sum of squares, and its sample count.
+const CHECKPOINT_PIXEL_BYTES: u64 = 52;
+// Checkpoints claiming to be wider or taller than this are taken to be
+// corrupt, rather than trusted with the memory they'd need.
+const MAX_CHECKPOINT_SIDE: u32 = 1 << 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameBuffer {
+    width: i32,
+    height: i32,
+    seed: u64,
+    scene_hash: u64,
+    passes: u64,
+    sum: Vec<Colour>,
+    sum_sqrd: Vec<Colour>,
+    samples: Vec<u32>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32, seed: u64, scene_hash: u64) -> Self {
+        let len = (width * height) as usize;
+
+        FrameBuffer {
+            width,
+            height,
+            seed,
+            scene_hash,
+            passes: 0,
+            sum: vec![Colour::new(0.0, 0.0, 0.0); len],
+            sum_sqrd: vec![Colour::new(0.0, 0.0, 0.0); len],
+            samples: vec![0; len],
+        }
+    }
+
+    pub fn blank(&self) -> Self {
+        FrameBuffer::new(self.width, self.height, self.seed, self.scene_hash)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn scene_hash(&self) -> u64 {
+        self.scene_hash
+    }
+
+    // The number of full-image passes accumulated so far, which is also the
+    // index of the next sample to render when resuming.
+    pub fn passes(&self) -> u64 {
+        self.passes
+    }
+
+    pub fn accumulate(&mut self, pass: &[Colour]) {
+        assert_eq!(pass.len(), self.sum.len());
+
+        for (index, colour) in pass.iter().enumerate() {
+            self.sum[index] += *colour;
+            self.sum_sqrd[index] += *colour * *colour;
+            self.samples[index] += 1;
+        }
+        self.passes += 1;
+    }
+
+    pub fn merge(&mut self, other: &FrameBuffer) {
+        assert_eq!(other.sum.len(), self.sum.len());
+
+        for index in 0..self.sum.len() {
+            self.sum[index] += other.sum[index];
+            self.sum_sqrd[index] += other.sum_sqrd[index];
+            self.samples[index] += other.samples[index];
+        }
+        self.passes += other.passes;
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = (Colour, u32)> + '_ {
+        self.sum.iter().copied().zip(self.samples.iter().copied())
+    }
+
+    pub fn mean(&self) -> Vec<Colour> {
+        self.pixels()
+            .map(|(sum, samples)| sum / (samples.max(1) as f64))
+            .collect()
+    }
+
+    pub fn variance(&self) -> Vec<Colour> {
+        self.pixels()
+            .zip(self.sum_sqrd.iter())
+            .map(|((sum, samples), sum_sqrd)| {
+                let n = samples.max(1) as f64;
+                let mean = sum / n;
+                *sum_sqrd / n - mean * mean
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, width: i32, height: i32, scene_hash: u64) -> bool {
+        self.width == width && self.height == height && self.scene_hash == scene_hash
+    }
+
+    // Written to a sibling file first and renamed over the target, so a render
+    // killed mid-write still leaves the previous checkpoint intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_with_aovs(path, None)
+    }
+
+    // AOVs are kept in the same file, so they can't fall out of step with the
+    // colours they were rendered with.
+    pub fn save_with_aovs<P: AsRef<Path>>(
+        &self,
+        path: P,
+        aov_buffer: Option<&AovBuffer>,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+            writer.write_all(CHECKPOINT_MAGIC)?;
+            writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+            writer.write_all(&self.width.to_le_bytes())?;
+            writer.write_all(&self.height.to_le_bytes())?;
+            writer.write_all(&self.seed.to_le_bytes())?;
+            writer.write_all(&self.scene_hash.to_le_bytes())?;
+            writer.write_all(&self.passes.to_le_bytes())?;
+
+            for index in 0..self.sum.len() {
+                write_colour(&mut writer, &self.sum[index])?;
+                write_colour(&mut writer, &self.sum_sqrd[index])?;
+                writer.write_all(&self.samples[index].to_le_bytes())?;
+            }
+            match aov_buffer {
+                Some(aov_buffer) => aov_buffer.write(&mut writer)?,
+                None => writer.write_all(&0u32.to_le_bytes())?,
+            }
+            writer.flush()?;
+        }
+        fs::rename(tmp_path, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(FrameBuffer::load_with_aovs(path)?.0)
+    }
+
+    // The AOVs are None if the checkpoint was saved without them, or by a
+    // version that didn't keep them.
+    pub fn load_with_aovs<P: AsRef<Path>>(path: P) -> io::Result<(Self, Option<AovBuffer>)> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(invalid_data("not a rust-rt checkpoint"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version == 0 || version > CHECKPOINT_VERSION {
+            return Err(invalid_data("unsupported checkpoint version"));
+        }
+
+        let (width, height) = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+        if width == 0 || height == 0 || width > MAX_CHECKPOINT_SIDE || height > MAX_CHECKPOINT_SIDE
+        {
+            return Err(invalid_data("invalid checkpoint dimensions"));
+        }
+        // The pixels must all be there before room is made for them.
+        let remaining = u64::from(width)
+            .checked_mul(u64::from(height))
+            .and_then(|pixels| pixels.checked_mul(CHECKPOINT_PIXEL_BYTES))
+            .and_then(|pixels_len| file_len.checked_sub(CHECKPOINT_HEADER_BYTES + pixels_len))
+            .ok_or_else(|| invalid_data("checkpoint is shorter than its dimensions need"))?;
+        let (width, height) = (width as i32, height as i32);
+
+        let mut framebuffer = FrameBuffer::new(
+            width,
+            height,
+            read_u64(&mut reader)?,
+            read_u64(&mut reader)?,
+        );
+        framebuffer.passes = read_u64(&mut reader)?;
+
+        for index in 0..framebuffer.sum.len() {
+            framebuffer.sum[index] = read_colour(&mut reader)?;
+            framebuffer.sum_sqrd[index] = read_colour(&mut reader)?;
+            framebuffer.samples[index] = read_u32(&mut reader)?;
+        }
+
+        let aov_buffer = match version {
+            1 => None,
+            _ => AovBuffer::read(&mut reader, width, height, remaining)?,
+        };
+        Ok((framebuffer, aov_buffer))
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_colour<W: Write>(writer: &mut W, colour: &Colour) -> io::Result<()> {
+    writer.write_all(&colour.x().to_le_bytes())?;
+    writer.write_all(&colour.y().to_le_bytes())?;
+    writer.write_all(&colour.z().to_le_bytes())
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn read_colour<R: Read>(reader: &mut R) -> io::Result<Colour> {
+    Ok(Colour::new(
+        read_f64(reader)?,
+        read_f64(reader)?,
+        read_f64(reader)?,
+    ))
+}
+
+#[cfg(test)]
+mod framebuffer_tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut framebuffer = FrameBuffer::new(2, 1, 7, 42);
+        framebuffer.accumulate(&[Colour::new(0.1, 0.2, 0.3), Colour::new(1.0, 0.5, 0.0)]);
+        framebuffer.accumulate(&[Colour::new(0.3, 0.2, 0.1), Colour::new(0.0, 0.5, 1.0)]);
+
+        let path = std::env::temp_dir().join("rust_rt_checkpoint_round_trip.ckpt");
+        framebuffer.save(&path).unwrap();
+        let loaded = FrameBuffer::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(framebuffer, loaded);
+        assert_eq!(2, loaded.passes());
+        assert!(loaded.matches(2, 1, 42));
+        assert!(!loaded.matches(2, 1, 43));
+
+        // AOVs are kept alongside when given.
+        let aovs = AovBuffer::new(2, 1, vec![crate::aov::Aov::Albedo]);
+        framebuffer.save_with_aovs(&path, Some(&aovs)).unwrap();
+        let (loaded, loaded_aovs) = FrameBuffer::load_with_aovs(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(framebuffer, loaded);
+        assert_eq!(Some(aovs), loaded_aovs);
+
+        // Headers claiming more pixels than the file holds are turned away
+        // before anything is allocated for them.
+        for &(width, height) in &[(30_000u32, 30_000u32), (u32::MAX, u32::MAX), (2, 0)] {
+            let mut bytes = CHECKPOINT_MAGIC.to_vec();
+            for value in &[CHECKPOINT_VERSION, width, height] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 24 + 52 * 2]);
+            fs::write(&path, bytes).unwrap();
+            assert!(FrameBuffer::load(&path).is_err());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_merge_matches_sequential_accumulation() {
+        let pass_a = [Colour::new(0.25, 0.5, 1.0)];
+        let pass_b = [Colour::new(0.75, 0.5, 0.0)];
+
+        let mut sequential = FrameBuffer::new(1, 1, 0, 0);
+        sequential.accumulate(&pass_a);
+        sequential.accumulate(&pass_b);
+
+        let mut merged = sequential.blank();
+        let mut other = sequential.blank();
+        merged.accumulate(&pass_a);
+        other.accumulate(&pass_b);
+        merged.merge(&other);
+
+        assert_eq!(sequential, merged);
+        assert_eq!(vec![Colour::new(0.5, 0.5, 0.5)], merged.mean());
+        assert_eq!(vec![Colour::new(0.0625, 0.0, 0.25)], merged.variance());
+    }
+}
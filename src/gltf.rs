@@ -0,0 +1,665 @@
+use crate::camera::{Camera, Cameras, Orthographic};
+use crate::framebuffer::invalid_data;
+use crate::json::Json;
+use crate::material::{Dielectric, Lambertian, Materials, Metal};
+use crate::mesh::Mesh;
+use crate::objects::{Object, SceneObjects};
+use crate::transform::Transform;
+use crate::vec3::{Colour, Point3D, Vec3};
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const JSON_CHUNK: u32 = 0x4e4f_534a;
+const BIN_CHUNK: u32 = 0x004e_4942;
+const TRIANGLES: usize = 4;
+
+#[derive(Debug, Copy, Clone)]
+enum Lens {
+    Perspective { vertical_fov: f64 },
+    Orthographic { view_height: f64 },
+}
+
+// A camera placed in the scene by its node. glTF cameras look down their
+// local -z axis with y up.
+#[derive(Debug, Copy, Clone)]
+struct View {
+    lens: Lens,
+    origin: Point3D,
+    target: Point3D,
+    up: Vec3<f64>,
+}
+
+// The meshes, materials and cameras of the default scene of a glTF 2.0 file,
+// either .gltf JSON or binary .glb. Each triangle primitive becomes a mesh in
+// world space; other primitive modes are skipped.
+#[derive(Debug)]
+pub struct Gltf {
+    objects: SceneObjects,
+    materials: Vec<Materials>,
+    views: Vec<View>,
+}
+
+impl Gltf {
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Gltf> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Gltf::from_bytes(&fs::read(path)?, directory)
+    }
+
+    // Buffers that aren't embedded are read relative to `directory`.
+    pub fn from_bytes(bytes: &[u8], directory: &Path) -> io::Result<Gltf> {
+        let (text, binary) = match bytes.starts_with(GLB_MAGIC) {
+            true => glb_chunks(bytes)?,
+            false => (bytes, None),
+        };
+        let text = std::str::from_utf8(text).map_err(|_| invalid_data("glTF JSON isn't UTF-8"))?;
+        let root = Json::parse(text)?;
+
+        let buffers = list(&root, "buffers")
+            .iter()
+            .map(|buffer| match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) if uri.starts_with("data:") => data_uri(uri),
+                Some(uri) => fs::read(buffer_path(directory, uri)?),
+                None => binary
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| invalid_data("glTF buffer has no data")),
+            })
+            .collect::<io::Result<Vec<Vec<u8>>>>()?;
+        let materials = list(&root, "materials").iter().map(material).collect();
+
+        let mut importer = Importer {
+            root: &root,
+            buffers,
+            gltf: Gltf {
+                objects: SceneObjects::new(),
+                materials,
+                views: Vec::new(),
+            },
+        };
+        for node in scene_nodes(&root) {
+            importer.node(node, &Transform::identity(), 0)?;
+        }
+        Ok(importer.gltf)
+    }
+
+    pub fn objects(&self) -> &SceneObjects {
+        &self.objects
+    }
+
+    pub fn into_objects(self) -> SceneObjects {
+        self.objects
+    }
+
+    pub fn materials(&self) -> &[Materials] {
+        &self.materials
+    }
+
+    // The scene's cameras in the order they were found, framed for the
+    // image's aspect ratio rather than the one in the file.
+    pub fn cameras(&self, aspect_ratio: f64) -> Vec<Cameras> {
+        self.views
+            .iter()
+            .map(|view| match view.lens {
+                Lens::Perspective { vertical_fov } => Cameras::Perspective(Camera::new(
+                    aspect_ratio,
+                    vertical_fov,
+                    view.origin,
+                    view.target,
+                    view.up,
+                    0.0,
+                    1.0,
+                )),
+                Lens::Orthographic { view_height } => Cameras::Orthographic(Orthographic::new(
+                    aspect_ratio,
+                    view_height,
+                    view.origin,
+                    view.target,
+                    view.up,
+                )),
+            })
+            .collect()
+    }
+}
+
+struct Importer<'a> {
+    root: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    gltf: Gltf,
+}
+
+impl Importer<'_> {
+    fn node(&mut self, index: usize, parent: &Transform, depth: usize) -> io::Result<()> {
+        let nodes = list(self.root, "nodes");
+        let node = nodes
+            .get(index)
+            .ok_or_else(|| invalid_data("glTF node doesn't exist"))?;
+        if depth > nodes.len() {
+            return Err(invalid_data("glTF node hierarchy has a cycle"));
+        }
+        let transform = parent.then(&local_transform(node));
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            let mesh = list(self.root, "meshes")
+                .get(mesh)
+                .ok_or_else(|| invalid_data("glTF mesh doesn't exist"))?;
+            for primitive in list(mesh, "primitives") {
+                self.primitive(primitive, &transform)?;
+            }
+        }
+
+        if let Some(camera) = node.get("camera").and_then(Json::as_usize) {
+            let camera = list(self.root, "cameras")
+                .get(camera)
+                .ok_or_else(|| invalid_data("glTF camera doesn't exist"))?;
+            let property = |lens: &str, name: &str| camera.get(lens)?.get(name)?.as_f64();
+            let lens = match camera.get("type").and_then(Json::as_str) {
+                Some("orthographic") => Lens::Orthographic {
+                    view_height: 2.0 * property("orthographic", "ymag").unwrap_or(1.0),
+                },
+                _ => Lens::Perspective {
+                    vertical_fov: property("perspective", "yfov")
+                        .unwrap_or(std::f64::consts::FRAC_PI_4)
+                        .to_degrees(),
+                },
+            };
+            let origin = transform.point(Point3D::new(0.0, 0.0, 0.0));
+            self.gltf.views.push(View {
+                lens,
+                origin,
+                target: origin + transform.vector(Vec3::new(0.0, 0.0, -1.0)).unit(),
+                up: transform.vector(Vec3::new(0.0, 1.0, 0.0)).unit(),
+            });
+        }
+
+        for child in list(node, "children") {
+            let child = child
+                .as_usize()
+                .ok_or_else(|| invalid_data("invalid glTF node index"))?;
+            self.node(child, &transform, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn primitive(&mut self, primitive: &Json, transform: &Transform) -> io::Result<()> {
+        let mode = primitive.get("mode").and_then(Json::as_usize);
+        if mode.unwrap_or(TRIANGLES) != TRIANGLES {
+            return Ok(());
+        }
+        let attribute = |name: &str| primitive.get("attributes")?.get(name)?.as_usize();
+
+        let position =
+            attribute("POSITION").ok_or_else(|| invalid_data("glTF primitive has no positions"))?;
+        let positions: Vec<Point3D> = self
+            .accessor(position, 3)?
+            .chunks(3)
+            .map(|p| transform.point(Point3D::new(p[0], p[1], p[2])))
+            .collect();
+        let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+            Some(indices) => self
+                .accessor(indices, 1)?
+                .into_iter()
+                .map(|index| index as usize)
+                .collect(),
+            None => (0..positions.len()).collect(),
+        };
+        if indices.iter().any(|&index| index >= positions.len()) {
+            return Err(invalid_data("glTF index refers to a missing vertex"));
+        }
+
+        // A mirroring transform turns the triangles inside out, so their
+        // winding is swapped back.
+        let mirrored = transform.determinant() < 0.0;
+        let triangles: Vec<[usize; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| match mirrored {
+                true => [t[0], t[2], t[1]],
+                false => [t[0], t[1], t[2]],
+            })
+            .collect();
+        if triangles.is_empty() {
+            return Ok(());
+        }
+
+        let material = match primitive.get("material").and_then(Json::as_usize) {
+            Some(index) => self
+                .gltf
+                .materials
+                .get(index)
+                .cloned()
+                .ok_or_else(|| invalid_data("glTF material doesn't exist"))?,
+            None => material(&Json::Null),
+        };
+        let count = positions.len();
+        let mut mesh = Mesh::new(positions, triangles, material);
+
+        if let Some(normal) = attribute("NORMAL") {
+            let normals = self
+                .accessor(normal, 3)?
+                .chunks(3)
+                .map(|n| transform.normal(Vec3::new(n[0], n[1], n[2])))
+                .collect::<Vec<_>>();
+            if normals.len() == count {
+                mesh = mesh.set_normals(normals);
+            }
+        }
+        if let Some(uv) = attribute("TEXCOORD_0") {
+            // glTF puts v = 0 at the top of the image.
+            let uvs: Vec<(f64, f64)> = self
+                .accessor(uv, 2)?
+                .chunks(2)
+                .map(|uv| (uv[0], 1.0 - uv[1]))
+                .collect();
+            if uvs.len() == count {
+                mesh = mesh.set_uvs(uvs);
+            }
+        }
+
+        self.gltf.objects.add(Object::Mesh(mesh));
+        Ok(())
+    }
+
+    // An accessor's values as floats, flattened, checking that each element
+    // has the expected number of components.
+    fn accessor(&self, index: usize, components: usize) -> io::Result<Vec<f64>> {
+        let accessor = list(self.root, "accessors")
+            .get(index)
+            .ok_or_else(|| invalid_data("glTF accessor doesn't exist"))?;
+        if accessor.get("sparse").is_some() {
+            return Err(invalid_data("sparse glTF accessors are not supported"));
+        }
+        let count = accessor
+            .get("count")
+            .and_then(Json::as_usize)
+            .ok_or_else(|| invalid_data("glTF accessor has no count"))?;
+        let expected = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => 0,
+        };
+        if expected != components {
+            return Err(invalid_data("glTF accessor has the wrong type"));
+        }
+        let component_type = accessor.get("componentType").and_then(Json::as_usize);
+        let (size, read): (usize, fn(&[u8]) -> f64) = match component_type {
+            Some(5120) => (1, |b| b[0] as i8 as f64),
+            Some(5121) => (1, |b| b[0] as f64),
+            Some(5122) => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f64),
+            Some(5123) => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f64),
+            Some(5125) => (4, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+            Some(5126) => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+            _ => return Err(invalid_data("unknown glTF component type")),
+        };
+        // Normalised integers map onto 0 to 1, or -1 to 1 if signed.
+        let scale = match (accessor.get("normalized"), component_type) {
+            (Some(Json::Bool(true)), Some(5120)) => Some(127.0),
+            (Some(Json::Bool(true)), Some(5121)) => Some(255.0),
+            (Some(Json::Bool(true)), Some(5122)) => Some(32767.0),
+            (Some(Json::Bool(true)), Some(5123)) => Some(65535.0),
+            _ => None,
+        };
+
+        // The count comes from the file, so it's checked against the data
+        // before anything is allocated for it. Accessors without a buffer view
+        // are all zeros, and can't be longer than the file's buffers.
+        let overflow = || invalid_data("glTF accessor is too long");
+        let length = count.checked_mul(components * size).ok_or_else(overflow)?;
+        let view = match accessor.get("bufferView").and_then(Json::as_usize) {
+            Some(view) => list(self.root, "bufferViews")
+                .get(view)
+                .ok_or_else(|| invalid_data("glTF buffer view doesn't exist"))?,
+            None if length <= self.buffers.iter().map(Vec::len).sum() => {
+                return Ok(vec![0.0; count * components])
+            }
+            None => return Err(overflow()),
+        };
+        let buffer = view
+            .get("buffer")
+            .and_then(Json::as_usize)
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid_data("glTF buffer doesn't exist"))?;
+        let offset = |json: &Json| json.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let start = offset(view) + offset(accessor);
+        let stride = view
+            .get("byteStride")
+            .and_then(Json::as_usize)
+            .unwrap_or(size * components);
+        let view_end = view
+            .get("byteLength")
+            .and_then(Json::as_usize)
+            .and_then(|length| offset(view).checked_add(length))
+            .ok_or_else(|| invalid_data("glTF buffer view has no length"))?;
+        let end = match count {
+            0 => Some(start),
+            _ => (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(start))
+                .and_then(|last| last.checked_add(components * size)),
+        };
+        if end.is_none_or(|end| end > view_end || end > buffer.len()) {
+            return Err(invalid_data("glTF accessor runs past its buffer"));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = start + element * stride + component * size;
+                let bytes = buffer
+                    .get(at..at + size)
+                    .ok_or_else(|| invalid_data("glTF accessor runs past its buffer"))?;
+                values.push(match scale {
+                    Some(scale) => (read(bytes) / scale).max(-1.0),
+                    None => read(bytes),
+                });
+            }
+        }
+        Ok(values)
+    }
+}
+
+// glTF's metallic-roughness materials mapped onto the nearest of ours: mostly
+// transmissive ones become glass, mostly metallic ones fuzzy metal with the
+// roughness as fuzz, and the rest diffuse, all in the base colour. Textures
+// aren't read. A missing material is the specification's default, a rough
+// white metal.
+fn material(json: &Json) -> Materials {
+    let pbr = json.get("pbrMetallicRoughness");
+    let factor = |name: &str, default: f64| {
+        pbr.and_then(|pbr| pbr.get(name))
+            .and_then(Json::as_f64)
+            .unwrap_or(default)
+    };
+    let base = pbr
+        .and_then(|pbr| pbr.get("baseColorFactor"))
+        .and_then(Json::as_numbers)
+        .filter(|colour| colour.len() >= 3)
+        .map(|colour| Colour::new(colour[0], colour[1], colour[2]))
+        .unwrap_or_else(|| Colour::new(1.0, 1.0, 1.0));
+    let extension =
+        |name: &str, property: &str| json.get("extensions")?.get(name)?.get(property)?.as_f64();
+
+    let transmission = extension("KHR_materials_transmission", "transmissionFactor");
+    if transmission.unwrap_or(0.0) >= 0.5 {
+        let ior = extension("KHR_materials_ior", "ior").unwrap_or(1.5);
+        return Materials::Dielectric(Dielectric::new(ior));
+    }
+    match factor("metallicFactor", 1.0) >= 0.5 {
+        true => Materials::Metal(Metal::new(base, factor("roughnessFactor", 1.0))),
+        false => Materials::Lambertian(Lambertian::new(base)),
+    }
+}
+
+// The JSON and binary chunks of a .glb file.
+fn glb_chunks(bytes: &[u8]) -> io::Result<(&[u8], Option<&[u8]>)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid_data("truncated glTF binary"))
+    };
+    if word(4)? != 2 {
+        return Err(invalid_data("only glTF 2.0 is supported"));
+    }
+    let length = (word(8)? as usize).min(bytes.len());
+
+    let (mut json, mut binary) = (None, None);
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let size = word(offset)? as usize;
+        let data = bytes
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(|| invalid_data("truncated glTF binary"))?;
+        match word(offset + 4)? {
+            JSON_CHUNK => json = json.or(Some(data)),
+            BIN_CHUNK => binary = binary.or(Some(data)),
+            _ => {}
+        }
+        offset += 8 + size;
+    }
+    Ok((
+        json.ok_or_else(|| invalid_data("glTF binary has no JSON chunk"))?,
+        binary,
+    ))
+}
+
+fn data_uri(uri: &str) -> io::Result<Vec<u8>> {
+    let start = uri
+        .find(";base64,")
+        .ok_or_else(|| invalid_data("glTF data URI isn't base64"))?;
+    base64(&uri[start + 8..])
+}
+
+// Buffer URIs are relative references, percent-encoded, that mustn't reach
+// outside the directory the asset is in.
+fn buffer_path(directory: &Path, uri: &str) -> io::Result<PathBuf> {
+    let mut bytes = Vec::with_capacity(uri.len());
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| invalid_data("invalid percent-encoding in glTF buffer URI"))?;
+        bytes.push(hex);
+        rest = &rest[2..];
+    }
+    let path = String::from_utf8(bytes).map_err(|_| invalid_data("glTF buffer URI isn't UTF-8"))?;
+
+    let path = Path::new(&path);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid_data(
+            "glTF buffer URI points outside the asset's directory",
+        ));
+    }
+    Ok(directory.join(path))
+}
+
+fn base64(text: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for character in text.bytes() {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ if character.is_ascii_whitespace() => continue,
+            _ => return Err(invalid_data("invalid base64 in glTF data URI")),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// The root nodes of the default scene, or of the first scene if none is
+// marked as default.
+fn scene_nodes(root: &Json) -> Vec<usize> {
+    let index = root.get("scene").and_then(Json::as_usize).unwrap_or(0);
+    list(root, "scenes")
+        .get(index)
+        .map(|scene| {
+            list(scene, "nodes")
+                .iter()
+                .filter_map(Json::as_usize)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// An array member, or an empty one if it's missing.
+fn list<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn local_transform(node: &Json) -> Transform {
+    if let Some(matrix) = node.get("matrix").and_then(Json::as_numbers) {
+        if let Ok(columns) = matrix.try_into() {
+            return Transform::from_columns(columns);
+        }
+    }
+    let vector = |name: &str, default: Vec<f64>| {
+        node.get(name)
+            .and_then(Json::as_numbers)
+            .filter(|values| values.len() == default.len())
+            .unwrap_or(default)
+    };
+    let t = vector("translation", vec![0.0, 0.0, 0.0]);
+    let q = vector("rotation", vec![0.0, 0.0, 0.0, 1.0]);
+    let s = vector("scale", vec![1.0, 1.0, 1.0]);
+
+    Transform::translate(Vec3::new(t[0], t[1], t[2]))
+        .then(&Transform::from_quaternion(q[0], q[1], q[2], q[3]))
+        .then(&Transform::scale(s[0], s[1], s[2]))
+}
+
+#[cfg(test)]
+mod gltf_tests {
+    use super::*;
+    use crate::objects::Hit;
+    use crate::ray::Ray;
+
+    // One triangle in a buffer: three float positions, then three short
+    // indices padded to four bytes.
+    fn buffer() -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for value in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in &[0u16, 1, 2, 0] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn document(buffer: &str) -> String {
+        format!(
+            r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0, 2]}}],
+            "nodes": [
+                {{"children": [1], "translation": [0, 0, -5]}},
+                {{"mesh": 0, "scale": [2, 2, 2], "rotation": [0, 0, 0.7071067811865476, 0.7071067811865476]}},
+                {{"camera": 0, "translation": [0, 0, 5]}}
+            ],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}}}],
+            "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.5, "znear": 0.1}}}}],
+            "buffers": [{{"byteLength": 44{}}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}, {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ]
+        }}"#,
+            buffer
+        )
+    }
+
+    fn check(gltf: &Gltf) {
+        assert_eq!(1, gltf.materials().len());
+        assert!(gltf.objects().object(2).is_none());
+
+        // Rotated a quarter turn about z and doubled, so the triangle spans
+        // x from -2 to 0 and y from 0 to 2, five units down -z.
+        let object = gltf.objects().object(1).unwrap();
+        let bounds = object.bounding_box().unwrap();
+        assert!((bounds.min().x() + 2.0).abs() < 1e-9 && bounds.max().x().abs() < 1e-9);
+        assert!((bounds.max().y() - 2.0).abs() < 1e-9);
+        let ray = Ray::new(Point3D::new(-0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = object.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.time() - 5.0).abs() < 1e-9);
+        assert!(record.front_face());
+        assert_eq!(Colour::new(1.0, 0.0, 0.0), record.material().albedo());
+
+        let cameras = gltf.cameras(1.5);
+        match cameras.as_slice() {
+            [Cameras::Perspective(camera)] => {
+                assert_eq!(Point3D::new(0.0, 0.0, 5.0), camera.origin())
+            }
+            _ => panic!("expected one perspective camera"),
+        }
+    }
+
+    #[test]
+    fn test_embedded_and_binary_files() {
+        let mut encoded = String::new();
+        for chunk in buffer().chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                bits | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                encoded.push(match i <= chunk.len() {
+                    true => {
+                        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+                            [(bits >> (18 - 6 * i) & 63) as usize] as char
+                    }
+                    false => '=',
+                });
+            }
+        }
+        let uri = format!(
+            r#", "uri": "data:application/octet-stream;base64,{}""#,
+            encoded
+        );
+        let embedded = Gltf::from_bytes(document(&uri).as_bytes(), Path::new("")).unwrap();
+        check(&embedded);
+
+        // A count from the file mustn't be trusted for allocating.
+        let huge = document(&uri).replacen(r#""count": 3"#, r#""count": 1e18"#, 1);
+        assert!(Gltf::from_bytes(huge.as_bytes(), Path::new("")).is_err());
+
+        let mut json = document("").into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let binary = buffer();
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+        for (data, kind) in &[(&json, JSON_CHUNK), (&binary, BIN_CHUNK)] {
+            glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&kind.to_le_bytes());
+            glb.extend_from_slice(data);
+        }
+        check(&Gltf::from_bytes(&glb, Path::new("")).unwrap());
+    }
+
+    #[test]
+    fn test_external_buffers() {
+        let directory = std::env::temp_dir().join("rust_rt_gltf_buffers");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("one triangle.bin"), buffer()).unwrap();
+        let uri = r#", "uri": "one%20triangle.bin""#;
+        check(&Gltf::from_bytes(document(uri).as_bytes(), &directory).unwrap());
+
+        for uri in &[
+            "../one triangle.bin",
+            "/etc/passwd",
+            "%2e%2e/one%20triangle.bin",
+            "%zz",
+        ] {
+            let document = document(&format!(r#", "uri": "{}""#, uri));
+            let error = Gltf::from_bytes(document.as_bytes(), &directory).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind(), "{}", uri);
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
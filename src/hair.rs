@@ -0,0 +1,500 @@
+use crate::objects::HitRecord;
+use crate::planar::tangent_frame;
+use crate::ray::Ray;
+use crate::utils::{clamp, random_double};
+use crate::vec3::{Colour, Vec3};
+use std::f64::consts::PI;
+
+// Scattering lobes that are tracked separately: R, TT and TRT. Everything
+// after them is lumped into one isotropic lobe.
+const P_MAX: usize = 3;
+const SQRT_PI_OVER_8: f64 = 0.626_657_068_657_750_1;
+
+// Absorption per unit of melanin concentration.
+const EUMELANIN: (f64, f64, f64) = (0.419, 0.697, 1.37);
+const PHEOMELANIN: (f64, f64, f64) = (0.187, 0.4, 1.05);
+
+// The hair scattering model from Physically Based Rendering (Chiang et al.
+// 2016 with d'Eon's longitudinal scattering). Strands are rough dielectric
+// cylinders that absorb `sigma_a` per unit of diameter. It relies on the hit
+// record's tangent for the direction of the strand and its v coordinate for
+// where across the width it was hit, as curves provide.
+#[derive(Debug, Clone)]
+pub struct Hair {
+    sigma_a: Colour,
+    eta: f64,
+    beta_m: f64,
+    beta_n: f64,
+    alpha: f64,
+    albedo: Colour,
+}
+
+impl Hair {
+    pub fn new(sigma_a: Colour) -> Self {
+        let channel = |sigma: f64| (-sigma).exp();
+        Hair {
+            sigma_a,
+            eta: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+            albedo: Colour::new(
+                channel(sigma_a.x()),
+                channel(sigma_a.y()),
+                channel(sigma_a.z()),
+            ),
+        }
+    }
+
+    // Absorption that gives roughly `colour` after multiple scattering, for
+    // the default azimuthal roughness.
+    pub fn from_reflectance(colour: Colour) -> Self {
+        let beta_n: f64 = 0.3;
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let channel = |c: f64| (c.max(1e-4).ln() / denominator).powi(2);
+        Hair {
+            albedo: colour,
+            ..Hair::new(Colour::new(
+                channel(colour.x()),
+                channel(colour.y()),
+                channel(colour.z()),
+            ))
+        }
+    }
+
+    // Natural hair colours from eumelanin (brown to black) and pheomelanin
+    // (red) concentrations; about 0.3 is blonde, 1.3 brown and 8 black.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64) -> Self {
+        Hair::new(Colour::new(
+            eumelanin * EUMELANIN.0 + pheomelanin * PHEOMELANIN.0,
+            eumelanin * EUMELANIN.1 + pheomelanin * PHEOMELANIN.1,
+            eumelanin * EUMELANIN.2 + pheomelanin * PHEOMELANIN.2,
+        ))
+    }
+
+    pub fn set_eta(mut self, eta: f64) -> Self {
+        self.eta = eta;
+        self
+    }
+
+    // Longitudinal and azimuthal roughness, from 0 to 1.
+    pub fn set_roughness(mut self, beta_m: f64, beta_n: f64) -> Self {
+        self.beta_m = beta_m;
+        self.beta_n = beta_n;
+        self
+    }
+
+    // The tilt of the cuticle scales, in degrees.
+    pub fn set_scale_angle(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    // The colour reported for it in AOVs, which is otherwise worked out
+    // from the absorption.
+    pub fn set_albedo(mut self, albedo: Colour) -> Self {
+        self.albedo = albedo;
+        self
+    }
+
+    pub fn albedo(&self) -> Colour {
+        self.albedo
+    }
+
+    pub fn sigma_a(&self) -> Colour {
+        self.sigma_a
+    }
+
+    pub fn eta(&self) -> f64 {
+        self.eta
+    }
+
+    pub fn roughness(&self) -> (f64, f64) {
+        (self.beta_m, self.beta_n)
+    }
+
+    pub fn scale_angle(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn scatter(&self, hit_record: &HitRecord, ray: &Ray) -> Option<(Ray, Colour)> {
+        // The frame has x along the strand and z facing back along the ray,
+        // across the strand, as for the flat curves the model is built on.
+        let tangent = match hit_record.tangent().near_zero() {
+            true => tangent_frame(hit_record.normal()).0,
+            false => hit_record.tangent().unit(),
+        };
+        let wo = -ray.direction().unit();
+        let facing = wo - tangent * wo.dot(&tangent);
+        let z = match facing.near_zero() {
+            true => hit_record.normal(),
+            false => facing.unit(),
+        };
+        let y = z.cross(&tangent);
+
+        let h = clamp(2.0 * hit_record.uv().1 - 1.0, -1.0, 1.0);
+        let bsdf = HairBsdf::new(self, h);
+        let local = Vec3::new(wo.dot(&tangent), wo.dot(&y), wo.dot(&z));
+        let samples = [
+            random_double(),
+            random_double(),
+            random_double(),
+            random_double(),
+        ];
+        let (wi, f, pdf) = bsdf.sample(local, samples);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        let direction = wi.x() * tangent + wi.y() * y + wi.z() * z;
+        Some((Ray::new(hit_record.point(), direction), f / pdf))
+    }
+}
+
+// The model evaluated at one offset `h` across the strand, from -1 to 1.
+struct HairBsdf {
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Colour,
+    v: [f64; P_MAX + 1],
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairBsdf {
+    fn new(hair: &Hair, h: f64) -> Self {
+        let beta_m = hair.beta_m;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let beta_n = hair.beta_n;
+        let s =
+            SQRT_PI_OVER_8 * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [hair.alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        HairBsdf {
+            h,
+            gamma_o: safe_asin(h),
+            eta: hair.eta,
+            sigma_a: hair.sigma_a,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // The outgoing elevation tilted by the cuticle scales for lobe p.
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = (self.sin_2k_alpha, self.cos_2k_alpha);
+        let (sin_op, cos_op) = match p {
+            0 => (
+                sin_theta_o * cos[1] - cos_theta_o * sin[1],
+                cos_theta_o * cos[1] + sin_theta_o * sin[1],
+            ),
+            1 => (
+                sin_theta_o * cos[0] + cos_theta_o * sin[0],
+                cos_theta_o * cos[0] - sin_theta_o * sin[0],
+            ),
+            2 => (
+                sin_theta_o * cos[2] + cos_theta_o * sin[2],
+                cos_theta_o * cos[2] - sin_theta_o * sin[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_op, cos_op.abs())
+    }
+
+    // Transmittance through the strand, and the refracted azimuth.
+    fn transmittance(&self, sin_theta_o: f64, cos_theta_o: f64) -> (Colour, f64) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = self.h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+
+        let path = 2.0 * cos_gamma_t / cos_theta_t;
+        let absorb = |sigma: f64| (-sigma * path).exp();
+        let t = Colour::new(
+            absorb(self.sigma_a.x()),
+            absorb(self.sigma_a.y()),
+            absorb(self.sigma_a.z()),
+        );
+        (t, safe_asin(sin_gamma_t))
+    }
+
+    // How much light each lobe carries.
+    fn attenuation(&self, cos_theta_o: f64, t: Colour) -> [Colour; P_MAX + 1] {
+        let cos_gamma_o = safe_sqrt(1.0 - self.h * self.h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let white = Colour::new(1.0, 1.0, 1.0);
+
+        let r = white * f;
+        let tt = t * (1.0 - f) * (1.0 - f);
+        let trt = tt * t * f;
+        let rest =
+            |channel: fn(&Colour) -> f64| channel(&trt) * f * channel(&t) / (1.0 - channel(&t) * f);
+        [
+            r,
+            tt,
+            trt,
+            Colour::new(rest(Colour::x), rest(Colour::y), rest(Colour::z)),
+        ]
+    }
+
+    // Probabilities of sampling each lobe, by its share of the light.
+    fn lobe_pdf(&self, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let (t, _) = self.transmittance(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, t);
+        let weight = |colour: &Colour| (colour.x() + colour.y() + colour.z()) / 3.0;
+        let total: f64 = ap.iter().map(weight).sum();
+
+        let mut pdf = [0.0; P_MAX + 1];
+        for (p, colour) in ap.iter().enumerate() {
+            pdf[p] = weight(colour) / total;
+        }
+        pdf
+    }
+
+    // The BSDF times the cosine to wi, which is what a path carries.
+    fn f(&self, wo: Vec3<f64>, wi: Vec3<f64>) -> Colour {
+        let (sin_theta_o, cos_theta_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i) = angles(wi);
+        let phi = wi.z().atan2(wi.y()) - wo.z().atan2(wo.y());
+
+        let (t, gamma_t) = self.transmittance(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, t);
+
+        let mut sum = Colour::new(0.0, 0.0, 0.0);
+        for (p, lobe) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = longitudinal(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p]);
+            sum += *lobe * (m * self.azimuthal(phi, p, gamma_t));
+        }
+        let m = longitudinal(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        sum + ap[P_MAX] * (m / (2.0 * PI))
+    }
+
+    fn pdf(&self, wo: Vec3<f64>, wi: Vec3<f64>) -> f64 {
+        let (sin_theta_o, cos_theta_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i) = angles(wi);
+        let phi = wi.z().atan2(wi.y()) - wo.z().atan2(wo.y());
+        let (_, gamma_t) = self.transmittance(sin_theta_o, cos_theta_o);
+        let lobe_pdf = self.lobe_pdf(cos_theta_o);
+
+        let mut pdf = 0.0;
+        for (p, lobe) in lobe_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = longitudinal(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p]);
+            pdf += m * lobe * self.azimuthal(phi, p, gamma_t);
+        }
+        let m = longitudinal(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        pdf + m * lobe_pdf[P_MAX] / (2.0 * PI)
+    }
+
+    // Picks a lobe, then an elevation and azimuth from it. Returns wi with
+    // f and the pdf of sampling it through any lobe.
+    fn sample(&self, wo: Vec3<f64>, u: [f64; 4]) -> (Vec3<f64>, Colour, f64) {
+        let (sin_theta_o, cos_theta_o) = angles(wo);
+        let phi_o = wo.z().atan2(wo.y());
+        let lobe_pdf = self.lobe_pdf(cos_theta_o);
+
+        let mut choice = u[0];
+        let mut p = 0;
+        while p < P_MAX && choice >= lobe_pdf[p] {
+            choice -= lobe_pdf[p];
+            p += 1;
+        }
+
+        let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let u1 = u[1].max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * u[2]).cos();
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let (_, gamma_t) = self.transmittance(sin_theta_o, cos_theta_o);
+        let dphi = match p < P_MAX {
+            true => phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(u[3], self.s),
+            false => 2.0 * PI * u[3],
+        };
+        let phi_i = phi_o + dphi;
+        let wi = Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+
+        (wi, self.f(wo, wi), self.pdf(wo, wi))
+    }
+
+    fn azimuthal(&self, phi_difference: f64, p: usize, gamma_t: f64) -> f64 {
+        let mut dphi = phi_difference - phi(p, self.gamma_o, gamma_t);
+        while dphi > PI {
+            dphi -= 2.0 * PI;
+        }
+        while dphi < -PI {
+            dphi += 2.0 * PI;
+        }
+        trimmed_logistic(dphi, self.s)
+    }
+}
+
+// Sine and cosine of the elevation from the strand's normal plane.
+fn angles(w: Vec3<f64>) -> (f64, f64) {
+    let sin_theta = clamp(w.x(), -1.0, 1.0);
+    (sin_theta, safe_sqrt(1.0 - sin_theta * sin_theta))
+}
+
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn longitudinal(
+    cos_theta_i: f64,
+    cos_theta_o: f64,
+    sin_theta_i: f64,
+    sin_theta_o: f64,
+    v: f64,
+) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    match v <= 0.1 {
+        // Written in logs so narrow lobes don't overflow.
+        true => {
+            (log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+        }
+        false => ((-b).exp() * bessel_i0(a)) / ((1.0 / v).sinh() * 2.0 * v),
+    }
+}
+
+// The modified Bessel function of the first kind, by its series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let x2 = x * x / 4.0;
+    for i in 1..10 {
+        term *= x2 / (i * i) as f64;
+        sum += term;
+    }
+    sum
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    match x > 12.0 {
+        true => x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x)),
+        false => bessel_i0(x).ln(),
+    }
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let e = (-x.abs() / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// The logistic distribution restricted to [-pi, pi].
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    clamp(x, -PI, PI)
+}
+
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = clamp(cos_theta_i, -1.0, 1.0);
+    let (eta_i, eta_t, cos_i) = match cos_theta_i > 0.0 {
+        true => (1.0, eta, cos_theta_i),
+        false => (eta, 1.0, -cos_theta_i),
+    };
+    let sin_t = eta_i / eta_t * safe_sqrt(1.0 - cos_i * cos_i);
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+    let parallel = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let perpendicular = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    clamp(x, -1.0, 1.0).asin()
+}
+
+#[cfg(test)]
+mod hair_tests {
+    use super::*;
+    use crate::utils::{random_range, random_unit_vec, seed_sampler};
+
+    fn random_samples() -> [f64; 4] {
+        [
+            random_double(),
+            random_double(),
+            random_double(),
+            random_double(),
+        ]
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        // Without absorption the lobes together scatter all the light, and
+        // sampling follows f exactly, so every sample's weight is one.
+        seed_sampler(7);
+        for &roughness in &[0.2, 0.5, 0.9] {
+            let hair = Hair::new(Colour::new(0.0, 0.0, 0.0)).set_roughness(roughness, roughness);
+
+            for n in 0..10 {
+                let bsdf = HairBsdf::new(&hair, random_range(-1.0, 1.0));
+                let wo = random_unit_vec();
+                for _ in 0..10 {
+                    let (_, f, pdf) = bsdf.sample(wo, random_samples());
+                    assert!((f.y() / pdf - 1.0).abs() < 1e-3);
+                }
+
+                if roughness < 0.5 || n >= 3 {
+                    continue;
+                }
+                let count = 60_000;
+                let total: f64 = (0..count)
+                    .map(|_| bsdf.f(wo, random_unit_vec()).y() * 4.0 * PI / count as f64)
+                    .sum();
+                assert!((total - 1.0).abs() < 0.05, "furnace {}", total);
+            }
+        }
+    }
+}
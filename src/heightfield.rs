@@ -0,0 +1,358 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::mesh::triangle_hit;
+use crate::objects::{Hit, HitRecord};
+use crate::pnm::GreyImage;
+use crate::ray::Ray;
+use crate::vec3::{Point3D, Vec3};
+
+// One level of the maximum mipmap: the lowest and highest point of each
+// block of 2^level by 2^level cells.
+#[derive(Debug, Clone)]
+struct Level {
+    width: usize,
+    depth: usize,
+    ranges: Vec<(f64, f64)>,
+}
+
+// A terrain from a grid of heights, such as a DEM image, with one sample per
+// vertex. Image columns run along x and rows along z from `corner`, and
+// values of 0 to 1 map to heights across `size.y()`. Each cell is two
+// triangles with normals interpolated from the vertices, found by walking a
+// quadtree of height ranges rather than storing any triangles. The UVs run
+// from 0 to 1 across the whole grid.
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    corner: Point3D,
+    size: Vec3<f64>,
+    columns: usize,
+    rows: usize,
+    heights: Vec<f64>,
+    normals: Vec<Vec3<f64>>,
+    levels: Vec<Level>,
+    material: Materials,
+}
+
+impl Heightfield {
+    pub fn new(image: &GreyImage, corner: Point3D, size: Vec3<f64>, material: Materials) -> Self {
+        let (columns, rows) = (image.width(), image.height());
+        assert!(
+            columns >= 2 && rows >= 2,
+            "a heightfield needs at least 2x2 samples"
+        );
+
+        let heights: Vec<f64> = image
+            .values()
+            .iter()
+            .map(|value| corner.y() + value * size.y())
+            .collect();
+        let mut heightfield = Heightfield {
+            corner,
+            size,
+            columns,
+            rows,
+            heights,
+            normals: Vec::new(),
+            levels: Vec::new(),
+            material,
+        };
+        heightfield.normals = heightfield.vertex_normals();
+        heightfield.levels = heightfield.mipmap();
+        heightfield
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn corner(&self) -> Point3D {
+        self.corner
+    }
+
+    pub fn size(&self) -> Vec3<f64> {
+        self.size
+    }
+
+    // The heights as image values from 0 to 1, as they were given.
+    pub fn image(&self) -> GreyImage {
+        let values = self
+            .heights
+            .iter()
+            .map(|height| match self.size.y() == 0.0 {
+                true => 0.0,
+                false => (height - self.corner.y()) / self.size.y(),
+            })
+            .collect();
+        GreyImage::new(self.columns, self.rows, values)
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.corner + self.size / 2.0
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.size.x() / (self.columns - 1) as f64,
+            self.size.z() / (self.rows - 1) as f64,
+        )
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3D {
+        let (dx, dz) = self.cell_size();
+        Point3D::new(
+            self.corner.x() + i as f64 * dx,
+            self.heights[j * self.columns + i],
+            self.corner.z() + j as f64 * dz,
+        )
+    }
+
+    // From central differences of the heights, one-sided at the edges.
+    fn vertex_normals(&self) -> Vec<Vec3<f64>> {
+        let (dx, dz) = self.cell_size();
+        let height = |i: usize, j: usize| self.heights[j * self.columns + i];
+        let mut normals = Vec::with_capacity(self.heights.len());
+        for j in 0..self.rows {
+            for i in 0..self.columns {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+                let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f64 * dx);
+                let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f64 * dz);
+                normals.push(Vec3::new(-slope_x, 1.0, -slope_z).unit());
+            }
+        }
+        normals
+    }
+
+    fn mipmap(&self) -> Vec<Level> {
+        let (width, depth) = (self.columns - 1, self.rows - 1);
+        let mut ranges = Vec::with_capacity(width * depth);
+        for j in 0..depth {
+            for i in 0..width {
+                let corners = [
+                    self.heights[j * self.columns + i],
+                    self.heights[j * self.columns + i + 1],
+                    self.heights[(j + 1) * self.columns + i],
+                    self.heights[(j + 1) * self.columns + i + 1],
+                ];
+                let low = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+                let high = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                ranges.push((low, high));
+            }
+        }
+
+        let mut levels = vec![Level {
+            width,
+            depth,
+            ranges,
+        }];
+        while let Some(below) = levels.last().filter(|level| level.width * level.depth > 1) {
+            let (width, depth) = (below.width.div_ceil(2), below.depth.div_ceil(2));
+            let mut ranges = vec![(f64::INFINITY, f64::NEG_INFINITY); width * depth];
+            for j in 0..below.depth {
+                for i in 0..below.width {
+                    let (low, high) = below.ranges[j * below.width + i];
+                    let range = &mut ranges[(j / 2) * width + i / 2];
+                    *range = (range.0.min(low), range.1.max(high));
+                }
+            }
+            levels.push(Level {
+                width,
+                depth,
+                ranges,
+            });
+        }
+        levels
+    }
+
+    fn node_bounds(&self, level: usize, i: usize, j: usize) -> Aabb {
+        let (dx, dz) = self.cell_size();
+        let span = 1 << level;
+        let (low, high) = self.levels[level].ranges[j * self.levels[level].width + i];
+        let x1 = ((i + 1) * span).min(self.columns - 1);
+        let z1 = ((j + 1) * span).min(self.rows - 1);
+
+        Aabb::new(
+            Point3D::new(
+                self.corner.x() + (i * span) as f64 * dx,
+                low,
+                self.corner.z() + (j * span) as f64 * dz,
+            ),
+            Point3D::new(
+                self.corner.x() + x1 as f64 * dx,
+                high,
+                self.corner.z() + z1 as f64 * dz,
+            ),
+        )
+    }
+
+    fn cell_hit(
+        &self,
+        i: usize,
+        j: usize,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'_>> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let mut closest = t_max;
+        let mut hit = None;
+
+        for triangle in &[[0, 1, 2], [0, 2, 3]] {
+            let [a, b, c] = triangle.map(|corner| corners[corner]);
+            let (p0, p1, p2) = (
+                self.vertex(a.0, a.1),
+                self.vertex(b.0, b.1),
+                self.vertex(c.0, c.1),
+            );
+            if let Some((t, beta, gamma)) = triangle_hit(ray, p0, p1, p2) {
+                if t < t_min || closest < t {
+                    continue;
+                }
+                let normal = |(i, j): (usize, usize)| self.normals[j * self.columns + i];
+                let smooth =
+                    (1.0 - beta - gamma) * normal(a) + beta * normal(b) + gamma * normal(c);
+                closest = t;
+                hit = Some((t, smooth.unit()));
+            }
+        }
+
+        let (t, normal) = hit?;
+        let point = ray.at(t);
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(
+                    (point.x() - self.corner.x()) / self.size.x(),
+                    (point.z() - self.corner.z()) / self.size.z(),
+                )
+                .set_face_normal(ray, &normal),
+        )
+    }
+}
+
+impl Hit for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let top = self.levels.len() - 1;
+        let entry = self.node_bounds(top, 0, 0).hit_span(ray, t_min, t_max)?.0;
+        let mut stack = vec![(entry, top, 0, 0)];
+        let mut closest = t_max;
+        let mut hit = None;
+
+        // Nodes are pushed far to near, so the first cell hit is usually
+        // the closest, and anything entered beyond it is skipped.
+        while let Some((entry, level, i, j)) = stack.pop() {
+            if entry > closest {
+                continue;
+            }
+            if level == 0 {
+                if let Some(record) = self.cell_hit(i, j, ray, t_min, closest) {
+                    closest = record.time();
+                    hit = Some(record);
+                }
+                continue;
+            }
+
+            let below = &self.levels[level - 1];
+            let mut children = Vec::with_capacity(4);
+            for (ci, cj) in [
+                (2 * i, 2 * j),
+                (2 * i + 1, 2 * j),
+                (2 * i, 2 * j + 1),
+                (2 * i + 1, 2 * j + 1),
+            ] {
+                if ci >= below.width || cj >= below.depth {
+                    continue;
+                }
+                let bounds = self.node_bounds(level - 1, ci, cj);
+                if let Some((entry, _)) = bounds.hit_span(ray, t_min, closest) {
+                    children.push((entry, level - 1, ci, cj));
+                }
+            }
+            children.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+            stack.extend(children);
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.node_bounds(self.levels.len() - 1, 0, 0))
+    }
+}
+
+#[cfg(test)]
+mod heightfield_tests {
+    use super::*;
+    use crate::material::grey;
+
+    #[test]
+    fn test_flat_field() {
+        let image = GreyImage::new(3, 3, vec![0.5; 9]);
+        let field = Heightfield::new(
+            &image,
+            Point3D::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 2.0, 2.0),
+            grey(),
+        );
+        let ray = Ray::new(Point3D::new(0.5, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.time() - 2.0).abs() < 1e-12);
+        assert!((record.normal() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert_eq!((0.75, 0.5), record.uv());
+
+        let beside = Ray::new(Point3D::new(1.5, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(field.hit(&beside, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_mipmap_matches_every_cell() {
+        // An odd-sized, hilly grid, so the quadtree has ragged edges.
+        let (columns, rows) = (37, 23);
+        let values = (0..columns * rows)
+            .map(|index| {
+                let (x, z) = ((index % columns) as f64, (index / columns) as f64);
+                0.5 + 0.25 * (0.4 * x).sin() * (0.3 * z).cos() + 0.2 * (0.9 * x + 0.7 * z).sin()
+            })
+            .collect();
+        let image = GreyImage::new(columns, rows, values);
+        let field = Heightfield::new(
+            &image,
+            Point3D::new(-3.0, 0.0, -2.0),
+            Vec3::new(6.0, 1.5, 4.0),
+            grey(),
+        );
+        assert_eq!(7, field.levels.len());
+
+        let mut hits = 0;
+        for n in 0..300 {
+            let k = n as f64;
+            let origin = Point3D::new(4.0 * (1.3 * k).sin(), 2.5, 4.0 * (0.7 * k).cos());
+            let target = Point3D::new(3.0 * (2.1 * k).sin(), 0.5, 2.0 * (1.7 * k).cos());
+            let ray = Ray::new(origin, target - origin);
+
+            let mut expected: Option<f64> = None;
+            for j in 0..rows - 1 {
+                for i in 0..columns - 1 {
+                    if let Some(record) = field.cell_hit(i, j, &ray, 0.001, f64::INFINITY) {
+                        expected = Some(expected.map_or(record.time(), |t| t.min(record.time())));
+                    }
+                }
+            }
+
+            let found = field
+                .hit(&ray, 0.001, f64::INFINITY)
+                .map(|record| record.time());
+            assert_eq!(expected, found);
+            hits += found.is_some() as usize;
+        }
+        assert!(hits > 100);
+    }
+}
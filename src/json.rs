@@ -0,0 +1,445 @@
+use std::fmt;
+use std::io;
+
+// Arrays and objects nested deeper than this are rejected, so hostile files
+// can't overflow the stack of the recursive parser.
+const MAX_DEPTH: usize = 512;
+
+// A parsed JSON document. Object members keep the order they were written in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.position == parser.bytes.len() {
+            true => Ok(value),
+            false => Err(parser.error("trailing characters")),
+        }
+    }
+
+    // The member called `key`, if this is an object that has one.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    // An array of numbers, as for vectors and matrices.
+    pub fn as_numbers(&self) -> Option<Vec<f64>> {
+        self.as_array()?.iter().map(Json::as_f64).collect()
+    }
+}
+
+// Written compactly, or indented with `{:#}`, where arrays of plain values
+// such as vectors stay on one line. Infinities are written as numbers too
+// large for a double, which read back as infinite, and NaN as null.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = match f.alternate() {
+            true => Some(0),
+            false => None,
+        };
+        self.write(f, indent)
+    }
+}
+
+impl Json {
+    fn write(&self, f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+        let is_plain = |value: &Json| !matches!(value, Json::Array(_) | Json::Object(_));
+        let inner = indent.map(|level| level + 1);
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.is_nan() => write!(f, "null"),
+            Json::Number(number) if number.is_infinite() => match *number > 0.0 {
+                true => write!(f, "1e999"),
+                false => write!(f, "-1e999"),
+            },
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(string) => write_string(f, string),
+            Json::Array(values) if values.iter().all(is_plain) => {
+                let separator = match indent {
+                    Some(_) => ", ",
+                    None => ",",
+                };
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "{}", separator)?;
+                    }
+                    value.write(f, None)?;
+                }
+                write!(f, "]")
+            }
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    new_line(f, inner)?;
+                    value.write(f, inner)?;
+                }
+                new_line(f, indent)?;
+                write!(f, "]")
+            }
+            Json::Object(members) if members.is_empty() => write!(f, "{{}}"),
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    new_line(f, inner)?;
+                    write_string(f, key)?;
+                    write!(f, ":{}", if indent.is_some() { " " } else { "" })?;
+                    value.write(f, inner)?;
+                }
+                new_line(f, indent)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// Starts an indented line, unless writing compactly.
+fn new_line(f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+    match indent {
+        Some(level) => write!(f, "\n{:width$}", "", width = 2 * level),
+        None => Ok(()),
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} in JSON at byte {}", message, self.position),
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(
+            self.bytes.get(self.position),
+            Some(b' ' | b'\t' | b'\n' | b'\r')
+        ) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> io::Result<()> {
+        match self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            true => {
+                self.position += literal.len();
+                Ok(())
+            }
+            false => Err(self.error(&format!("expected {}", literal))),
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.nested(Parser::array),
+            Some(b'{') => self.nested(Parser::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> io::Result<Json>) -> io::Result<Json> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.position;
+        while matches!(
+            self.bytes.get(self.position),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while !matches!(self.bytes.get(self.position), Some(b'"' | b'\\') | None) {
+                self.position += 1;
+            }
+            string.push_str(
+                std::str::from_utf8(&self.bytes[start..self.position])
+                    .map_err(|_| self.error("invalid UTF-8"))?,
+            );
+
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    let escape = *self
+                        .bytes
+                        .get(self.position + 1)
+                        .ok_or_else(|| self.error("unexpected end"))?;
+                    self.position += 2;
+                    match escape {
+                        b'"' => string.push('"'),
+                        b'\\' => string.push('\\'),
+                        b'/' => string.push('/'),
+                        b'b' => string.push('\u{8}'),
+                        b'f' => string.push('\u{c}'),
+                        b'n' => string.push('\n'),
+                        b'r' => string.push('\r'),
+                        b't' => string.push('\t'),
+                        b'u' => string.push(self.unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // The four hex digits after \u, combining a surrogate pair if needed.
+    fn unicode_escape(&mut self) -> io::Result<char> {
+        let high = self.hex_digits()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                self.expect("\\u")?;
+                let low = self.hex_digits()?;
+                0x10000 + ((high - 0xd800) << 10) + low.wrapping_sub(0xdc00)
+            }
+            _ => high,
+        };
+        Ok(char::from_u32(code).unwrap_or('\u{fffd}'))
+    }
+
+    fn hex_digits(&mut self) -> io::Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn array(&mut self) -> io::Result<Json> {
+        self.expect("[")?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected , or ]")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Json> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected , or }")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let json = Json::parse(
+            r#" {"name": "tri\"angle\u00e9\ud83d\ude00", "values": [1, -2.5e1, true, null],
+                "nested": {"empty": [], "also": {}}} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some("tri\"angleé😀"),
+            json.get("name").and_then(Json::as_str)
+        );
+        let values = json.get("values").and_then(Json::as_array).unwrap();
+        assert_eq!(Some(1), values[0].as_usize());
+        assert_eq!(Some(-25.0), values[1].as_f64());
+        assert_eq!(Some(true), values[2].as_bool());
+        assert_eq!(Json::Null, values[3]);
+        assert_eq!(
+            Some(&Json::Object(Vec::new())),
+            json.get("nested").and_then(|n| n.get("also"))
+        );
+
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{\"a\": 1} x").is_err());
+        assert!(Json::parse(&"[".repeat(200_000)).is_err());
+        let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(Json::parse(&deep).is_ok());
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let json = Json::Object(vec![
+            (
+                "name".to_string(),
+                Json::String("a \"quoted\"\n\u{1}é".to_string()),
+            ),
+            (
+                "values".to_string(),
+                Json::Array(vec![
+                    Json::Number(0.1),
+                    Json::Number(-1e-300),
+                    Json::Number(f64::INFINITY),
+                    Json::Bool(false),
+                    Json::Null,
+                ]),
+            ),
+            (
+                "nested".to_string(),
+                Json::Array(vec![Json::Object(Vec::new()), Json::Array(Vec::new())]),
+            ),
+        ]);
+
+        for text in &[format!("{}", json), format!("{:#}", json)] {
+            assert_eq!(json, Json::parse(text).unwrap());
+        }
+        assert_eq!(
+            "{\n  \"values\": [1, 2.5],\n  \"empty\": {}\n}",
+            format!(
+                "{:#}",
+                Json::Object(vec![
+                    (
+                        "values".to_string(),
+                        Json::Array(vec![Json::Number(1.0), Json::Number(2.5)])
+                    ),
+                    ("empty".to_string(), Json::Object(Vec::new())),
+                ])
+            )
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use crate::pnm::GreyImage;
+use crate::ray::Ray;
+use crate::utils::{random_double, randon_unit_disk};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    Polygon(u32, f64),
+    Mask(Box<ApertureMask>),
+}
+
+impl Aperture {
+    // An n-bladed iris, with the first blade rotated by `rotation` degrees.
+    pub fn polygon(blades: u32, rotation: f64) -> Self {
+        Aperture::Polygon(blades.max(3), rotation.to_radians())
+    }
+
+    // The mask is stretched over the unit square around the lens centre and
+    // its brightness is the transmittance at each point.
+    pub fn mask<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Aperture::from_image(GreyImage::read(path)?)
+    }
+
+    // Masks must let some light through for the lens to be sampled at all.
+    pub fn from_image(image: GreyImage) -> io::Result<Self> {
+        let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        if image.values().is_empty() {
+            return invalid("aperture mask is empty");
+        }
+        if !image.values().iter().any(|value| *value > 0.0) {
+            return invalid("aperture mask is completely opaque");
+        }
+        Ok(Aperture::Mask(Box::new(ApertureMask::new(image))))
+    }
+
+    // A point on the aperture in lens-radius units, with z = 0.
+    pub fn sample(&self) -> Vec3<f64> {
+        match self {
+            Aperture::Circular => randon_unit_disk(),
+            Aperture::Polygon(blades, rotation) => {
+                let segment = 2.0 * PI / *blades as f64;
+                let blade = ((random_double() * *blades as f64) as u32).min(blades - 1);
+                let start = rotation + blade as f64 * segment;
+
+                let (mut a, mut b) = (random_double(), random_double());
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                Vec3::new(
+                    a * start.cos() + b * (start + segment).cos(),
+                    a * start.sin() + b * (start + segment).sin(),
+                    0.0,
+                )
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+// A greyscale aperture mask along with the cumulative distributions of its
+// transmittance down the rows and along each row, so points can be drawn in
+// proportion to it without rejecting any, however little of it is open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApertureMask {
+    image: GreyImage,
+    rows: Vec<f64>,
+    columns: Vec<f64>,
+}
+
+impl ApertureMask {
+    // The mask must let some light through, as `Aperture::from_image` checks.
+    fn new(image: GreyImage) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let mut rows = Vec::with_capacity(height);
+        let mut columns = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+        for row in 0..height {
+            let mut along = 0.0;
+            for column in 0..width {
+                let value = image.get(column, row);
+                along += if value > 0.0 { value.min(1.0) } else { 0.0 };
+                columns.push(along);
+            }
+            total += along;
+            rows.push(total);
+        }
+        ApertureMask {
+            image,
+            rows,
+            columns,
+        }
+    }
+
+    pub fn image(&self) -> &GreyImage {
+        &self.image
+    }
+
+    fn sample(&self) -> Vec3<f64> {
+        let (width, height) = (self.image.width(), self.image.height());
+        let pick = |cdf: &[f64]| {
+            let target = random_double() * cdf[cdf.len() - 1];
+            cdf.partition_point(|total| *total <= target)
+                .min(cdf.len() - 1)
+        };
+        let row = pick(&self.rows);
+        let column = pick(&self.columns[row * width..(row + 1) * width]);
+
+        Vec3::new(
+            2.0 * (column as f64 + random_double()) / width as f64 - 1.0,
+            1.0 - 2.0 * (row as f64 + random_double()) / height as f64,
+            0.0,
+        )
+    }
+}
+
+// Brown-Conrady distortion with radial (k1, k2) and tangential (p1, p2)
+// terms, applied from image to scene positions in units of half the image
+// height. Positive k1 pulls more of the scene into the frame edges, giving
+// barrel distortion; negative k1 gives pincushion.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Distortion {
+    k1: f64,
+    k2: f64,
+    p1: f64,
+    p2: f64,
+}
+
+impl Distortion {
+    pub fn new(k1: f64, k2: f64, p1: f64, p2: f64) -> Self {
+        Distortion { k1, k2, p1, p2 }
+    }
+
+    pub fn radial(k1: f64, k2: f64) -> Self {
+        Distortion::new(k1, k2, 0.0, 0.0)
+    }
+
+    // k1, k2, p1 and p2 in the order `new` takes them.
+    pub fn coefficients(&self) -> [f64; 4] {
+        [self.k1, self.k2, self.p1, self.p2]
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+}
+
+// One surface of a lens prescription as listed in lens patents, front
+// (scene side) first: the radius of curvature, positive when its centre lies
+// towards the film and zero for the aperture stop; the axial distance to the
+// next surface; the index of refraction behind the surface, with zero meaning
+// air; and the clear aperture diameter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LensElement {
+    radius: f64,
+    thickness: f64,
+    ior: f64,
+    aperture: f64,
+}
+
+impl LensElement {
+    pub fn new(radius: f64, thickness: f64, ior: f64, aperture: f64) -> Self {
+        LensElement {
+            radius,
+            thickness,
+            ior,
+            aperture,
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn thickness(&self) -> f64 {
+        self.thickness
+    }
+
+    pub fn ior(&self) -> f64 {
+        if self.ior == 0.0 {
+            1.0
+        } else {
+            self.ior
+        }
+    }
+
+    pub fn aperture(&self) -> f64 {
+        self.aperture
+    }
+}
+
+// A stack of spherical lens elements in scene units. Rays are traced in lens
+// space, with the film at z = 0, the optical axis along +z towards the scene
+// and the rear vertex `film_distance` in front of the film.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Self {
+        LensSystem { elements }
+    }
+
+    // Prescription files have one surface per line as
+    // `radius thickness ior aperture`, in millimetres, with `#` comments.
+    // Scene units are taken to be metres.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        LensSystem::from_prescription(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_prescription(text: &str) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut elements = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| invalid("invalid number in lens prescription"))?;
+            match values[..] {
+                [radius, thickness, ior, aperture] => elements.push(LensElement::new(
+                    radius / 1000.0,
+                    thickness / 1000.0,
+                    ior,
+                    aperture / 1000.0,
+                )),
+                _ => {
+                    return Err(invalid(
+                        "lens surfaces need radius, thickness, ior and aperture",
+                    ))
+                }
+            }
+        }
+
+        if elements.is_empty() {
+            return Err(invalid("lens prescription has no surfaces"));
+        }
+        Ok(LensSystem::new(elements))
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    pub fn rear_element(&self) -> &LensElement {
+        &self.elements[self.elements.len() - 1]
+    }
+
+    // Distance from the rear vertex to the front vertex.
+    pub fn length(&self) -> f64 {
+        self.elements[..self.elements.len() - 1]
+            .iter()
+            .map(|element| element.thickness)
+            .sum()
+    }
+
+    // Follows a ray through every surface, from the film out into the scene
+    // or the other way, returning None if it is blocked by an aperture or
+    // totally internally reflected.
+    pub fn trace(&self, film_distance: f64, ray: &Ray, from_film: bool) -> Option<Ray> {
+        let mut origin = ray.origin();
+        let mut direction = ray.direction().unit();
+        let count = self.elements.len();
+        let mut vertex = match from_film {
+            true => film_distance,
+            false => film_distance + self.length(),
+        };
+
+        for step in 0..count {
+            let index = match from_film {
+                true => count - 1 - step,
+                false => step,
+            };
+            let element = &self.elements[index];
+            if from_film && step > 0 {
+                vertex += element.thickness;
+            }
+            if !from_film && step > 0 {
+                vertex -= self.elements[index - 1].thickness;
+            }
+
+            let (point, normal) = if element.radius == 0.0 {
+                let t = (vertex - origin.z()) / direction.z();
+                (origin + t * direction, Vec3::new(0.0, 0.0, 1.0))
+            } else {
+                let centre = Vec3::new(0.0, 0.0, vertex - element.radius);
+                let t = intersect_surface(origin, direction, centre, element.radius)?;
+                let point = origin + t * direction;
+                (point, (point - centre) / element.radius)
+            };
+
+            let aperture_radius = element.aperture / 2.0;
+            if point.x() * point.x() + point.y() * point.y() > aperture_radius * aperture_radius {
+                return None;
+            }
+
+            if element.radius != 0.0 {
+                let ior_front = match index {
+                    0 => 1.0,
+                    _ => self.elements[index - 1].ior(),
+                };
+                let (from, to) = match from_film {
+                    true => (element.ior(), ior_front),
+                    false => (ior_front, element.ior()),
+                };
+                direction = refract(direction, normal, from / to)?;
+            }
+            origin = point;
+        }
+
+        Some(Ray::new(origin, direction))
+    }
+
+    // The effective focal length, from a ray entering parallel to the axis.
+    pub fn focal_length(&self) -> f64 {
+        let height = self.elements[0].aperture * 0.005;
+        let ray = Ray::new(
+            Vec3::new(height, 0.0, self.length() + 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+
+        match self.trace(0.0, &ray, false) {
+            Some(exit) => height * (exit.direction().z() / exit.direction().x()).abs(),
+            None => f64::NAN,
+        }
+    }
+
+    // The rear vertex to film distance that brings a point on the axis
+    // `focus_distance` in front of the film into focus, for paraxial rays.
+    // Focusing moves the whole lens, so the field of view changes with it.
+    pub fn film_distance(&self, focus_distance: f64) -> f64 {
+        let height = self.elements[0].aperture * 0.005;
+        let mut film_distance = 0.0;
+
+        for _ in 0..20 {
+            let front = film_distance + self.length();
+            let ray = match focus_distance.is_finite() {
+                true => Ray::new(
+                    Vec3::new(0.0, 0.0, focus_distance),
+                    Vec3::new(height, 0.0, front - focus_distance),
+                ),
+                false => Ray::new(
+                    Vec3::new(height, 0.0, front + 1.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                ),
+            };
+
+            let exit = match self.trace(film_distance, &ray, false) {
+                Some(exit) => exit,
+                None => break,
+            };
+            let crossing =
+                exit.origin().z() - exit.origin().x() * exit.direction().z() / exit.direction().x();
+            film_distance -= crossing;
+
+            if crossing.abs() < 1e-12 {
+                break;
+            }
+        }
+
+        film_distance
+    }
+}
+
+// The intersection with the part of the sphere around the surface vertex.
+fn intersect_surface(
+    origin: Vec3<f64>,
+    direction: Vec3<f64>,
+    centre: Vec3<f64>,
+    radius: f64,
+) -> Option<f64> {
+    let oc = origin - centre;
+    let half_b = oc.dot(&direction);
+    let c = oc.length_sqrd() - radius * radius;
+    let discriminant = half_b * half_b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    [-half_b - root, -half_b + root]
+        .iter()
+        .copied()
+        .find(|t| *t > 1e-9 && ((origin + *t * direction).z() - centre.z()) * radius > 0.0)
+}
+
+// Snell refraction of a unit direction, with `normal` in either orientation.
+fn refract(direction: Vec3<f64>, normal: Vec3<f64>, ratio: f64) -> Option<Vec3<f64>> {
+    let normal = match normal.dot(&direction) > 0.0 {
+        true => -normal,
+        false => normal,
+    };
+    let cos_theta = -direction.dot(&normal);
+    let sin2_theta = ratio * ratio * (1.0 - cos_theta * cos_theta);
+    if sin2_theta > 1.0 {
+        return None;
+    }
+
+    Some(ratio * direction + (ratio * cos_theta - (1.0 - sin2_theta).sqrt()) * normal)
+}
+
+#[cfg(test)]
+mod lens_tests {
+    use super::*;
+
+    #[test]
+    fn test_polygon_samples_stay_inside() {
+        let aperture = Aperture::polygon(5, 18.0);
+        let inradius = (PI / 5.0).cos();
+
+        for _ in 0..1000 {
+            let point = aperture.sample();
+            assert!(point.length() <= 1.0 + 1e-12);
+
+            let angle = point.y().atan2(point.x()) - 18f64.to_radians();
+            let segment = 2.0 * PI / 5.0;
+            let offset = angle.rem_euclid(segment) - segment / 2.0;
+            assert!(point.length() * offset.cos() <= inradius + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_mask_samples_fall_where_it_is_open() {
+        // A pinhole: one open pixel in the top right of a 100x100 mask.
+        let mut values = vec![0.0; 100 * 100];
+        values[99] = 0.5;
+        let aperture = Aperture::from_image(GreyImage::new(100, 100, values)).unwrap();
+
+        for _ in 0..1000 {
+            let point = aperture.sample();
+            assert!(point.x() >= 0.98 && point.x() <= 1.0);
+            assert!(point.y() >= 0.98 && point.y() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_distortion_is_identity_at_centre() {
+        let distortion = Distortion::new(0.1, 0.01, 0.001, 0.002);
+        assert_eq!((0.0, 0.0), distortion.apply(0.0, 0.0));
+
+        let (x, y) = Distortion::radial(0.1, 0.0).apply(1.0, 0.0);
+        assert!((x - 1.1).abs() < 1e-12 && y == 0.0);
+    }
+
+    #[test]
+    fn test_double_gauss_prescription() {
+        let lens =
+            LensSystem::from_prescription(include_str!("../lenses/dgauss.50mm.dat")).unwrap();
+        assert_eq!(11, lens.elements().len());
+        assert!((lens.focal_length() - 0.05).abs() < 0.001);
+
+        // Focusing closer moves the lens away from the film.
+        let infinity = lens.film_distance(f64::INFINITY);
+        let near = lens.film_distance(1.0);
+        assert!(infinity > 0.0 && near > infinity);
+    }
+}
@@ -1,7 +1,33 @@
+pub mod aabb;
+pub mod animation;
+pub mod aov;
+pub mod bvh;
 pub mod camera;
+pub mod csg;
+pub mod curve;
+pub mod denoise;
+pub mod exr;
+pub mod framebuffer;
+pub mod gltf;
+pub mod hair;
+pub mod heightfield;
+pub mod json;
+pub mod lens;
 pub mod material;
+pub mod mesh;
 pub mod objects;
+pub mod pbrt;
+pub mod planar;
+pub mod ply;
+pub mod png;
+pub mod pnm;
+pub mod quadrics;
 pub mod ray;
 pub mod scene;
+pub mod scenefile;
+pub mod sdf;
+pub mod stl;
+pub mod subdivision;
+pub mod transform;
 pub mod utils;
 pub mod vec3;
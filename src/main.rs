@@ -1,128 +1,1123 @@
 use indicatif::{ParallelProgressIterator, ProgressBar};
 use rayon::iter::ParallelIterator;
 use rayon::prelude::*;
-use rust_rt::camera::Camera;
+use rust_rt::animation::{CameraAnimation, CameraKey, Interpolation};
+use rust_rt::aov::{light_layer, Aov, AovBuffer, Channels};
+use rust_rt::camera::{
+    Camera, Cameras, Cubemap, Equirectangular, Fisheye, FisheyeMapping, Ods, Orthographic,
+    PhotographicSettings, Realistic, Stereo, StereoConvergence, StereoLayout, Viewport,
+};
+use rust_rt::denoise::Denoiser;
+use rust_rt::exr::ExrImage;
+use rust_rt::framebuffer::FrameBuffer;
+use rust_rt::lens::{Aperture, Distortion, LensSystem};
 use rust_rt::material::{Dielectric, Lambertian, Materials, Metal};
 use rust_rt::objects::{Object, SceneObjects, Sphere};
-use rust_rt::scene::Scene;
-use rust_rt::vec3::{Colour, Point3D, Transpose, Vec3};
-use rand::Rng;
+use rust_rt::planar::Plane;
+use rust_rt::png;
+use rust_rt::scene::{CropWindow, Scene};
+use rust_rt::scenefile;
+use rust_rt::utils::{fnv1a, random_double, random_range, seed_sampler};
+use rust_rt::vec3::{Colour, Point3D, Vec3};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
 
-fn write_render(rendered_scene: &Vec<Colour>, samples_per_pxl: i16) {
-    for render in rendered_scene {
-        println!("{:}", render.write_colour(samples_per_pxl));
+const SCENE_SEED: u64 = 0x5eed;
+const ASPECT_RATIO: f64 = 3.0 / 2.0;
+const IMG_WIDTH: i32 = 1200;
+const IMG_HEIGHT: i32 = (IMG_WIDTH as f64 / ASPECT_RATIO) as i32;
+const SAMPLES_PER_PIXEL: i16 = 500;
+const MAX_DEPTH: i16 = 50;
+
+#[derive(Clone)]
+struct Options {
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: u64,
+    resume: Option<PathBuf>,
+    aov_exr: Option<PathBuf>,
+    aov_dir: Option<PathBuf>,
+    aovs: Vec<Aov>,
+    denoise: bool,
+    orthographic: Option<f64>,
+    projection: String,
+    fov: Option<f64>,
+    interocular: f64,
+    convergence: Option<f64>,
+    stereo_layout: StereoLayout,
+    sensor: (f64, f64),
+    focal_length: Option<f64>,
+    f_stop: f64,
+    shutter_speed: f64,
+    iso: f64,
+    aperture: Aperture,
+    cat_eye: f64,
+    chromatic_aberration: f64,
+    distortion: Distortion,
+    lens: Option<LensSystem>,
+    shift: (f64, f64),
+    tilt: (f64, f64),
+    animation: Option<CameraAnimation>,
+    frames: Option<(u32, u32)>,
+    frame_dir: PathBuf,
+    crop: CropWindow,
+    crop_full: bool,
+    focus_dist: Option<f64>,
+    autofocus: Option<(f64, f64)>,
+    focus_object: Option<String>,
+    scene: Option<PathBuf>,
+    save_scene: Option<PathBuf>,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: String) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("invalid value for {}: {}", flag, value);
+        process::exit(2);
+    })
+}
+
+// Distances like the convergence distance, which must lie in front of the
+// camera.
+fn parse_distance(flag: &str, value: String) -> f64 {
+    let distance: f64 = parse_value(flag, value);
+    if distance <= 0.0 || distance.is_nan() {
+        eprintln!("{} must be greater than 0, got {}", flag, distance);
+        process::exit(2);
     }
+    distance
 }
 
-fn random_scene() -> SceneObjects {
+fn parse_options(args: Vec<String>) -> Options {
+    let mut options = Options {
+        checkpoint: None,
+        checkpoint_interval: 50,
+        resume: None,
+        aov_exr: None,
+        aov_dir: None,
+        aovs: Aov::ALL.to_vec(),
+        denoise: false,
+        orthographic: None,
+        projection: "perspective".to_string(),
+        fov: None,
+        interocular: 0.065,
+        convergence: None,
+        stereo_layout: StereoLayout::SideBySide,
+        sensor: (36.0, 24.0),
+        focal_length: None,
+        f_stop: 8.0,
+        shutter_speed: 1.0 / 125.0,
+        iso: 100.0,
+        aperture: Aperture::Circular,
+        cat_eye: 0.0,
+        chromatic_aberration: 0.0,
+        distortion: Distortion::default(),
+        lens: None,
+        shift: (0.0, 0.0),
+        tilt: (0.0, 0.0),
+        animation: None,
+        frames: None,
+        frame_dir: PathBuf::from("."),
+        crop: CropWindow::full(IMG_WIDTH, IMG_HEIGHT),
+        crop_full: false,
+        focus_dist: None,
+        autofocus: None,
+        focus_object: None,
+        scene: None,
+        save_scene: None,
+    };
+    let mut keys = Vec::new();
+    let mut interpolation = Interpolation::CatmullRom;
+    let mut aperture_blades = None;
+    let mut aperture_rotation = 0.0;
+    let mut aperture_mask = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().unwrap_or_else(|| {
+                eprintln!("missing value for {}", arg);
+                process::exit(2);
+            })
+        };
+
+        match arg.as_str() {
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value())),
+            "--checkpoint-interval" => options.checkpoint_interval = parse_value(&arg, value()),
+            "--resume" => options.resume = Some(PathBuf::from(value())),
+            "--aovs" => options.aov_exr = Some(PathBuf::from(value())),
+            "--aov-dir" => options.aov_dir = Some(PathBuf::from(value())),
+            "--aov-list" => {
+                options.aovs = value()
+                    .split(',')
+                    .map(|name| {
+                        Aov::from_name(name.trim()).unwrap_or_else(|| {
+                            eprintln!("unknown AOV: {}", name);
+                            process::exit(2);
+                        })
+                    })
+                    .collect()
+            }
+            "--denoise" => options.denoise = true,
+            "--orthographic" => options.orthographic = Some(parse_value(&arg, value())),
+            "--projection" => options.projection = value(),
+            "--fov" => options.fov = Some(parse_value(&arg, value())),
+            "--interocular" => options.interocular = parse_value(&arg, value()),
+            "--convergence" => options.convergence = Some(parse_distance(&arg, value())),
+            "--sensor" => {
+                let value = value();
+                options.sensor = match value.split_once('x') {
+                    Some((width, height)) => (
+                        parse_value(&arg, width.to_string()),
+                        parse_value(&arg, height.to_string()),
+                    ),
+                    None => {
+                        eprintln!("--sensor expects <width>x<height> in mm, got {}", value);
+                        process::exit(2);
+                    }
+                }
+            }
+            "--focal-length" => options.focal_length = Some(parse_value(&arg, value())),
+            "--f-stop" => options.f_stop = parse_value(&arg, value()),
+            "--shutter" => {
+                let value = value();
+                options.shutter_speed = match value.split_once('/') {
+                    Some((numerator, denominator)) => {
+                        parse_value::<f64>(&arg, numerator.to_string())
+                            / parse_value::<f64>(&arg, denominator.to_string())
+                    }
+                    None => parse_value(&arg, value),
+                }
+            }
+            "--iso" => options.iso = parse_value(&arg, value()),
+            "--aperture-blades" => aperture_blades = Some(parse_value(&arg, value())),
+            "--aperture-rotation" => aperture_rotation = parse_value(&arg, value()),
+            "--aperture-mask" => aperture_mask = Some(PathBuf::from(value())),
+            "--lens" => {
+                let path = PathBuf::from(value());
+                options.lens = Some(LensSystem::read(&path).unwrap_or_else(|err| {
+                    eprintln!("failed to read lens {}: {}", path.display(), err);
+                    process::exit(1);
+                }))
+            }
+            "--shift" | "--tilt" => {
+                let value = value();
+                let mut terms = value
+                    .split(',')
+                    .map(|term| parse_value::<f64>(&arg, term.trim().to_string()));
+                let pair = (terms.next().unwrap_or(0.0), terms.next().unwrap_or(0.0));
+                match arg.as_str() {
+                    "--shift" => options.shift = pair,
+                    _ => options.tilt = pair,
+                }
+            }
+            "--keyframe" => keys.push(value()),
+            "--interpolation" => {
+                let value = value();
+                interpolation = Interpolation::from_name(&value).unwrap_or_else(|| {
+                    eprintln!("unknown interpolation: {}", value);
+                    process::exit(2);
+                })
+            }
+            "--frames" => {
+                let value = value();
+                options.frames = match value.split_once('-') {
+                    Some((first, last)) => Some((
+                        parse_value(&arg, first.to_string()),
+                        parse_value(&arg, last.to_string()),
+                    )),
+                    None => {
+                        eprintln!("--frames expects <first>-<last>, got {}", value);
+                        process::exit(2);
+                    }
+                }
+            }
+            "--frame-dir" => options.frame_dir = PathBuf::from(value()),
+            "--crop" | "--crop-normalised" => {
+                let value = value();
+                let terms: Vec<f64> = value
+                    .split(',')
+                    .map(|term| parse_value(&arg, term.trim().to_string()))
+                    .collect();
+                options.crop = match (arg.as_str(), &terms[..]) {
+                    ("--crop", [x, y, width, height]) => {
+                        CropWindow::new(*x as i32, *y as i32, *width as i32, *height as i32)
+                    }
+                    (_, [x0, y0, x1, y1]) => {
+                        CropWindow::from_normalised(*x0, *y0, *x1, *y1, IMG_WIDTH, IMG_HEIGHT)
+                    }
+                    _ => {
+                        eprintln!("{} expects four comma-separated values, got {}", arg, value);
+                        process::exit(2);
+                    }
+                }
+            }
+            "--crop-full" => options.crop_full = true,
+            "--focus-dist" => options.focus_dist = Some(parse_distance(&arg, value())),
+            "--autofocus" => {
+                let value = value();
+                options.autofocus = match value.split_once(',') {
+                    Some((x, y)) => {
+                        let x: f64 = parse_value(&arg, x.trim().to_string());
+                        let y: f64 = parse_value(&arg, y.trim().to_string());
+                        Some((
+                            (x + 0.5) / IMG_WIDTH as f64,
+                            1.0 - (y + 0.5) / IMG_HEIGHT as f64,
+                        ))
+                    }
+                    None if value == "centre" || value == "center" => Some((0.5, 0.5)),
+                    None => {
+                        eprintln!(
+                            "--autofocus expects centre or <x>,<y> in pixels, got {}",
+                            value
+                        );
+                        process::exit(2);
+                    }
+                }
+            }
+            "--focus-object" => options.focus_object = Some(value()),
+            "--cat-eye" => options.cat_eye = parse_value(&arg, value()),
+            "--chromatic-aberration" => options.chromatic_aberration = parse_value(&arg, value()),
+            "--distortion" => {
+                let value = value();
+                let terms: Vec<f64> = value
+                    .split(',')
+                    .map(|term| parse_value(&arg, term.trim().to_string()))
+                    .collect();
+                options.distortion = match terms[..] {
+                    [k1] => Distortion::radial(k1, 0.0),
+                    [k1, k2] => Distortion::radial(k1, k2),
+                    [k1, k2, p1, p2] => Distortion::new(k1, k2, p1, p2),
+                    _ => {
+                        eprintln!("--distortion expects k1[,k2[,p1,p2]], got {}", value);
+                        process::exit(2);
+                    }
+                }
+            }
+            "--scene" => options.scene = Some(PathBuf::from(value())),
+            "--save-scene" => options.save_scene = Some(PathBuf::from(value())),
+            "--stereo-layout" => {
+                options.stereo_layout = match value().as_str() {
+                    "side-by-side" => StereoLayout::SideBySide,
+                    "top-bottom" => StereoLayout::TopBottom,
+                    layout => {
+                        eprintln!("unknown stereo layout: {}", layout);
+                        process::exit(2);
+                    }
+                }
+            }
+            _ => {
+                eprintln!("unknown argument: {}", arg);
+                process::exit(2);
+            }
+        }
+    }
+
+    options.aperture = match (aperture_mask, aperture_blades) {
+        (Some(path), _) => Aperture::mask(&path).unwrap_or_else(|err| {
+            eprintln!("failed to read aperture mask {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        (None, Some(blades)) => Aperture::polygon(blades, aperture_rotation),
+        (None, None) => Aperture::Circular,
+    };
+
+    if !keys.is_empty() {
+        let animation = keys
+            .iter()
+            .fold(CameraAnimation::new(interpolation), |animation, key| {
+                animation.add_key(parse_keyframe(key, options.fov))
+            });
+        options.animation = Some(animation);
+        // Sequences pick up where they left off by skipping finished frames.
+        if options.checkpoint.is_some() || options.resume.is_some() {
+            eprintln!("--checkpoint and --resume can't be used with --keyframe");
+            process::exit(2);
+        }
+    }
+
+    options.crop = options.crop.clipped(IMG_WIDTH, IMG_HEIGHT);
+    if options.crop.width() == 0 || options.crop.height() == 0 {
+        eprintln!("the crop window lies outside the image");
+        process::exit(2);
+    }
+
+    if options.lens.is_some()
+        && (options.orthographic.is_some() || options.projection != "perspective")
+    {
+        eprintln!("--lens can't be combined with --projection or --orthographic");
+        process::exit(2);
+    }
+
+    let photographic =
+        options.projection == "perspective" || options.projection.starts_with("stereo");
+    if options.focal_length.is_some() && options.fov.is_some() && photographic {
+        eprintln!("--fov is ignored, as the field of view comes from --focal-length");
+    }
+
+    options.checkpoint_interval = options.checkpoint_interval.max(1);
+    if options.checkpoint.is_none() {
+        options.checkpoint = options.resume.clone();
+    }
+
+    if options.aov_exr.is_none() && options.aov_dir.is_none() {
+        options.aovs.clear();
+    }
+    if options.denoise {
+        for guide in &[Aov::Albedo, Aov::Normal] {
+            if !options.aovs.contains(guide) {
+                options.aovs.push(*guide);
+            }
+        }
+    }
+
+    options
+}
+
+// Keyframes are `frame:x,y,z:x,y,z[:fov[:focus]]` giving the camera position
+// and target. The field of view defaults to `--fov` and the focus distance to
+// the distance to the target.
+fn parse_keyframe(value: &str, fov: Option<f64>) -> CameraKey {
+    let invalid = || -> ! {
+        eprintln!(
+            "--keyframe expects frame:x,y,z:x,y,z[:fov[:focus]], got {}",
+            value
+        );
+        process::exit(2);
+    };
+    let point = |value: &str| {
+        let coordinates: Vec<f64> = value
+            .split(',')
+            .map(|coordinate| parse_value("--keyframe", coordinate.trim().to_string()))
+            .collect();
+        match coordinates[..] {
+            [x, y, z] => Point3D::new(x, y, z),
+            _ => invalid(),
+        }
+    };
+
+    let fields: Vec<&str> = value.split(':').collect();
+    if fields.len() < 3 || fields.len() > 5 {
+        invalid();
+    }
+    let origin = point(fields[1]);
+    let target = point(fields[2]);
+    let vertical_fov = match fields.get(3) {
+        Some(fov) => parse_value("--keyframe", fov.to_string()),
+        None => fov.unwrap_or(20.0),
+    };
+    let focus_dist = match fields.get(4) {
+        Some(focus) => parse_value("--keyframe", focus.to_string()),
+        None => (target - origin).length(),
+    };
+
+    CameraKey::new(
+        parse_value("--keyframe", fields[0].to_string()),
+        origin,
+        target,
+        vertical_fov,
+        focus_dist,
+    )
+}
+
+// Display-ready colours for the render, at full size with black outside the
+// crop window if `--crop-full` was given.
+fn output_colours(
+    options: &Options,
+    framebuffer: &FrameBuffer,
+    denoised: Option<&[Colour]>,
+) -> (i32, i32, Vec<Colour>) {
+    let colours: Vec<Colour> = match denoised {
+        Some(denoised) => denoised
+            .iter()
+            .map(|colour| colour.write_colour(1))
+            .collect(),
+        None => framebuffer
+            .pixels()
+            .map(|(sum, samples)| sum.write_colour(samples as i16))
+            .collect(),
+    };
+
+    match options.crop_full {
+        true => (
+            IMG_WIDTH,
+            IMG_HEIGHT,
+            options
+                .crop
+                .expand(IMG_WIDTH, IMG_HEIGHT, &colours, Colour::new(0.0, 0.0, 0.0)),
+        ),
+        false => (framebuffer.width(), framebuffer.height(), colours),
+    }
+}
+
+// Cropped renders are only resumed from checkpoints of the same window.
+fn checkpoint_hash(options: &Options, scene: &Scene) -> u64 {
+    if options.crop.is_full(IMG_WIDTH, IMG_HEIGHT) {
+        return scene.hash();
+    }
+
+    let mut bytes = scene.hash().to_le_bytes().to_vec();
+    bytes.extend_from_slice(&options.crop.x().to_le_bytes());
+    bytes.extend_from_slice(&options.crop.y().to_le_bytes());
+    fnv1a(&bytes)
+}
+
+fn denoise_command(args: Vec<String>) {
+    let mut paths = Vec::new();
+    let mut denoiser = Denoiser::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().unwrap_or_else(|| {
+                eprintln!("missing value for {}", arg);
+                process::exit(2);
+            })
+        };
+
+        // The edge-stopping weights divide by each sigma.
+        let mut sigma = || {
+            let sigma: f64 = parse_value(&arg, value());
+            if !(sigma > 0.0 && sigma.is_finite()) {
+                eprintln!("{} expects a value greater than 0, got {}", arg, sigma);
+                process::exit(2);
+            }
+            sigma
+        };
+
+        match arg.as_str() {
+            "--iterations" => denoiser = denoiser.set_iterations(parse_value(&arg, value())),
+            "--sigma-colour" => denoiser = denoiser.set_sigma_colour(sigma()),
+            "--sigma-normal" => denoiser = denoiser.set_sigma_normal(sigma()),
+            "--sigma-albedo" => denoiser = denoiser.set_sigma_albedo(sigma()),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        eprintln!("usage: rust-rt denoise <input.exr> <output.exr> [--iterations N]");
+        process::exit(2);
+    }
+
+    let mut image = ExrImage::read(&paths[0]).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {}", paths[0].display(), err);
+        process::exit(1);
+    });
+
+    let colour = image.colours(None);
+    let albedo = image.colours(Some(Aov::Albedo.name()));
+    let normal = (
+        image.channel("normal.X"),
+        image.channel("normal.Y"),
+        image.channel("normal.Z"),
+    );
+    let (colour, albedo, normal) = match (colour, albedo, normal) {
+        (Some(colour), Some(albedo), (Some(x), Some(y), Some(z))) => {
+            let normal: Vec<Vec3<f64>> = (0..x.len())
+                .map(|index| Vec3::new(x[index] as f64, y[index] as f64, z[index] as f64))
+                .collect();
+            (colour, albedo, normal)
+        }
+        _ => {
+            eprintln!(
+                "{} needs R, G, B, albedo.* and normal.* channels; render it with --aovs",
+                paths[0].display()
+            );
+            process::exit(1);
+        }
+    };
+
+    let denoised = denoiser.denoise(image.width(), image.height(), &colour, &albedo, &normal);
+    image.add_colours(None, &denoised);
+    if let Err(err) = image.write(&paths[1]) {
+        eprintln!("failed to write {}: {}", paths[1].display(), err);
+        process::exit(1);
+    }
+}
+
+fn write_aovs(
+    options: &Options,
+    framebuffer: &FrameBuffer,
+    aov_buffer: &AovBuffer,
+    denoised: Option<&[Colour]>,
+) {
+    let (width, height) = match options.crop_full {
+        true => (IMG_WIDTH, IMG_HEIGHT),
+        false => (framebuffer.width(), framebuffer.height()),
+    };
+    let expand = |values: Vec<f32>| match options.crop_full {
+        true => options.crop.expand(IMG_WIDTH, IMG_HEIGHT, &values, 0.0),
+        false => values,
+    };
+    let expand_colours = |colours: &[Colour]| match options.crop_full {
+        true => options
+            .crop
+            .expand(IMG_WIDTH, IMG_HEIGHT, colours, Colour::new(0.0, 0.0, 0.0)),
+        false => colours.to_vec(),
+    };
+
+    if let Some(path) = &options.aov_exr {
+        let mut image = ExrImage::new(width, height);
+        image.add_colours(None, &expand_colours(&framebuffer.mean()));
+        if let Some(denoised) = denoised {
+            image.add_colours(Some("denoised"), &expand_colours(denoised));
+        }
+        for (layer, channels) in aov_layers(aov_buffer) {
+            for (channel, values) in channels {
+                image.add_channel(&format!("{}.{}", layer, channel), expand(values));
+            }
+        }
+        if let Err(err) = image.write(path) {
+            eprintln!("failed to write {}: {}", path.display(), err);
+        }
+    }
+
+    if let Some(dir) = &options.aov_dir {
+        for (layer, channels) in aov_layers(aov_buffer) {
+            let mut image = ExrImage::new(width, height);
+            for (channel, values) in channels {
+                image.add_channel(channel, expand(values));
+            }
+
+            let path = dir.join(format!("{}.exr", layer));
+            if let Err(err) = image.write(&path) {
+                eprintln!("failed to write {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+// Each pass's layer name and channels, with the lights pass split into a
+// layer per light.
+fn aov_layers(aov_buffer: &AovBuffer) -> Vec<(String, Channels)> {
+    let mut layers = Vec::new();
+    for aov in aov_buffer.aovs() {
+        match aov {
+            Aov::Lights => {
+                for light in aov_buffer.lights() {
+                    layers.push((light_layer(light), aov_buffer.light_channels(light)));
+                }
+            }
+            _ => layers.push((aov.name().to_string(), aov_buffer.channels(*aov))),
+        }
+    }
+    layers
+}
+
+// The focus distance from `--focus-object`, `--autofocus` or `--focus-dist`,
+// in that order, falling back to `default`. Autofocus picks its pixel through
+// the perspective camera's field of view.
+fn focus_distance(
+    options: &Options,
+    world: &SceneObjects,
+    origin: Point3D,
+    target: Point3D,
+    up: Vec3<f64>,
+    default: f64,
+) -> f64 {
+    let vertical_fov = match options.focal_length {
+        Some(focal_length) => PhotographicSettings::new(
+            options.sensor.0,
+            options.sensor.1,
+            focal_length,
+            options.f_stop,
+            options.shutter_speed,
+            options.iso,
+        )
+        .vertical_fov(ASPECT_RATIO),
+        None => options.fov.unwrap_or(20.0),
+    };
+    let pinhole = Camera::new(ASPECT_RATIO, vertical_fov, origin, target, up, 0.0, default);
+
+    match (&options.focus_object, options.autofocus) {
+        (Some(key), _) => {
+            let object = world
+                .named(key)
+                .or_else(|| key.parse().ok().and_then(|id| world.object(id)));
+            match object {
+                Some(object) => pinhole
+                    .focus_distance_to(object.center())
+                    .unwrap_or_else(|| {
+                        eprintln!("can't focus on {}, as it isn't in front of the camera", key);
+                        process::exit(2);
+                    }),
+                None => {
+                    eprintln!("no object named or numbered {} to focus on", key);
+                    process::exit(2);
+                }
+            }
+        }
+        (None, Some((u, v))) => pinhole.autofocus(world, u, v).unwrap_or_else(|| {
+            eprintln!("autofocus found nothing to focus on, using {}", default);
+            default
+        }),
+        (None, None) => options.focus_dist.unwrap_or(default),
+    }
+}
+
+fn build_camera(
+    options: &Options,
+    aspect_ratio: f64,
+    camera_origin: Point3D,
+    camera_target: Point3D,
+    up: Vec3<f64>,
+    focus_dist: f64,
+) -> Cameras {
+    let perspective = |aspect_ratio: f64| {
+        let camera = match options.focal_length {
+            Some(focal_length) => Camera::from_photographic(
+                &PhotographicSettings::new(
+                    options.sensor.0,
+                    options.sensor.1,
+                    focal_length,
+                    options.f_stop,
+                    options.shutter_speed,
+                    options.iso,
+                ),
+                aspect_ratio,
+                camera_origin,
+                camera_target,
+                up,
+                focus_dist,
+            ),
+            None => Camera::new(
+                aspect_ratio,
+                options.fov.unwrap_or(20.0),
+                camera_origin,
+                camera_target,
+                up,
+                0.1,
+                focus_dist,
+            ),
+        };
+        camera
+            .set_aperture(options.aperture.clone())
+            .set_cat_eye(options.cat_eye)
+            .set_chromatic_aberration(options.chromatic_aberration)
+            .set_distortion(options.distortion)
+            .set_shift(options.shift.0, options.shift.1)
+            .set_tilt(options.tilt.0, options.tilt.1)
+    };
+    let eye_aspect_ratio = match options.stereo_layout {
+        StereoLayout::SideBySide => aspect_ratio / 2.0,
+        StereoLayout::TopBottom => aspect_ratio * 2.0,
+    };
+    let convergence = options.convergence.unwrap_or(focus_dist);
+
+    if let Some(lens) = &options.lens {
+        return Cameras::Realistic(Realistic::new(
+            lens.clone(),
+            Viewport::new(options.sensor.0 / 1000.0, options.sensor.1 / 1000.0).fit(aspect_ratio),
+            focus_dist,
+            camera_origin,
+            camera_target,
+            up,
+        ));
+    }
 
+    match (options.orthographic, options.projection.as_str()) {
+        (Some(view_height), _) => Cameras::Orthographic(Orthographic::new(
+            aspect_ratio,
+            view_height,
+            camera_origin,
+            camera_target,
+            up,
+        )),
+        (None, "perspective") => Cameras::Perspective(perspective(aspect_ratio)),
+        (None, "equirectangular") => {
+            Cameras::Equirectangular(Equirectangular::new(camera_origin, camera_target, up))
+        }
+        (None, "fisheye-equidistant") | (None, "fisheye-equisolid") => {
+            let mapping = match options.projection.as_str() {
+                "fisheye-equidistant" => FisheyeMapping::Equidistant,
+                _ => FisheyeMapping::Equisolid,
+            };
+            Cameras::Fisheye(Fisheye::new(
+                aspect_ratio,
+                options.fov.unwrap_or(180.0),
+                mapping,
+                camera_origin,
+                camera_target,
+                up,
+            ))
+        }
+        (None, "cubemap") => Cameras::Cubemap(Cubemap::new(camera_origin, camera_target, up)),
+        (None, "stereo-toe-in") => Cameras::Stereo(Stereo::new(
+            perspective(eye_aspect_ratio),
+            options.interocular,
+            StereoConvergence::ToeIn(convergence),
+            options.stereo_layout,
+        )),
+        (None, "stereo-off-axis") => Cameras::Stereo(Stereo::new(
+            perspective(eye_aspect_ratio),
+            options.interocular,
+            StereoConvergence::OffAxis(convergence),
+            options.stereo_layout,
+        )),
+        (None, "ods") => Cameras::Ods(Ods::new(
+            camera_origin,
+            camera_target,
+            up,
+            options.interocular,
+            options.stereo_layout,
+        )),
+        (None, projection) => {
+            eprintln!("unknown projection: {}", projection);
+            process::exit(2);
+        }
+    }
+}
+
+fn random_scene() -> SceneObjects {
     let mut world = SceneObjects::new();
     let material_ground = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
-    world.add(Object::Sphere(Sphere::new(
-        Point3D::new(0.0, -1000.0, 0.0),
-        1000.0,
-        material_ground,
-    )));
-
-    let mut rng = rand::thread_rng();
+    world.add_named(
+        "ground",
+        Object::Plane(Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material_ground,
+        )),
+    );
 
     let ref_point = Point3D::new(4.0, 0.2, 0.0);
 
     for a in -11..11 {
         for b in -11..11 {
+            let choose_mat = random_double();
 
-            let choose_mat = rng.gen::<f64>();
-
-            let center = Point3D::new(a as f64 + 0.9*rng.gen::<f64>(), 0.2,  b as f64 +0.9*rng.gen::<f64>());
+            let center = Point3D::new(
+                a as f64 + 0.9 * random_double(),
+                0.2,
+                b as f64 + 0.9 * random_double(),
+            );
 
             if (center - ref_point).length() > 0.9 {
-                let mat = if choose_mat <  0.8 {
+                let mat = if choose_mat < 0.8 {
                     let albedo = Vec3::random() * Vec3::random();
                     Materials::Lambertian(Lambertian::new(albedo))
-                }
-                else if choose_mat < 0.95 {
+                } else if choose_mat < 0.95 {
                     let albedo = Vec3::random_from_range(0.5, 1.0);
-                    let fuzz = rng.gen_range(0.0..0.5);
+                    let fuzz = random_range(0.0, 0.5);
 
                     Materials::Metal(Metal::new(albedo, fuzz))
-                }
-                else {
+                } else {
                     Materials::Dielectric(Dielectric::new(1.5))
                 };
 
-                world.add(Object::Sphere(Sphere::new(
-                    center,
-                    0.2,
-                    mat,
-                )));
+                world.add(Object::Sphere(Sphere::new(center, 0.2, mat)));
             }
         }
     }
 
     let mat = Materials::Dielectric(Dielectric::new(1.5));
-    world.add(Object::Sphere(Sphere::new(
-        Point3D::new(0.0, 1.0, 0.0),
-        1.0,
-        mat,
-    )));
+    world.add_named(
+        "glass",
+        Object::Sphere(Sphere::new(Point3D::new(0.0, 1.0, 0.0), 1.0, mat)),
+    );
 
     let mat2 = Materials::Lambertian(Lambertian::new(Colour::new(0.4, 0.2, 0.1)));
-    world.add(Object::Sphere(Sphere::new(
-        Point3D::new(-4.0, 1.0, 0.0),
-        1.0,
-        mat2,
-    )));
+    world.add_named(
+        "matte",
+        Object::Sphere(Sphere::new(Point3D::new(-4.0, 1.0, 0.0), 1.0, mat2)),
+    );
 
     let mat2 = Materials::Metal(Metal::new(Colour::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Object::Sphere(Sphere::new(
-        Point3D::new(4.0, 1.0, 0.0),
-        1.0,
-        mat2,
-    )));
-
+    world.add_named(
+        "metal",
+        Object::Sphere(Sphere::new(Point3D::new(4.0, 1.0, 0.0), 1.0, mat2)),
+    );
 
     world
-
 }
 
-fn main() {
-    const ASPECT_RATIO: f64 = 3.0 / 2.0;
-    const IMG_WIDTH: i32 = 1200;
-    const IMG_HEIGHT: i32 = (IMG_WIDTH as f64 / ASPECT_RATIO) as i32;
-    const SAMPLES_PER_PIXEL: i16 = 500;
-    const MAX_DEPTH: i16 = 50;
-
+// The generated spheres, seen through the camera the options describe. Scenes
+// read with `--scene` bring their own camera instead.
+fn default_scene(options: &Options) -> Scene {
+    seed_sampler(SCENE_SEED);
     let world = random_scene();
 
     let camera_origin = Point3D::new(13.0, 2.0, 3.0);
     let camera_target = Point3D::new(0.0, 0.0, 0.0);
-    let focus_dist = 10.0;
+    let up = Point3D::new(0.0, 1.0, 0.0);
+    let focus_dist = focus_distance(options, &world, camera_origin, camera_target, up, 10.0);
+
+    let camera = build_camera(
+        options,
+        ASPECT_RATIO,
+        camera_origin,
+        camera_target,
+        up,
+        focus_dist,
+    );
+    Scene::new(world, camera)
+}
 
-    let scene = Scene::new(
-        world,
-        Camera::new(
+fn render(
+    options: &Options,
+    scene: &Scene,
+    mut framebuffer: FrameBuffer,
+    mut aov_buffer: AovBuffer,
+    checkpoint: Option<&PathBuf>,
+) -> (FrameBuffer, AovBuffer) {
+    let target_samples = SAMPLES_PER_PIXEL as u64;
+    let bar = ProgressBar::new(target_samples);
+    bar.set_position(framebuffer.passes().min(target_samples));
+
+    while framebuffer.passes() < target_samples {
+        let start = framebuffer.passes();
+        let end = (start + options.checkpoint_interval).min(target_samples);
+
+        let (batch, aov_batch) = (start..end)
+            .into_par_iter()
+            .progress_with(bar.clone())
+            .map(|sample| {
+                scene.render_sample_crop_aovs(
+                    sample,
+                    &options.crop,
+                    MAX_DEPTH,
+                    IMG_WIDTH,
+                    IMG_HEIGHT,
+                )
+            })
+            .fold(
+                || (framebuffer.blank(), aov_buffer.blank()),
+                |(mut acc, mut aov_acc), pass| {
+                    let colours: Vec<Colour> = pass.iter().map(|sample| sample.colour()).collect();
+                    acc.accumulate(&colours);
+                    aov_acc.accumulate(&pass);
+                    (acc, aov_acc)
+                },
+            )
+            .reduce(
+                || (framebuffer.blank(), aov_buffer.blank()),
+                |(mut acc, mut aov_acc), (other, aov_other)| {
+                    acc.merge(&other);
+                    aov_acc.merge(&aov_other);
+                    (acc, aov_acc)
+                },
+            );
+        framebuffer.merge(&batch);
+        aov_buffer.merge(&aov_batch);
+
+        if let Some(path) = checkpoint {
+            if let Err(err) = framebuffer.save_with_aovs(path, Some(&aov_buffer)) {
+                eprintln!("failed to write checkpoint {}: {}", path.display(), err);
+            }
+        }
+    }
+    bar.finish();
+
+    (framebuffer, aov_buffer)
+}
+
+fn denoise_render(
+    options: &Options,
+    framebuffer: &FrameBuffer,
+    aov_buffer: &AovBuffer,
+) -> Option<Vec<Colour>> {
+    match (
+        options.denoise,
+        aov_buffer.vectors(Aov::Albedo),
+        aov_buffer.vectors(Aov::Normal),
+    ) {
+        (true, Some(albedo), Some(normal)) => Some(Denoiser::new().denoise(
+            framebuffer.width(),
+            framebuffer.height(),
+            &framebuffer.mean(),
+            &albedo,
+            &normal,
+        )),
+        _ => None,
+    }
+}
+
+// Renders every frame of the camera animation to a numbered PNG, skipping
+// frames that are already on disk so an interrupted sequence can be re-run.
+// AOVs are written for each frame too: `--aovs passes.exr` gives
+// `passes_0001.exr` and so on, and `--aov-dir passes` gives a directory per
+// frame, `passes/frame_0001`.
+fn render_sequence(options: &Options, animation: &CameraAnimation, mut scene: Scene) {
+    let keys = animation.keys();
+    let (first, last) = options.frames.unwrap_or((
+        keys[0].frame().round() as u32,
+        keys[keys.len() - 1].frame().round() as u32,
+    ));
+
+    for frame in first..=last {
+        let path = options.frame_dir.join(format!("frame_{:04}.png", frame));
+        if path.exists() {
+            eprintln!("skipping {}, already rendered", path.display());
+            continue;
+        }
+
+        let key = animation.at(frame as f64).unwrap();
+        let mut frame_options = options.clone();
+        frame_options.fov = Some(key.vertical_fov());
+        frame_options.aov_exr = options.aov_exr.as_ref().map(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{}_{:04}.exr", stem, frame))
+        });
+        frame_options.aov_dir = options.aov_dir.as_ref().map(|dir| {
+            let dir = dir.join(format!("frame_{:04}", frame));
+            if let Err(err) = fs::create_dir_all(&dir) {
+                eprintln!("failed to create {}: {}", dir.display(), err);
+                process::exit(1);
+            }
+            dir
+        });
+        let up = Point3D::new(0.0, 1.0, 0.0);
+        let focus_dist = focus_distance(
+            &frame_options,
+            scene.world(),
+            key.origin(),
+            key.target(),
+            up,
+            key.focus_dist(),
+        );
+        let camera = build_camera(
+            &frame_options,
             ASPECT_RATIO,
-            20.0,
-            camera_origin,
-            camera_target,
-            Point3D::new(0.0, 1.0, 0.0),
-            0.1,
+            key.origin(),
+            key.target(),
+            up,
             focus_dist,
-        ),
-    );
+        );
+
+        scene = scene.set_camera(camera);
+        let framebuffer = FrameBuffer::new(
+            options.crop.width(),
+            options.crop.height(),
+            scene.seed(),
+            checkpoint_hash(options, &scene),
+        );
+
+        eprintln!("rendering {}", path.display());
+        let aov_buffer = AovBuffer::new(
+            framebuffer.width(),
+            framebuffer.height(),
+            options.aovs.clone(),
+        );
+        let (framebuffer, aov_buffer) = render(options, &scene, framebuffer, aov_buffer, None);
+        let denoised = denoise_render(options, &framebuffer, &aov_buffer);
+        let (width, height, colours) = output_colours(options, &framebuffer, denoised.as_deref());
+
+        write_aovs(
+            &frame_options,
+            &framebuffer,
+            &aov_buffer,
+            denoised.as_deref(),
+        );
+        if let Err(err) = png::write(&path, width, height, &colours) {
+            eprintln!("failed to write {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("denoise") {
+        denoise_command(args[1..].to_vec());
+        return;
+    }
+    let options = parse_options(args);
 
-    let bar = ProgressBar::new(SAMPLES_PER_PIXEL as u64);
+    let scene = match &options.scene {
+        Some(path) => scenefile::read(path).unwrap_or_else(|err| {
+            eprintln!("failed to read scene {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => default_scene(&options),
+    };
 
-    let rendered_scene: Vec<Vec3<f64>> = (0..SAMPLES_PER_PIXEL)
-        .into_par_iter()
-        .progress_with(bar)
-        .map(|_| scene.render(MAX_DEPTH, IMG_WIDTH, IMG_HEIGHT))
-        .collect::<Vec<Vec<Colour>>>()
-        .transpose()
-        .into_iter()
-        .map(|x| x.into_iter().sum::<Colour>())
-        .collect();
+    if let Some(path) = &options.save_scene {
+        if let Err(err) = scenefile::write(&scene, path) {
+            eprintln!("failed to write scene {}: {}", path.display(), err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(animation) = &options.animation {
+        render_sequence(&options, animation, scene);
+        return;
+    }
+
+    let scene_hash = checkpoint_hash(&options, &scene);
 
-    println!("P3\n{:?} {:?}\n255", IMG_WIDTH, IMG_HEIGHT);
-    write_render(&rendered_scene, SAMPLES_PER_PIXEL);
+    let fresh_aovs = |framebuffer: &FrameBuffer| {
+        AovBuffer::new(
+            framebuffer.width(),
+            framebuffer.height(),
+            options.aovs.clone(),
+        )
+    };
+    let (framebuffer, aov_buffer) = match &options.resume {
+        Some(path) => {
+            let (framebuffer, aov_buffer) =
+                FrameBuffer::load_with_aovs(path).unwrap_or_else(|err| {
+                    eprintln!("failed to load checkpoint {}: {}", path.display(), err);
+                    process::exit(1);
+                });
+            if !framebuffer.matches(options.crop.width(), options.crop.height(), scene_hash) {
+                eprintln!(
+                    "checkpoint {} was rendered from a different scene or resolution",
+                    path.display()
+                );
+                process::exit(1);
+            }
+
+            // The passes already rendered can only be added to if the
+            // checkpoint kept every AOV asked for, over all of them.
+            let aov_buffer = match aov_buffer {
+                _ if options.aovs.is_empty() || framebuffer.passes() == 0 => {
+                    fresh_aovs(&framebuffer)
+                }
+                Some(aov_buffer)
+                    if aov_buffer.aovs() == options.aovs.as_slice()
+                        && aov_buffer.samples() as u64 == framebuffer.passes() =>
+                {
+                    aov_buffer
+                }
+                _ => {
+                    eprintln!(
+                        "checkpoint {} doesn't have the AOVs asked for, which --denoise also needs",
+                        path.display()
+                    );
+                    process::exit(1);
+                }
+            };
+            (framebuffer, aov_buffer)
+        }
+        None => {
+            let framebuffer = FrameBuffer::new(
+                options.crop.width(),
+                options.crop.height(),
+                scene.seed(),
+                scene_hash,
+            );
+            let aov_buffer = fresh_aovs(&framebuffer);
+            (framebuffer, aov_buffer)
+        }
+    };
+    let scene = scene.set_seed(framebuffer.seed());
+
+    let (framebuffer, aov_buffer) = render(
+        &options,
+        &scene,
+        framebuffer,
+        aov_buffer,
+        options.checkpoint.as_ref(),
+    );
+    let denoised = denoise_render(&options, &framebuffer, &aov_buffer);
+
+    write_aovs(&options, &framebuffer, &aov_buffer, denoised.as_deref());
+
+    let (width, height, colours) = output_colours(&options, &framebuffer, denoised.as_deref());
+    println!("P3\n{:?} {:?}\n255", width, height);
+    for colour in colours {
+        println!("{:}", colour);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::hair::Hair;
 use crate::objects::HitRecord;
 use crate::ray::Ray;
 use crate::utils::{random_unit_sphere, random_unit_vec};
@@ -7,23 +8,70 @@ pub trait Material {
     fn scatter(&self, hit_record: &HitRecord, ray: &Ray) -> Option<(Ray, Colour)>;
 }
 
+#[derive(Debug, Clone)]
 pub struct Metal {
     albedo: Colour,
     fuzz: f64,
 }
 
+#[derive(Debug, Clone)]
 pub struct Lambertian {
     albedo: Colour,
 }
 
+#[derive(Debug, Clone)]
 pub struct Dielectric {
     refraction_index: f64,
 }
 
+// Emits light rather than scattering it, from the front of the surface only
+// unless it's two-sided.
+#[derive(Debug, Clone)]
+pub struct DiffuseLight {
+    emit: Colour,
+    two_sided: bool,
+}
+
+#[derive(Debug, Clone)]
 pub enum Materials {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    Hair(Hair),
+    DiffuseLight(DiffuseLight),
+}
+
+impl Materials {
+    pub fn albedo(&self) -> Colour {
+        match self {
+            Materials::Lambertian(lambertian) => lambertian.albedo,
+            Materials::Metal(metal) => metal.albedo,
+            Materials::Dielectric(_) => Colour::new(1.0, 1.0, 1.0),
+            Materials::Hair(hair) => hair.albedo(),
+            Materials::DiffuseLight(_) => Colour::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        match self {
+            Materials::Lambertian(_) => 1,
+            Materials::Metal(_) => 2,
+            Materials::Dielectric(_) => 3,
+            Materials::Hair(_) => 4,
+            Materials::DiffuseLight(_) => 5,
+        }
+    }
+
+    // The light given off where the ray hit, black for everything but
+    // lights.
+    pub fn emitted(&self, hit_record: &HitRecord) -> Colour {
+        match self {
+            Materials::DiffuseLight(light) if hit_record.front_face() || light.two_sided => {
+                light.emit
+            }
+            _ => Colour::new(0.0, 0.0, 0.0),
+        }
+    }
 }
 
 impl Material for Materials {
@@ -32,6 +80,8 @@ impl Material for Materials {
             Materials::Lambertian(lambertian) => lambertian.scatter(hit_record, ray),
             Materials::Metal(metal) => metal.scatter(hit_record, ray),
             Materials::Dielectric(dielectric) => dielectric.scatter(hit_record, ray),
+            Materials::Hair(hair) => hair.scatter(hit_record, ray),
+            Materials::DiffuseLight(_) => None,
         }
     }
 }
@@ -40,6 +90,10 @@ impl Lambertian {
     pub fn new(albedo: Colour) -> Self {
         Lambertian { albedo }
     }
+
+    pub fn albedo(&self) -> Colour {
+        self.albedo
+    }
 }
 
 impl Material for Lambertian {
@@ -59,6 +113,14 @@ impl Metal {
         let fuzz = if fuzz_float < 1.0 { fuzz_float } else { 1.0 };
         Metal { albedo, fuzz }
     }
+
+    pub fn albedo(&self) -> Colour {
+        self.albedo
+    }
+
+    pub fn fuzz(&self) -> f64 {
+        self.fuzz
+    }
 }
 
 impl Material for Metal {
@@ -81,6 +143,10 @@ impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
         Dielectric { refraction_index }
     }
+
+    pub fn refraction_index(&self) -> f64 {
+        self.refraction_index
+    }
 }
 
 impl Material for Dielectric {
@@ -91,8 +157,36 @@ impl Material for Dielectric {
             self.refraction_index
         };
 
-        let refracted_ray = ray.interact(&hit_record, refraction_ratio);
+        let refracted_ray = ray.interact(hit_record, refraction_ratio);
 
         Some((refracted_ray, Colour::new(1.0, 1.0, 1.0)))
     }
 }
+
+impl DiffuseLight {
+    pub fn new(emit: Colour) -> Self {
+        DiffuseLight {
+            emit,
+            two_sided: false,
+        }
+    }
+
+    pub fn set_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    pub fn emit(&self) -> Colour {
+        self.emit
+    }
+
+    pub fn two_sided(&self) -> bool {
+        self.two_sided
+    }
+}
+
+// A plain grey diffuse material for tests that only care about geometry.
+#[cfg(test)]
+pub(crate) fn grey() -> Materials {
+    Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)))
+}
@@ -0,0 +1,341 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::objects::{Hit, HitRecord};
+use crate::ray::Ray;
+use crate::vec3::{Point3D, Vec3};
+use std::cmp::Ordering;
+
+// Rays closer than this to parallel with a triangle miss it.
+const PARALLEL: f64 = 1e-12;
+
+// Nodes with this many triangles or fewer aren't split further.
+const LEAF_SIZE: usize = 4;
+
+// A node of the bounding volume hierarchy over a mesh's triangles, covering
+// `triangles[start..end]`. Inner nodes' children follow in `nodes`.
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    start: usize,
+    end: usize,
+    children: Option<(usize, usize)>,
+}
+
+// Triangles sharing a list of vertices, each with a normal and UV that are
+// interpolated across the faces. Normals are worked out from the triangles
+// unless given. The triangles are sorted into a bounding volume hierarchy as
+// the mesh is made, so large meshes stay cheap to hit.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    positions: Vec<Point3D>,
+    normals: Vec<Vec3<f64>>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+    nodes: Vec<Node>,
+    material: Materials,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point3D>, triangles: Vec<[usize; 3]>, material: Materials) -> Self {
+        assert!(!triangles.is_empty(), "a mesh needs at least one triangle");
+        assert!(
+            triangles
+                .iter()
+                .flatten()
+                .all(|&index| index < positions.len()),
+            "mesh triangles refer to missing vertices"
+        );
+
+        let normals = vertex_normals(&positions, &triangles);
+        let uvs = vec![(0.0, 0.0); positions.len()];
+        let mut mesh = Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            nodes: Vec::new(),
+            material,
+        };
+        let count = mesh.triangles.len();
+        mesh.build(0, count);
+        mesh
+    }
+
+    pub fn set_normals(mut self, normals: Vec<Vec3<f64>>) -> Self {
+        assert_eq!(normals.len(), self.positions.len());
+        self.normals = normals.into_iter().map(unit_or_zero).collect();
+        self
+    }
+
+    pub fn set_uvs(mut self, uvs: Vec<(f64, f64)>) -> Self {
+        assert_eq!(uvs.len(), self.positions.len());
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn positions(&self) -> &[Point3D] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3<f64>] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.uvs
+    }
+
+    // In hierarchy order rather than the order they were given in.
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.nodes[0].bounds.center()
+    }
+
+    fn triangle_bounds(&self, triangle: &[usize; 3]) -> Aabb {
+        Aabb::from_points(&triangle.map(|index| self.positions[index]))
+    }
+
+    fn centroid(&self, triangle: &[usize; 3]) -> Point3D {
+        triangle
+            .iter()
+            .map(|&index| self.positions[index])
+            .sum::<Point3D>()
+            / 3.0
+    }
+
+    // Splits the triangles at the median along the longest axis of their
+    // centroids until the leaves are small, returning the node's index.
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let bounds = self.triangles[start + 1..end].iter().fold(
+            self.triangle_bounds(&self.triangles[start]),
+            |bounds, triangle| bounds.surrounding(&self.triangle_bounds(triangle)),
+        );
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            start,
+            end,
+            children: None,
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        let centroids: Vec<Point3D> = self.triangles[start..end]
+            .iter()
+            .map(|triangle| self.centroid(triangle))
+            .collect();
+        let extent = Aabb::from_points(&centroids);
+        let size = extent.max() - extent.min();
+        let axis = |point: Point3D| match (size.x() >= size.y(), size.x() >= size.z()) {
+            (true, true) => point.x(),
+            _ if size.y() >= size.z() => point.y(),
+            _ => point.z(),
+        };
+
+        let mut keyed: Vec<(f64, [usize; 3])> = centroids
+            .into_iter()
+            .map(axis)
+            .zip(self.triangles[start..end].iter().cloned())
+            .collect();
+        keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        for (slot, (_, triangle)) in self.triangles[start..end].iter_mut().zip(keyed) {
+            *slot = triangle;
+        }
+
+        let middle = (start + end) / 2;
+        let left = self.build(start, middle);
+        let right = self.build(middle, end);
+        self.nodes[index].children = Some((left, right));
+        index
+    }
+
+    fn triangle_hit(
+        &self,
+        triangle: &[usize; 3],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'_>> {
+        let [a, b, c] = *triangle;
+        let (p0, p1, p2) = (self.positions[a], self.positions[b], self.positions[c]);
+        let (t, beta, gamma) = triangle_hit(ray, p0, p1, p2)?;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let alpha = 1.0 - beta - gamma;
+        let smooth = alpha * self.normals[a] + beta * self.normals[b] + gamma * self.normals[c];
+        let normal = match smooth.near_zero() {
+            true => (p1 - p0).cross(&(p2 - p0)).unit(),
+            false => smooth.unit(),
+        };
+        let (u, v) = (
+            alpha * self.uvs[a].0 + beta * self.uvs[b].0 + gamma * self.uvs[c].0,
+            alpha * self.uvs[a].1 + beta * self.uvs[b].1 + gamma * self.uvs[c].1,
+        );
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(ray.at(t))
+                .set_uv(u, v)
+                .set_face_normal(ray, &normal),
+        )
+    }
+}
+
+impl Hit for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let entry = self.nodes[0].bounds.hit_span(ray, t_min, t_max)?.0;
+        let mut stack = vec![(entry, 0)];
+        let mut closest = t_max;
+        let mut hit = None;
+
+        // As for heightfields, nearer children are pushed last so they're
+        // searched first and the nodes beyond the closest hit are skipped.
+        while let Some((entry, index)) = stack.pop() {
+            let node = &self.nodes[index];
+            if entry > closest {
+                continue;
+            }
+            let (left, right) = match node.children {
+                Some(children) => children,
+                None => {
+                    for triangle in &self.triangles[node.start..node.end] {
+                        if let Some(record) = self.triangle_hit(triangle, ray, t_min, closest) {
+                            closest = record.time();
+                            hit = Some(record);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let span = |child: usize| {
+                let bounds = self.nodes[child].bounds;
+                Some((bounds.hit_span(ray, t_min, closest)?.0, child))
+            };
+            match (span(left), span(right)) {
+                (Some(near), Some(far)) if far.0 < near.0 => stack.extend([near, far]),
+                (Some(near), Some(far)) => stack.extend([far, near]),
+                (one, other) => stack.extend(one.or(other)),
+            }
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.nodes[0].bounds)
+    }
+}
+
+// Each vertex's normal is the area-weighted average of those of the
+// triangles around it, so they follow the triangles' winding.
+fn vertex_normals(positions: &[Point3D], triangles: &[[usize; 3]]) -> Vec<Vec3<f64>> {
+    let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); positions.len()];
+    for &[a, b, c] in triangles {
+        let face = (positions[b] - positions[a]).cross(&(positions[c] - positions[a]));
+        for index in [a, b, c] {
+            normals[index] += face;
+        }
+    }
+    normals.into_iter().map(unit_or_zero).collect()
+}
+
+// Vertices off every triangle, or on degenerate ones only, keep a zero
+// normal, and hits near them use the triangle's own.
+pub(crate) fn unit_or_zero(normal: Vec3<f64>) -> Vec3<f64> {
+    match normal.near_zero() {
+        true => Vec3::new(0.0, 0.0, 0.0),
+        false => normal.unit(),
+    }
+}
+
+// Moller-Trumbore intersection, giving t and the barycentric weights of the
+// second and third vertices.
+pub(crate) fn triangle_hit(
+    ray: &Ray,
+    p0: Point3D,
+    p1: Point3D,
+    p2: Point3D,
+) -> Option<(f64, f64, f64)> {
+    let (edge1, edge2) = (p1 - p0, p2 - p0);
+    let p = ray.direction().cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < PARALLEL {
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let offset = ray.origin() - p0;
+    let beta = offset.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+    let q = offset.cross(&edge1);
+    let gamma = ray.direction().dot(&q) * inverse;
+    if gamma < 0.0 || beta + gamma > 1.0 {
+        return None;
+    }
+
+    Some((edge2.dot(&q) * inverse, beta, gamma))
+}
+
+#[cfg(test)]
+mod mesh_tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::utils::{random_range, random_unit_vec, seed_sampler};
+    use crate::vec3::Colour;
+
+    #[test]
+    fn test_hierarchy_matches_every_triangle() {
+        seed_sampler(5);
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for n in 0..200 {
+            let corner = Point3D::new(
+                random_range(-2.0, 2.0),
+                random_range(-2.0, 2.0),
+                random_range(-2.0, 2.0),
+            );
+            for _ in 0..3 {
+                positions.push(corner + 0.3 * random_unit_vec());
+            }
+            triangles.push([3 * n, 3 * n + 1, 3 * n + 2]);
+        }
+        let grey = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
+        let mesh = Mesh::new(positions, triangles, grey);
+        assert!(mesh.nodes.len() > 1);
+
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = 4.0 * random_unit_vec();
+            let ray = Ray::new(
+                origin,
+                random_range(0.5, 1.5) * random_unit_vec() - origin / 4.0,
+            );
+            let brute = mesh
+                .triangles
+                .iter()
+                .filter_map(|triangle| mesh.triangle_hit(triangle, &ray, 0.001, f64::INFINITY))
+                .map(|record| record.time())
+                .fold(f64::INFINITY, f64::min);
+            match mesh.hit(&ray, 0.001, f64::INFINITY) {
+                Some(record) => {
+                    hits += 1;
+                    assert_eq!(brute, record.time());
+                }
+                None => assert!(brute.is_infinite()),
+            }
+        }
+        assert!(hits > 50, "only {} rays hit", hits);
+    }
+}
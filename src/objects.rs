@@ -1,27 +1,88 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::csg::Csg;
+use crate::curve::Curve;
+use crate::heightfield::Heightfield;
 use crate::material::{Material, Materials};
+use crate::mesh::Mesh;
+use crate::planar::{Cuboid, Disk, Plane, Quad, Rect};
+use crate::quadrics::{Cone, Cylinder, Hyperboloid, Paraboloid, Torus};
 use crate::ray::Ray;
+use crate::sdf::Sdf;
 use crate::vec3::{Colour, Point3D, Vec3};
+use std::sync::OnceLock;
 
 pub trait Hit {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    // None for shapes without finite bounds.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Every crossing of the surface between t_min and t_max, in order. By
+    // default this steps past each closest hit in turn; shapes that can find
+    // all their crossings at once should do so.
+    fn hits(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        let mut hits = Vec::new();
+        let mut t_min = t_min;
+        while let Some(record) = self.hit(ray, t_min, t_max) {
+            // Far along the ray the step rounds away, so it's at least one
+            // representable value, and a crossing that doesn't move on ends
+            // the search rather than being found forever.
+            let next = record.t.next_up().max(record.t + CROSSING_STEP);
+            hits.push(record);
+            if next <= t_min || next.is_nan() {
+                break;
+            }
+            t_min = next;
+        }
+        hits
+    }
+
+    // The stretches of the whole line through the ray that lie inside a
+    // closed shape, in order. A ray leaving the shape before it has entered
+    // only grazed an open surface, so that crossing is dropped.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let mut intervals = Vec::new();
+        let mut enter = None;
+        for record in self.hits(ray, f64::NEG_INFINITY, f64::INFINITY) {
+            match (record.front_face, enter.take()) {
+                (true, None) => enter = Some(record),
+                (true, Some(previous)) => enter = Some(previous),
+                (false, Some(previous)) => intervals.push(Interval::new(previous, record)),
+                (false, None) => {}
+            }
+        }
+        intervals
+    }
 }
 
+// How far past a crossing the default `Hit::hits` looks for the next one.
+const CROSSING_STEP: f64 = 1e-7;
+
 pub struct HitRecord<'a> {
     point: Point3D,
     normal: Vec3<f64>,
+    tangent: Vec3<f64>,
     t: f64,
+    u: f64,
+    v: f64,
     front_face: bool,
+    object_id: usize,
     material: &'a Materials,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(material: &Materials) -> HitRecord {
+    pub fn new(material: &Materials) -> HitRecord<'_> {
         HitRecord {
             point: Point3D::new(0.0, 0.0, 0.0),
             normal: Vec3::new(0.0, 0.0, 0.0),
+            tangent: Vec3::new(0.0, 0.0, 0.0),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
-            material: material,
+            object_id: 0,
+            material,
         }
     }
 
@@ -35,6 +96,24 @@ impl<'a> HitRecord<'a> {
         self
     }
 
+    pub fn set_uv(mut self, u: f64, v: f64) -> HitRecord<'a> {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    // The direction of increasing u along the surface, where a material
+    // needs it, as for hair. Zero when the shape doesn't provide one.
+    pub fn set_tangent(mut self, tangent: Vec3<f64>) -> HitRecord<'a> {
+        self.tangent = tangent;
+        self
+    }
+
+    pub fn set_object_id(mut self, object_id: usize) -> HitRecord<'a> {
+        self.object_id = object_id;
+        self
+    }
+
     pub fn set_face_normal(mut self, ray: &Ray, outward_normal: &Vec3<f64>) -> HitRecord<'a> {
         self.front_face = ray.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -46,6 +125,13 @@ impl<'a> HitRecord<'a> {
         self
     }
 
+    // Swaps which side of the surface counts as outside, as for the surface
+    // of a shape subtracted from another. The normal already faces the ray.
+    pub fn flip_face(mut self) -> HitRecord<'a> {
+        self.front_face = !self.front_face;
+        self
+    }
+
     pub fn normal(&self) -> Vec3<f64> {
         self.normal
     }
@@ -54,8 +140,28 @@ impl<'a> HitRecord<'a> {
         self.point
     }
 
+    pub fn tangent(&self) -> Vec3<f64> {
+        self.tangent
+    }
+
+    pub fn time(&self) -> f64 {
+        self.t
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        (self.u, self.v)
+    }
+
+    pub fn object_id(&self) -> usize {
+        self.object_id
+    }
+
+    pub fn material(&self) -> &Materials {
+        self.material
+    }
+
     pub fn scatter_on_mat(&self, ray: &Ray) -> Option<(Ray, Colour)> {
-        self.material.scatter(&self, ray)
+        self.material.scatter(self, ray)
     }
 
     pub fn front_face(&self) -> bool {
@@ -63,20 +169,167 @@ impl<'a> HitRecord<'a> {
     }
 }
 
+// A stretch of a ray inside a closed shape, between where it enters and
+// where it leaves.
+pub struct Interval<'a> {
+    enter: HitRecord<'a>,
+    exit: HitRecord<'a>,
+}
+
+impl<'a> Interval<'a> {
+    pub fn new(enter: HitRecord<'a>, exit: HitRecord<'a>) -> Self {
+        Interval { enter, exit }
+    }
+
+    pub fn enter(&self) -> &HitRecord<'a> {
+        &self.enter
+    }
+
+    pub fn exit(&self) -> &HitRecord<'a> {
+        &self.exit
+    }
+
+    pub fn into_hits(self) -> (HitRecord<'a>, HitRecord<'a>) {
+        (self.enter, self.exit)
+    }
+}
+
+#[derive(Debug)]
 pub enum Object {
     Sphere(Sphere),
+    Quad(Quad),
+    Rect(Rect),
+    Disk(Disk),
+    Cuboid(Cuboid),
+    Plane(Plane),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Paraboloid(Paraboloid),
+    Hyperboloid(Hyperboloid),
+    Csg(Csg),
+    Sdf(Sdf),
+    Heightfield(Heightfield),
+    Curve(Curve),
+    Mesh(Mesh),
     SceneObjects(SceneObjects),
 }
 
 impl Hit for Object {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         match self {
             Object::Sphere(sphere) => sphere.hit(ray, t_min, t_max),
+            Object::Quad(quad) => quad.hit(ray, t_min, t_max),
+            Object::Rect(rect) => rect.hit(ray, t_min, t_max),
+            Object::Disk(disk) => disk.hit(ray, t_min, t_max),
+            Object::Cuboid(cuboid) => cuboid.hit(ray, t_min, t_max),
+            Object::Plane(plane) => plane.hit(ray, t_min, t_max),
+            Object::Cylinder(cylinder) => cylinder.hit(ray, t_min, t_max),
+            Object::Cone(cone) => cone.hit(ray, t_min, t_max),
+            Object::Torus(torus) => torus.hit(ray, t_min, t_max),
+            Object::Paraboloid(paraboloid) => paraboloid.hit(ray, t_min, t_max),
+            Object::Hyperboloid(hyperboloid) => hyperboloid.hit(ray, t_min, t_max),
+            Object::Csg(csg) => csg.hit(ray, t_min, t_max),
+            Object::Sdf(sdf) => sdf.hit(ray, t_min, t_max),
+            Object::Heightfield(heightfield) => heightfield.hit(ray, t_min, t_max),
+            Object::Curve(curve) => curve.hit(ray, t_min, t_max),
+            Object::Mesh(mesh) => mesh.hit(ray, t_min, t_max),
             Object::SceneObjects(scene_object) => scene_object.hit(ray, t_min, t_max),
         }
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Object::Sphere(sphere) => sphere.bounding_box(),
+            Object::Quad(quad) => quad.bounding_box(),
+            Object::Rect(rect) => rect.bounding_box(),
+            Object::Disk(disk) => disk.bounding_box(),
+            Object::Cuboid(cuboid) => cuboid.bounding_box(),
+            Object::Plane(plane) => plane.bounding_box(),
+            Object::Cylinder(cylinder) => cylinder.bounding_box(),
+            Object::Cone(cone) => cone.bounding_box(),
+            Object::Torus(torus) => torus.bounding_box(),
+            Object::Paraboloid(paraboloid) => paraboloid.bounding_box(),
+            Object::Hyperboloid(hyperboloid) => hyperboloid.bounding_box(),
+            Object::Csg(csg) => csg.bounding_box(),
+            Object::Sdf(sdf) => sdf.bounding_box(),
+            Object::Heightfield(heightfield) => heightfield.bounding_box(),
+            Object::Curve(curve) => curve.bounding_box(),
+            Object::Mesh(mesh) => mesh.bounding_box(),
+            Object::SceneObjects(scene_object) => scene_object.bounding_box(),
+        }
+    }
+
+    fn hits(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        match self {
+            Object::Sphere(sphere) => sphere.hits(ray, t_min, t_max),
+            Object::Quad(quad) => quad.hits(ray, t_min, t_max),
+            Object::Rect(rect) => rect.hits(ray, t_min, t_max),
+            Object::Disk(disk) => disk.hits(ray, t_min, t_max),
+            Object::Cuboid(cuboid) => cuboid.hits(ray, t_min, t_max),
+            Object::Plane(plane) => plane.hits(ray, t_min, t_max),
+            Object::Cylinder(cylinder) => cylinder.hits(ray, t_min, t_max),
+            Object::Cone(cone) => cone.hits(ray, t_min, t_max),
+            Object::Torus(torus) => torus.hits(ray, t_min, t_max),
+            Object::Paraboloid(paraboloid) => paraboloid.hits(ray, t_min, t_max),
+            Object::Hyperboloid(hyperboloid) => hyperboloid.hits(ray, t_min, t_max),
+            Object::Csg(csg) => csg.hits(ray, t_min, t_max),
+            Object::Sdf(sdf) => sdf.hits(ray, t_min, t_max),
+            Object::Heightfield(heightfield) => heightfield.hits(ray, t_min, t_max),
+            Object::Curve(curve) => curve.hits(ray, t_min, t_max),
+            Object::Mesh(mesh) => mesh.hits(ray, t_min, t_max),
+            Object::SceneObjects(scene_object) => scene_object.hits(ray, t_min, t_max),
+        }
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        match self {
+            Object::Sphere(sphere) => sphere.intervals(ray),
+            Object::Quad(quad) => quad.intervals(ray),
+            Object::Rect(rect) => rect.intervals(ray),
+            Object::Disk(disk) => disk.intervals(ray),
+            Object::Cuboid(cuboid) => cuboid.intervals(ray),
+            Object::Plane(plane) => plane.intervals(ray),
+            Object::Cylinder(cylinder) => cylinder.intervals(ray),
+            Object::Cone(cone) => cone.intervals(ray),
+            Object::Torus(torus) => torus.intervals(ray),
+            Object::Paraboloid(paraboloid) => paraboloid.intervals(ray),
+            Object::Hyperboloid(hyperboloid) => hyperboloid.intervals(ray),
+            Object::Csg(csg) => csg.intervals(ray),
+            Object::Sdf(sdf) => sdf.intervals(ray),
+            Object::Heightfield(heightfield) => heightfield.intervals(ray),
+            Object::Curve(curve) => curve.intervals(ray),
+            Object::Mesh(mesh) => mesh.intervals(ray),
+            Object::SceneObjects(scene_object) => scene_object.intervals(ray),
+        }
+    }
+}
+
+impl Object {
+    pub fn center(&self) -> Point3D {
+        match self {
+            Object::Sphere(sphere) => sphere.center(),
+            Object::Quad(quad) => quad.center(),
+            Object::Rect(rect) => rect.center(),
+            Object::Disk(disk) => disk.center(),
+            Object::Cuboid(cuboid) => cuboid.center(),
+            Object::Plane(plane) => plane.point(),
+            Object::Cylinder(cylinder) => cylinder.center(),
+            Object::Cone(cone) => cone.center(),
+            Object::Torus(torus) => torus.center(),
+            Object::Paraboloid(paraboloid) => paraboloid.center(),
+            Object::Hyperboloid(hyperboloid) => hyperboloid.center(),
+            Object::Csg(csg) => csg.center(),
+            Object::Sdf(sdf) => sdf.center(),
+            Object::Heightfield(heightfield) => heightfield.center(),
+            Object::Curve(curve) => curve.center(),
+            Object::Mesh(mesh) => mesh.center(),
+            Object::SceneObjects(scene_objects) => scene_objects.center(),
+        }
+    }
 }
 
+#[derive(Debug)]
 pub struct Sphere {
     center: Point3D,
     radius: f64,
@@ -99,10 +352,14 @@ impl Sphere {
     pub fn radius(&self) -> f64 {
         self.radius
     }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
 }
 
 impl Hit for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc: Vec3<f64> = ray.origin() - self.center;
 
         let a: f64 = ray.direction().length_sqrd();
@@ -126,46 +383,451 @@ impl Hit for Sphere {
 
         let outward_normal: Vec3<f64> = (ray.at(root) - self.center) / self.radius;
 
+        let (u, v) = sphere_uv(&outward_normal);
+
         let hit_record = HitRecord::new(&self.material)
             .set_time(root)
             .set_point(ray.at(root))
+            .set_uv(u, v)
             .set_face_normal(ray, &outward_normal);
 
         Some(hit_record)
     }
+
+    fn hits(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        let mut hits = Vec::with_capacity(2);
+        if let Some(near) = self.hit(ray, t_min, t_max) {
+            let t = near.t;
+            hits.push(near);
+            hits.extend(self.hit(ray, t.next_up(), t_max));
+        }
+        hits
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+}
+
+fn sphere_uv(point: &Point3D) -> (f64, f64) {
+    let theta = (-point.y()).acos();
+    let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
+}
+
+// An object in a scene, with the id it's known by in hit records and the
+// object_id AOV, and what tools have said about it.
+#[derive(Debug)]
+struct Entry {
+    id: usize,
+    name: Option<String>,
+    tags: Vec<String>,
+    parent: Option<usize>,
+    hidden: bool,
+    visible: bool,
+    object: Object,
 }
 
+// The objects in a scene, each given an id from 1 as it's added. Ids are
+// never reused, so they stay the same as other objects are removed. Objects
+// can be named, tagged and arranged under each other; hiding an object hides
+// everything under it too. The visible objects are sorted into a bounding
+// volume hierarchy the first time a ray is traced after any change.
+#[derive(Debug, Default)]
 pub struct SceneObjects {
-    objects: Vec<Object>,
+    entries: Vec<Entry>,
+    last_id: usize,
+    bvh: OnceLock<Bvh>,
 }
 
 impl SceneObjects {
     pub fn new() -> Self {
         SceneObjects {
-            objects: Vec::new(),
+            entries: Vec::new(),
+            last_id: 0,
+            bvh: OnceLock::new(),
+        }
+    }
+
+    pub fn add(&mut self, object: Object) -> usize {
+        let id = self.last_id + 1;
+        self.add_with_id(id, object);
+        id
+    }
+
+    // Adds an object with a given id, as when reading a saved scene, so long
+    // as it's later than any id given out before, keeping the entries in id
+    // order. False if it isn't.
+    pub fn add_with_id(&mut self, id: usize, object: Object) -> bool {
+        if id <= self.last_id {
+            return false;
         }
+        self.last_id = id;
+        self.bvh = OnceLock::new();
+        self.entries.push(Entry {
+            id,
+            name: None,
+            tags: Vec::new(),
+            parent: None,
+            hidden: false,
+            visible: true,
+            object,
+        });
+        true
     }
 
-    pub fn add(&mut self, object: Object) {
-        self.objects.push(object);
+    pub fn add_named(&mut self, name: &str, object: Object) -> Option<usize> {
+        if self.id(name).is_some() {
+            return None;
+        }
+        let id = self.add(object);
+        self.set_name(id, Some(name));
+        Some(id)
     }
 
+    // Removes every object, but carries on counting ids from where it was.
     pub fn clear(&mut self) {
-        self.objects.clear();
+        self.entries.clear();
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Every object and its id, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Object)> {
+        self.entries.iter().map(|entry| (entry.id, &entry.object))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries.iter().map(|entry| entry.id)
+    }
+
+    pub fn object(&self, id: usize) -> Option<&Object> {
+        Some(&self.entry(id)?.object)
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Object> {
+        self.object(self.id(name)?)
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name.as_deref() == Some(name))
+            .map(|entry| entry.id)
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.entry(id)?.name.as_deref()
+    }
+
+    // Names are unique, so this fails if another object already has it.
+    pub fn set_name(&mut self, id: usize, name: Option<&str>) -> bool {
+        if name
+            .and_then(|name| self.id(name))
+            .is_some_and(|other| other != id)
+        {
+            return false;
+        }
+        match self.entry_mut(id) {
+            Some(entry) => {
+                entry.name = name.map(str::to_string);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn tags(&self, id: usize) -> &[String] {
+        self.entry(id).map_or(&[], |entry| &entry.tags)
+    }
+
+    pub fn tag(&mut self, id: usize, tag: &str) -> bool {
+        match self.entry_mut(id) {
+            Some(entry) => {
+                if !entry.tags.iter().any(|other| other == tag) {
+                    entry.tags.push(tag.to_string());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn untag(&mut self, id: usize, tag: &str) -> bool {
+        match self.entry_mut(id) {
+            Some(entry) => {
+                entry.tags.retain(|other| other != tag);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.tags.iter().any(|other| other == tag))
+            .map(|entry| entry.id)
+    }
+
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.entry(id)?.parent
+    }
+
+    pub fn children(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.parent == Some(id))
+            .map(|entry| entry.id)
+    }
+
+    // Fails if either object is missing, or the parent is already under the
+    // child, as that would make a loop.
+    pub fn set_parent(&mut self, id: usize, parent: Option<usize>) -> bool {
+        if self.entry(id).is_none() {
+            return false;
+        }
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(above) = ancestor {
+                if above == id || self.entry(above).is_none() {
+                    return false;
+                }
+                ancestor = self.parent(above);
+            }
+        }
+        if let Some(entry) = self.entry_mut(id) {
+            entry.parent = parent;
+        }
+        self.update_visibility();
+        true
+    }
+
+    // Whether the object was hidden itself, rather than by being under a
+    // hidden object.
+    pub fn is_hidden(&self, id: usize) -> bool {
+        self.entry(id).is_some_and(|entry| entry.hidden)
+    }
+
+    pub fn is_visible(&self, id: usize) -> bool {
+        self.entry(id).is_some_and(|entry| entry.visible)
+    }
+
+    pub fn set_hidden(&mut self, id: usize, hidden: bool) -> bool {
+        match self.entry_mut(id) {
+            Some(entry) => entry.hidden = hidden,
+            None => return false,
+        }
+        self.update_visibility();
+        true
+    }
+
+    // Keeps the object's id, name, tags and place among the others.
+    pub fn replace(&mut self, id: usize, object: Object) -> Option<Object> {
+        self.bvh = OnceLock::new();
+        let entry = self.entry_mut(id)?;
+        Some(std::mem::replace(&mut entry.object, object))
+    }
+
+    // The object's children are moved up to its own parent.
+    pub fn remove(&mut self, id: usize) -> Option<Object> {
+        let index = self.index(id)?;
+        let entry = self.entries.remove(index);
+        for child in self.entries.iter_mut() {
+            if child.parent == Some(id) {
+                child.parent = entry.parent;
+            }
+        }
+        self.update_visibility();
+        Some(entry.object)
+    }
+
+    // The average of the visible objects' centers.
+    pub fn center(&self) -> Point3D {
+        let visible = || self.entries.iter().filter(|entry| entry.visible);
+        let sum: Point3D = visible().map(|entry| entry.object.center()).sum();
+        sum / visible().count().max(1) as f64
+    }
+
+    // Ids are handed out in increasing order and entries are kept in the
+    // order they were added, so they can be found by bisection.
+    fn index(&self, id: usize) -> Option<usize> {
+        self.entries
+            .binary_search_by_key(&id, |entry| entry.id)
+            .ok()
+    }
+
+    fn entry(&self, id: usize) -> Option<&Entry> {
+        Some(&self.entries[self.index(id)?])
+    }
+
+    fn entry_mut(&mut self, id: usize) -> Option<&mut Entry> {
+        let index = self.index(id)?;
+        Some(&mut self.entries[index])
+    }
+
+    fn update_visibility(&mut self) {
+        let visible: Vec<bool> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut current = Some(entry);
+                while let Some(entry) = current {
+                    if entry.hidden {
+                        return false;
+                    }
+                    current = entry.parent.and_then(|parent| self.entry(parent));
+                }
+                true
+            })
+            .collect();
+        for (entry, visible) in self.entries.iter_mut().zip(visible) {
+            entry.visible = visible;
+        }
+        self.bvh = OnceLock::new();
     }
 }
 
 impl Hit for SceneObjects {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut closest_so_far = t_max;
-        let mut hit_record: Option<HitRecord> = None;
-
-        for object in self.objects.iter() {
-            if let Some(tmp_record) = object.hit(ray, t_min, closest_so_far) {
-                closest_so_far = tmp_record.t;
-                hit_record = Some(tmp_record)
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let bvh = self.bvh.get_or_init(|| {
+            Bvh::new(
+                self.entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| entry.visible)
+                    .map(|(index, entry)| (index, entry.object.bounding_box())),
+            )
+        });
+        bvh.hit(ray, t_min, t_max, |index, t_min, t_max| {
+            let entry = &self.entries[index];
+            Some(entry.object.hit(ray, t_min, t_max)?.set_object_id(entry.id))
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self
+            .entries
+            .iter()
+            .filter(|entry| entry.visible)
+            .map(|entry| entry.object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |aabb, other| Some(aabb.surrounding(&other?)))
+    }
+}
+
+#[cfg(test)]
+mod objects_tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::utils::{random_range, random_unit_vec, seed_sampler};
+
+    fn sphere(x: f64) -> Object {
+        let grey = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
+        Object::Sphere(Sphere::new(Point3D::new(x, 0.0, 0.0), 0.5, grey))
+    }
+
+    #[test]
+    fn test_scene_graph() {
+        let mut world = SceneObjects::new();
+        let table = world.add_named("table", sphere(0.0)).unwrap();
+        let cup = world.add_named("cup", sphere(2.0)).unwrap();
+        let saucer = world.add(sphere(4.0));
+        assert!(world.add_named("cup", sphere(6.0)).is_none());
+        assert!(world.set_parent(cup, Some(table)));
+        assert!(world.set_parent(saucer, Some(cup)));
+        assert!(!world.set_parent(table, Some(saucer)));
+        world.tag(cup, "crockery");
+        world.tag(saucer, "crockery");
+        assert_eq!(
+            vec![cup, saucer],
+            world.tagged("crockery").collect::<Vec<_>>()
+        );
+
+        // Hiding the table hides everything on it.
+        let ray = |x: f64| Ray::new(Point3D::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(world.set_hidden(table, true));
+        assert!(!world.is_visible(saucer));
+        assert!(world.hit(&ray(4.0), 0.001, f64::INFINITY).is_none());
+        world.set_hidden(table, false);
+        let record = world.hit(&ray(4.0), 0.001, f64::INFINITY).unwrap();
+        assert_eq!(saucer, record.object_id());
+
+        // Removing the cup leaves the other ids alone and moves the saucer
+        // onto the table.
+        assert!(world.remove(cup).is_some());
+        assert!(world.named("cup").is_none());
+        assert_eq!(Some(table), world.parent(saucer));
+        assert_eq!(
+            saucer,
+            world
+                .hit(&ray(4.0), 0.001, f64::INFINITY)
+                .unwrap()
+                .object_id()
+        );
+        assert!(world.replace(saucer, sphere(2.0)).is_some());
+        assert_eq!(
+            saucer,
+            world
+                .hit(&ray(2.0), 0.001, f64::INFINITY)
+                .unwrap()
+                .object_id()
+        );
+        assert_eq!(saucer + 1, world.add(sphere(8.0)));
+    }
+
+    #[test]
+    fn test_hierarchy_matches_every_object() {
+        seed_sampler(9);
+        let grey = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
+        let mut world = SceneObjects::new();
+        for _ in 0..100 {
+            let center = Point3D::new(
+                random_range(-4.0, 4.0),
+                random_range(-4.0, 4.0),
+                random_range(-4.0, 4.0),
+            );
+            world.add(Object::Sphere(Sphere::new(center, 0.3, grey.clone())));
+        }
+        // Planes have no bounds, so they're tried outside the hierarchy.
+        let floor = world.add(Object::Plane(Plane::new(
+            Point3D::new(0.0, -3.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            grey,
+        )));
+
+        let mut floor_hits = 0;
+        for _ in 0..500 {
+            let origin = 6.0 * random_unit_vec();
+            let ray = Ray::new(origin, random_unit_vec() - origin / 6.0);
+            let brute = world
+                .iter()
+                .filter_map(|(id, object)| Some((object.hit(&ray, 0.001, f64::INFINITY)?, id)))
+                .min_by(|a, b| a.0.time().partial_cmp(&b.0.time()).unwrap());
+            match (world.hit(&ray, 0.001, f64::INFINITY), brute) {
+                (Some(record), Some((closest, id))) => {
+                    assert_eq!(closest.time(), record.time());
+                    assert_eq!(id, record.object_id());
+                    floor_hits += (id == floor) as usize;
+                }
+                (None, None) => {}
+                (record, brute) => {
+                    panic!("hit {} but expected {}", record.is_some(), brute.is_some())
+                }
             }
         }
-        hit_record
+        assert!(floor_hits > 0);
     }
 }
@@ -0,0 +1,1033 @@
+use crate::camera::{Camera, Cameras, Orthographic};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Materials, Metal};
+use crate::mesh::Mesh;
+use crate::objects::{Object, SceneObjects, Sphere};
+use crate::planar::Disk;
+use crate::ply;
+use crate::scene::{Background, Scene};
+use crate::transform::Transform;
+use crate::vec3::{Colour, Point3D, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Directive(String),
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+// A parameter such as `"rgb reflectance" [0.5 0.5 0.5]`.
+#[derive(Debug, Clone)]
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        self.get(name)?
+            .values
+            .iter()
+            .map(|value| match value {
+                Value::Number(number) => Some(*number),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn number(&self, name: &str) -> Option<f64> {
+        self.numbers(name)?.first().cloned()
+    }
+
+    fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.values.first()? {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)?.values.first()? {
+            Value::Bool(value) => Some(*value),
+            Value::Text(text) => Some(text == "true"),
+            _ => None,
+        }
+    }
+}
+
+// The state that AttributeBegin and AttributeEnd save and restore.
+#[derive(Debug, Clone)]
+struct Attributes {
+    transform: Transform,
+    // None for the interface material, whose shapes aren't drawn.
+    material: Option<Materials>,
+    area_light: Option<DiffuseLight>,
+    reverse_orientation: bool,
+}
+
+// A scene in a practical subset of the PBRT v3 and v4 formats: cameras,
+// film size, the transform and attribute stacks, spheres, disks, triangle
+// and PLY meshes, diffuse, conductor and dielectric materials, and diffuse
+// area and uniform infinite lights. Anything else is skipped with a warning.
+// PBRT's coordinates are left-handed, so the world is mirrored in x to come
+// out the same way round.
+#[derive(Debug)]
+pub struct Pbrt {
+    world: SceneObjects,
+    camera: Cameras,
+    background: Background,
+    resolution: (usize, usize),
+    warnings: Vec<String>,
+}
+
+impl Pbrt {
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Pbrt> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut builder = Builder::new(directory);
+        builder.open_files.push(fs::canonicalize(path)?);
+        builder.file(&fs::read_to_string(path)?)?;
+        Ok(builder.finish())
+    }
+
+    // Included files and PLY meshes are read relative to `directory`.
+    pub fn parse(text: &str, directory: &Path) -> io::Result<Pbrt> {
+        let mut builder = Builder::new(directory);
+        builder.file(text)?;
+        Ok(builder.finish())
+    }
+
+    pub fn world(&self) -> &SceneObjects {
+        &self.world
+    }
+
+    pub fn camera(&self) -> &Cameras {
+        &self.camera
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    // The film's width and height in pixels.
+    pub fn resolution(&self) -> (usize, usize) {
+        self.resolution
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn into_scene(self) -> Scene {
+        Scene::new(self.world, self.camera).set_background(self.background)
+    }
+}
+
+struct Builder {
+    directory: PathBuf,
+    attributes: Attributes,
+    // Saved attributes, and whether only the transform is to be restored.
+    stack: Vec<(Attributes, bool)>,
+    named_materials: HashMap<String, Option<Materials>>,
+    coordinate_systems: HashMap<String, Transform>,
+    camera: Option<(String, Params, Transform)>,
+    resolution: (usize, usize),
+    world: SceneObjects,
+    background: Option<Colour>,
+    // Transforms only apply at the start of the shutter interval.
+    end_time_only: bool,
+    object_depth: usize,
+    // The files being read, innermost last, so includes can't loop.
+    open_files: Vec<PathBuf>,
+    warnings: Vec<String>,
+    warned: HashSet<String>,
+}
+
+impl Builder {
+    fn new(directory: &Path) -> Self {
+        Builder {
+            directory: directory.to_path_buf(),
+            attributes: Attributes {
+                transform: Transform::identity(),
+                material: Some(Materials::Lambertian(Lambertian::new(Colour::new(
+                    0.5, 0.5, 0.5,
+                )))),
+                area_light: None,
+                reverse_orientation: false,
+            },
+            stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            camera: None,
+            resolution: (1280, 720),
+            world: SceneObjects::new(),
+            background: None,
+            end_time_only: false,
+            object_depth: 0,
+            open_files: Vec::new(),
+            warnings: Vec::new(),
+            warned: HashSet::new(),
+        }
+    }
+
+    // Each distinct problem is reported once, at its first line.
+    fn warn(&mut self, line: usize, message: String) {
+        if self.warned.insert(message.clone()) {
+            self.warnings.push(format!("line {}: {}", line, message));
+        }
+    }
+
+    fn file(&mut self, text: &str) -> io::Result<()> {
+        let tokens = tokenize(text)?;
+        let mut position = 0;
+        while position < tokens.len() {
+            let (name, line) = match &tokens[position] {
+                (Token::Directive(name), line) => (name.clone(), *line),
+                (_, line) => return Err(syntax_error(*line, "expected a directive")),
+            };
+            let end = tokens[position + 1..]
+                .iter()
+                .position(|(token, _)| matches!(token, Token::Directive(_)))
+                .map_or(tokens.len(), |offset| position + 1 + offset);
+            let arguments: Vec<Token> = tokens[position + 1..end]
+                .iter()
+                .map(|(token, _)| token.clone())
+                .collect();
+            self.directive(&name, &arguments, line)?;
+            position = end;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, name: &str, arguments: &[Token], line: usize) -> io::Result<()> {
+        let (values, params) = split_arguments(arguments, line)?;
+        let numbers = |count: usize| -> io::Result<Vec<f64>> {
+            let numbers: Vec<f64> = values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::Number(number) => Some(*number),
+                    _ => None,
+                })
+                .collect();
+            match numbers.len() == count {
+                true => Ok(numbers),
+                false => Err(syntax_error(
+                    line,
+                    &format!("{} expects {} numbers", name, count),
+                )),
+            }
+        };
+        let text = || match values.first() {
+            Some(Value::Text(text)) => Ok(text.clone()),
+            _ => Err(syntax_error(line, &format!("{} expects a name", name))),
+        };
+
+        match name {
+            "Identity" => self.set_transform(Transform::identity()),
+            "Translate" => {
+                let v = numbers(3)?;
+                self.concat(Transform::translate(Vec3::new(v[0], v[1], v[2])));
+            }
+            "Scale" => {
+                let v = numbers(3)?;
+                self.concat(Transform::scale(v[0], v[1], v[2]));
+            }
+            "Rotate" => {
+                let v = numbers(4)?;
+                self.concat(Transform::rotate(v[0], Vec3::new(v[1], v[2], v[3])));
+            }
+            "LookAt" => {
+                let v = numbers(9)?;
+                match look_at(
+                    Point3D::new(v[0], v[1], v[2]),
+                    Point3D::new(v[3], v[4], v[5]),
+                    Vec3::new(v[6], v[7], v[8]),
+                ) {
+                    Some(transform) => self.concat(transform),
+                    None => self.warn(line, "degenerate LookAt ignored".to_string()),
+                }
+            }
+            "Transform" | "ConcatTransform" => {
+                let v = numbers(16)?;
+                let mut columns = [0.0; 16];
+                columns.copy_from_slice(&v);
+                match name {
+                    "Transform" => self.set_transform(Transform::from_columns(columns)),
+                    _ => self.concat(Transform::from_columns(columns)),
+                }
+            }
+            "CoordinateSystem" => {
+                let transform = self.attributes.transform;
+                self.coordinate_systems.insert(text()?, transform);
+            }
+            "CoordSysTransform" => match self.coordinate_systems.get(&text()?).cloned() {
+                Some(transform) => self.set_transform(transform),
+                None => self.warn(line, format!("unknown coordinate system {}", text()?)),
+            },
+            "ActiveTransform" => {
+                self.end_time_only = text()? == "EndTime";
+                if text()? != "All" {
+                    self.warn(line, "motion blur is not supported".to_string());
+                }
+            }
+            "ReverseOrientation" => {
+                self.attributes.reverse_orientation = !self.attributes.reverse_orientation
+            }
+            "Camera" => {
+                let camera_from_world = self.attributes.transform;
+                if let Some(world_from_camera) = camera_from_world.inverse() {
+                    self.coordinate_systems
+                        .insert("camera".to_string(), world_from_camera);
+                }
+                self.camera = Some((text()?, params, camera_from_world));
+            }
+            "Film" => {
+                let size = |name: &str, default: f64| params.number(name).unwrap_or(default);
+                self.resolution = (
+                    size("xresolution", 1280.0).max(1.0) as usize,
+                    size("yresolution", 720.0).max(1.0) as usize,
+                );
+            }
+            "WorldBegin" => {
+                self.attributes.transform = Transform::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Transform::identity());
+            }
+            "WorldEnd" => {}
+            "AttributeBegin" | "TransformBegin" => {
+                self.stack
+                    .push((self.attributes.clone(), name == "TransformBegin"));
+            }
+            "AttributeEnd" | "TransformEnd" => match self.stack.pop() {
+                Some((saved, true)) => self.attributes.transform = saved.transform,
+                Some((saved, false)) => self.attributes = saved,
+                None => self.warn(line, format!("unmatched {}", name)),
+            },
+            "Material" => {
+                let material = self.material(&text()?, &params, line);
+                self.attributes.material = material;
+            }
+            "MakeNamedMaterial" => {
+                let kind = params.text("type").unwrap_or("diffuse").to_string();
+                let material = self.material(&kind, &params, line);
+                self.named_materials.insert(text()?, material);
+            }
+            "NamedMaterial" => match self.named_materials.get(&text()?).cloned() {
+                Some(material) => self.attributes.material = material,
+                None => self.warn(line, format!("unknown material {}", text()?)),
+            },
+            "AreaLightSource" => {
+                if text()? != "diffuse" {
+                    self.warn(line, format!("unsupported area light {}", text()?));
+                }
+                let emit =
+                    self.spectrum(&params, "L", line) * params.number("scale").unwrap_or(1.0);
+                self.attributes.area_light = Some(
+                    DiffuseLight::new(emit).set_two_sided(params.bool("twosided").unwrap_or(false)),
+                );
+            }
+            "LightSource" => match text()?.as_str() {
+                "infinite" => {
+                    if params.get("filename").is_some() {
+                        self.warn(
+                            line,
+                            "environment maps are not supported, using a uniform colour"
+                                .to_string(),
+                        );
+                    }
+                    let colour =
+                        self.spectrum(&params, "L", line) * params.number("scale").unwrap_or(1.0);
+                    self.background = Some(
+                        self.background
+                            .unwrap_or_else(|| Colour::new(0.0, 0.0, 0.0))
+                            + colour,
+                    );
+                }
+                kind => self.warn(
+                    line,
+                    format!(
+                        "{} lights are not supported, only area and infinite ones",
+                        kind
+                    ),
+                ),
+            },
+            "Shape" => self.shape(&text()?, &params, line)?,
+            "Include" | "Import" => {
+                let path = self.directory.join(text()?);
+                let error = |error: io::Error| {
+                    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+                };
+                let included = fs::read_to_string(&path).map_err(error)?;
+                let canonical = fs::canonicalize(&path).map_err(error)?;
+                if self.open_files.contains(&canonical) {
+                    return Err(syntax_error(
+                        line,
+                        &format!("{} includes itself", path.display()),
+                    ));
+                }
+                self.open_files.push(canonical);
+                self.file(&included)?;
+                self.open_files.pop();
+            }
+            "ObjectBegin" => {
+                self.object_depth += 1;
+                self.stack.push((self.attributes.clone(), false));
+                self.warn(line, "object instancing is not supported".to_string());
+            }
+            "ObjectEnd" => {
+                self.object_depth = self.object_depth.saturating_sub(1);
+                if let Some((saved, _)) = self.stack.pop() {
+                    self.attributes = saved;
+                }
+            }
+            "ObjectInstance" => {}
+            _ => self.warn(line, format!("unsupported directive {}", name)),
+        }
+        Ok(())
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        if !self.end_time_only {
+            self.attributes.transform = transform;
+        }
+    }
+
+    fn concat(&mut self, transform: Transform) {
+        if !self.end_time_only {
+            self.attributes.transform = self.attributes.transform.then(&transform);
+        }
+    }
+
+    // An RGB value, or white for anything else.
+    fn spectrum(&mut self, params: &Params, name: &str, line: usize) -> Colour {
+        let param = match params.get(name) {
+            Some(param) => param,
+            None => return Colour::new(1.0, 1.0, 1.0),
+        };
+        match (param.kind.as_str(), params.numbers(name)) {
+            ("rgb" | "color", Some(rgb)) if rgb.len() == 3 => Colour::new(rgb[0], rgb[1], rgb[2]),
+            (kind, _) => {
+                self.warn(line, format!("{} values are treated as white", kind));
+                Colour::new(1.0, 1.0, 1.0)
+            }
+        }
+    }
+
+    // A reflectance, for which textures fall back to `default`.
+    fn reflectance(
+        &mut self,
+        params: &Params,
+        names: &[&str],
+        default: Colour,
+        line: usize,
+    ) -> Colour {
+        match names.iter().find(|name| params.get(name).is_some()) {
+            Some(name) if params.get(name).map(|p| p.kind.as_str()) == Some("texture") => {
+                self.warn(line, "textures are not supported".to_string());
+                default
+            }
+            Some(name) => match params.get(name).map(|p| p.kind.as_str()) {
+                Some("float") => Colour::new(1.0, 1.0, 1.0) * params.number(name).unwrap_or(0.5),
+                _ => self.spectrum(params, name, line),
+            },
+            None => default,
+        }
+    }
+
+    fn material(&mut self, kind: &str, params: &Params, line: usize) -> Option<Materials> {
+        let grey = Colour::new(0.5, 0.5, 0.5);
+        match kind {
+            "diffuse" | "matte" => Some(Materials::Lambertian(Lambertian::new(self.reflectance(
+                params,
+                &["reflectance", "Kd"],
+                grey,
+                line,
+            )))),
+            "conductor" | "metal" => {
+                let colour = match params.text("eta") {
+                    Some(name) => metal_colour(name),
+                    None => self.reflectance(
+                        params,
+                        &["reflectance"],
+                        metal_colour("metal-Cu-eta"),
+                        line,
+                    ),
+                };
+                let roughness = params
+                    .number("roughness")
+                    .or_else(|| params.number("uroughness"))
+                    .unwrap_or(0.0);
+                Some(Materials::Metal(Metal::new(colour, roughness)))
+            }
+            "dielectric" | "glass" | "thindielectric" => {
+                let eta = params
+                    .number("eta")
+                    .or_else(|| params.number("index"))
+                    .unwrap_or(1.5);
+                Some(Materials::Dielectric(Dielectric::new(eta)))
+            }
+            "interface" | "" | "none" => None,
+            _ => {
+                self.warn(
+                    line,
+                    format!("unsupported material {}, using diffuse", kind),
+                );
+                Some(Materials::Lambertian(Lambertian::new(self.reflectance(
+                    params,
+                    &["reflectance", "Kd"],
+                    grey,
+                    line,
+                ))))
+            }
+        }
+    }
+
+    // The material for a new shape, an area light if one is set, or None if
+    // the shape isn't to be drawn.
+    fn shape_material(&self) -> Option<Materials> {
+        match (&self.attributes.area_light, &self.attributes.material) {
+            _ if self.object_depth > 0 => None,
+            (Some(light), _) => Some(Materials::DiffuseLight(light.clone())),
+            (None, material) => material.clone(),
+        }
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params, line: usize) -> io::Result<()> {
+        let material = match self.shape_material() {
+            Some(material) => material,
+            None => return Ok(()),
+        };
+        let transform = mirror().then(&self.attributes.transform);
+        let determinant = transform.determinant();
+        let scale = determinant.abs().cbrt();
+
+        match kind {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"]
+                    .iter()
+                    .any(|name| params.get(name).is_some())
+                {
+                    self.warn(line, "partial spheres are drawn whole".to_string());
+                }
+                if !is_uniform(&transform) {
+                    self.warn(line, "spheres are not scaled unevenly".to_string());
+                }
+                let radius = params.number("radius").unwrap_or(1.0) * scale;
+                let center = transform.point(Point3D::new(0.0, 0.0, 0.0));
+                self.world
+                    .add(Object::Sphere(Sphere::new(center, radius, material)));
+            }
+            "disk" => {
+                if params.get("innerradius").is_some() || params.get("phimax").is_some() {
+                    self.warn(line, "partial disks are drawn whole".to_string());
+                }
+                let height = params.number("height").unwrap_or(0.0);
+                let normal = transform.normal(Vec3::new(0.0, 0.0, 1.0));
+                let normal = match self.attributes.reverse_orientation {
+                    true => -normal,
+                    false => normal,
+                };
+                self.world.add(Object::Disk(Disk::new(
+                    transform.point(Point3D::new(0.0, 0.0, height)),
+                    normal,
+                    params.number("radius").unwrap_or(1.0) * scale,
+                    material,
+                )));
+            }
+            "trianglemesh" => {
+                let points = params
+                    .numbers("P")
+                    .filter(|points| !points.is_empty() && points.len() % 3 == 0)
+                    .ok_or_else(|| syntax_error(line, "trianglemesh needs points P"))?;
+                let positions: Vec<Point3D> = points
+                    .chunks(3)
+                    .map(|p| Point3D::new(p[0], p[1], p[2]))
+                    .collect();
+                let indices: Vec<usize> = match params.numbers("indices") {
+                    Some(indices) => {
+                        if indices
+                            .iter()
+                            .any(|&index| index < 0.0 || index.fract() != 0.0)
+                        {
+                            return Err(syntax_error(
+                                line,
+                                "trianglemesh indices must be whole numbers from 0",
+                            ));
+                        }
+                        indices.iter().map(|&index| index as usize).collect()
+                    }
+                    None if positions.len() == 3 => vec![0, 1, 2],
+                    None => return Err(syntax_error(line, "trianglemesh needs indices")),
+                };
+                let triangles = indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect();
+                let normals = params
+                    .numbers("N")
+                    .map(|n| n.chunks(3).map(|n| Vec3::new(n[0], n[1], n[2])).collect());
+                let uvs = params
+                    .numbers("uv")
+                    .or_else(|| params.numbers("st"))
+                    .map(|uv| uv.chunks(2).map(|uv| (uv[0], uv[1])).collect());
+                self.mesh(positions, triangles, normals, uvs, material, line)?;
+            }
+            "plymesh" => {
+                let filename = params
+                    .text("filename")
+                    .ok_or_else(|| syntax_error(line, "plymesh needs a filename"))?;
+                let path = self.directory.join(filename);
+                let polygons = ply::read(&path).map_err(|error| {
+                    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+                })?;
+                if params.get("displacement").is_some() {
+                    self.warn(line, "plymesh displacement is not supported".to_string());
+                }
+                let triangles = polygons
+                    .faces()
+                    .iter()
+                    .flat_map(|face| {
+                        (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]])
+                    })
+                    .collect();
+                self.mesh(
+                    polygons.positions().to_vec(),
+                    triangles,
+                    polygons.normals().map(<[Vec3<f64>]>::to_vec),
+                    polygons.uvs().map(<[(f64, f64)]>::to_vec),
+                    material,
+                    line,
+                )?;
+            }
+            _ => self.warn(line, format!("unsupported shape {}", kind)),
+        }
+        Ok(())
+    }
+
+    fn mesh(
+        &mut self,
+        positions: Vec<Point3D>,
+        triangles: Vec<[usize; 3]>,
+        normals: Option<Vec<Vec3<f64>>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        material: Materials,
+        line: usize,
+    ) -> io::Result<()> {
+        let count = positions.len();
+        if triangles.is_empty() {
+            return Ok(());
+        }
+        if triangles.iter().flatten().any(|&index| index >= count) {
+            return Err(syntax_error(line, "mesh index refers to a missing vertex"));
+        }
+
+        // Without normals, the winding decides which side is outside, and
+        // both a mirroring transform and ReverseOrientation swap it.
+        let transform = mirror().then(&self.attributes.transform);
+        let flip = (transform.determinant() < 0.0) != self.attributes.reverse_orientation;
+        let triangles = triangles
+            .into_iter()
+            .map(|[a, b, c]| match flip {
+                true => [a, c, b],
+                false => [a, b, c],
+            })
+            .collect();
+        let positions = positions
+            .into_iter()
+            .map(|point| transform.point(point))
+            .collect();
+
+        let mut mesh = Mesh::new(positions, triangles, material);
+        if let Some(normals) = normals.filter(|normals| normals.len() == count) {
+            mesh = mesh.set_normals(
+                normals
+                    .into_iter()
+                    .map(|normal| transform.normal(normal))
+                    .collect(),
+            );
+        }
+        if let Some(uvs) = uvs.filter(|uvs| uvs.len() == count) {
+            mesh = mesh.set_uvs(uvs);
+        }
+        self.world.add(Object::Mesh(mesh));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Pbrt {
+        let (kind, params, camera_from_world) = self.camera.take().unwrap_or_else(|| {
+            (
+                "perspective".to_string(),
+                Params::default(),
+                Transform::identity(),
+            )
+        });
+        let world_from_camera = mirror().then(&camera_from_world.inverse().unwrap_or_default());
+        let origin = world_from_camera.point(Point3D::new(0.0, 0.0, 0.0));
+        let target = world_from_camera.point(Point3D::new(0.0, 0.0, 1.0));
+        let up = world_from_camera.vector(Vec3::new(0.0, 1.0, 0.0));
+        let aspect_ratio = self.resolution.0 as f64 / self.resolution.1 as f64;
+
+        let camera = match kind.as_str() {
+            "orthographic" => {
+                let view_height = match params.numbers("screenwindow") {
+                    Some(window) if window.len() == 4 => window[3] - window[2],
+                    _ => 2.0 / aspect_ratio.min(1.0),
+                };
+                Cameras::Orthographic(Orthographic::new(
+                    aspect_ratio,
+                    view_height,
+                    origin,
+                    target,
+                    up,
+                ))
+            }
+            _ => {
+                if kind != "perspective" {
+                    self.warn(0, format!("{} cameras are drawn as perspective", kind));
+                }
+                // The field of view spans the shorter side of the image.
+                let fov = params.number("fov").unwrap_or(90.0);
+                let vertical_fov = match aspect_ratio < 1.0 {
+                    true => {
+                        2.0 * ((fov.to_radians() / 2.0).tan() / aspect_ratio)
+                            .atan()
+                            .to_degrees()
+                    }
+                    false => fov,
+                };
+                Cameras::Perspective(Camera::new(
+                    aspect_ratio,
+                    vertical_fov,
+                    origin,
+                    target,
+                    up,
+                    2.0 * params.number("lensradius").unwrap_or(0.0),
+                    params.number("focaldistance").unwrap_or(1e6),
+                ))
+            }
+        };
+
+        Pbrt {
+            world: self.world,
+            camera,
+            background: Background::Uniform(
+                self.background
+                    .unwrap_or_else(|| Colour::new(0.0, 0.0, 0.0)),
+            ),
+            resolution: self.resolution,
+            warnings: self.warnings,
+        }
+    }
+}
+
+fn mirror() -> Transform {
+    Transform::scale(-1.0, 1.0, 1.0)
+}
+
+fn is_uniform(transform: &Transform) -> bool {
+    let lengths: Vec<f64> = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|axis| transform.vector(*axis).length())
+    .collect();
+    (lengths[0] - lengths[1]).abs() < 1e-6 * lengths[0]
+        && (lengths[0] - lengths[2]).abs() < 1e-6 * lengths[0]
+}
+
+// PBRT's camera-from-world transform for a camera at `eye` looking at
+// `target`.
+fn look_at(eye: Point3D, target: Point3D, up: Vec3<f64>) -> Option<Transform> {
+    let direction = (target - eye).unit();
+    let right = up.unit().cross(&direction);
+    if right.near_zero() || direction.near_zero() {
+        return None;
+    }
+    let right = right.unit();
+    let up = direction.cross(&right);
+    Transform::from_columns([
+        right.x(),
+        right.y(),
+        right.z(),
+        0.0,
+        up.x(),
+        up.y(),
+        up.z(),
+        0.0,
+        direction.x(),
+        direction.y(),
+        direction.z(),
+        0.0,
+        eye.x(),
+        eye.y(),
+        eye.z(),
+        1.0,
+    ])
+    .inverse()
+}
+
+// Approximate colours for PBRT's named metal spectra, copper otherwise.
+fn metal_colour(name: &str) -> Colour {
+    match name {
+        name if name.contains("-Au-") => Colour::new(1.0, 0.78, 0.34),
+        name if name.contains("-Ag-") => Colour::new(0.97, 0.96, 0.91),
+        name if name.contains("-Al-") => Colour::new(0.91, 0.92, 0.92),
+        name if name.contains("-CuZn-") => Colour::new(0.91, 0.78, 0.42),
+        _ => Colour::new(0.95, 0.64, 0.54),
+    }
+}
+
+// Positional values, then the parameter list, which starts at the first
+// quoted "type name" pair.
+fn split_arguments(arguments: &[Token], line: usize) -> io::Result<(Vec<Value>, Params)> {
+    let is_declaration = |token: &Token| match token {
+        Token::Text(text) => text.split_whitespace().count() == 2,
+        _ => false,
+    };
+    let value = |token: &Token| match token {
+        Token::Text(text) => Some(Value::Text(text.clone())),
+        Token::Number(number) => Some(Value::Number(*number)),
+        Token::Bool(value) => Some(Value::Bool(*value)),
+        _ => None,
+    };
+
+    let start = arguments
+        .iter()
+        .position(is_declaration)
+        .unwrap_or(arguments.len());
+    let values = arguments[..start].iter().filter_map(value).collect();
+
+    let mut params = Vec::new();
+    let mut position = start;
+    while position < arguments.len() {
+        let declaration = match &arguments[position] {
+            Token::Text(text) => text.split_whitespace().collect::<Vec<&str>>(),
+            _ => return Err(syntax_error(line, "expected a parameter")),
+        };
+        position += 1;
+        let end = arguments[position..]
+            .iter()
+            .position(is_declaration)
+            .map_or(arguments.len(), |offset| position + offset);
+        params.push(Param {
+            kind: declaration[0].to_string(),
+            name: declaration[1].to_string(),
+            values: arguments[position..end].iter().filter_map(value).collect(),
+        });
+        position = end;
+    }
+    Ok((values, Params(params)))
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<(Token, usize)>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut position, mut line) = (0, 1);
+    while let Some(&byte) = bytes.get(position) {
+        match byte {
+            b'\n' => {
+                line += 1;
+                position += 1;
+            }
+            _ if byte.is_ascii_whitespace() => position += 1,
+            b'#' => {
+                while !matches!(bytes.get(position), Some(b'\n') | None) {
+                    position += 1;
+                }
+            }
+            b'[' | b']' => {
+                let token = match byte {
+                    b'[' => Token::Open,
+                    _ => Token::Close,
+                };
+                tokens.push((token, line));
+                position += 1;
+            }
+            b'"' => {
+                let start = position + 1;
+                let end = bytes[start..]
+                    .iter()
+                    .position(|&byte| byte == b'"')
+                    .map(|offset| start + offset)
+                    .ok_or_else(|| syntax_error(line, "unterminated string"))?;
+                tokens.push((Token::Text(text[start..end].to_string()), line));
+                line += bytes[start..end]
+                    .iter()
+                    .filter(|&&byte| byte == b'\n')
+                    .count();
+                position = end + 1;
+            }
+            _ => {
+                let start = position;
+                while matches!(bytes.get(position), Some(byte) if !byte.is_ascii_whitespace() && !b"[]\"#".contains(byte))
+                {
+                    position += 1;
+                }
+                let word = &text[start..position];
+                let token = match word {
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => match word.parse() {
+                        Ok(number) => Token::Number(number),
+                        Err(_) if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                            Token::Directive(word.to_string())
+                        }
+                        Err(_) => return Err(syntax_error(line, &format!("unexpected {}", word))),
+                    },
+                };
+                tokens.push((token, line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn syntax_error(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+#[cfg(test)]
+mod pbrt_tests {
+    use super::*;
+    use crate::camera::Projection;
+    use crate::objects::Hit;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_cornell_style_scene() {
+        let pbrt = Pbrt::parse(
+            r#"
+            # A red floor under a square light, seen from above.
+            LookAt 0 5 0  0 0 0  0 0 1
+            Camera "perspective" "float fov" [ 40 ]
+            Film "rgb" "integer xresolution" [ 200 ] "integer yresolution" [ 100 ]
+            Sampler "halton"
+            WorldBegin
+            LightSource "infinite" "rgb L" [ 0.1 0.1 0.1 ]
+            AttributeBegin
+                Material "diffuse" "rgb reflectance" [ 0.8 0.1 0.1 ]
+                Shape "trianglemesh" "point3 P" [ -1 0 -1  1 0 -1  1 0 1  -1 0 1 ]
+                    "integer indices" [ 0 2 1  0 3 2 ]
+                Translate 2 1 0
+                Scale 0.5 0.5 0.5
+                Shape "sphere" "float radius" 2
+            AttributeEnd
+            AttributeBegin
+                AreaLightSource "diffuse" "rgb L" [ 4 4 4 ] "bool twosided" false
+                Translate 0 3 0
+                Rotate 90 1 0 0
+                Shape "disk" "float radius" 0.5
+            AttributeEnd
+            Shape "curve" "point3 P" [ 0 0 0 1 1 1 2 2 2 3 3 3 ]
+            "#,
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!((200, 100), pbrt.resolution());
+        assert_eq!(
+            Background::Uniform(Colour::new(0.1, 0.1, 0.1)),
+            pbrt.background()
+        );
+        assert_eq!(2, pbrt.warnings().len(), "{:?}", pbrt.warnings());
+        assert!(pbrt.warnings()[0].starts_with("line 6: "));
+
+        // The floor faces up, towards the camera and the light.
+        let world = pbrt.world();
+        let down = Ray::new(Point3D::new(0.5, 5.0, 0.2), Vec3::new(0.0, -1.0, 0.0));
+        let floor = world
+            .object(1)
+            .unwrap()
+            .hit(&down, 0.001, f64::INFINITY)
+            .unwrap();
+        assert!(floor.front_face());
+        assert_eq!(Colour::new(0.8, 0.1, 0.1), floor.material().albedo());
+
+        // Translated after mirroring x, and scaled by half.
+        match world.object(2) {
+            Some(Object::Sphere(sphere)) => {
+                assert_eq!(Point3D::new(-2.0, 1.0, 0.0), sphere.center());
+                assert_eq!(1.0, sphere.radius());
+            }
+            _ => panic!("expected a sphere"),
+        }
+
+        // The light faces down after its quarter turn, so it shines on the floor
+        // but not up at the sky.
+        let light = world.object(3).unwrap();
+        let up = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let record = light.hit(&up, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(
+            Colour::new(4.0, 4.0, 4.0),
+            record.material().emitted(&record)
+        );
+        let down = Ray::new(Point3D::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = light.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(
+            Colour::new(0.0, 0.0, 0.0),
+            record.material().emitted(&record)
+        );
+
+        // The file's +x is to the right of this view, which is -x once
+        // mirrored.
+        let camera = match pbrt.camera() {
+            Cameras::Perspective(camera) => camera,
+            _ => panic!("expected a perspective camera"),
+        };
+        assert_eq!(Point3D::new(0.0, 5.0, 0.0), camera.origin());
+        let right = camera.get_ray(0.9, 0.5).direction();
+        assert!(right.x() < 0.0, "{:?}", right);
+    }
+
+    #[test]
+    fn test_trianglemesh_indices() {
+        let triangle = |indices: &str| {
+            format!(
+                "WorldBegin Shape \"trianglemesh\" \"point3 P\" [0 0 0 1 0 0 0 1 0] \
+                 \"integer indices\" [{}] WorldEnd",
+                indices
+            )
+        };
+        let here = Path::new("");
+        assert!(Pbrt::parse(&triangle("0 1 2"), here).is_ok());
+        assert!(Pbrt::parse(&triangle("0 -1 2"), here).is_err());
+        assert!(Pbrt::parse(&triangle("0 0.5 2"), here).is_err());
+    }
+
+    #[test]
+    fn test_include_loop() {
+        let directory = std::env::temp_dir().join("rust_rt_pbrt_includes");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a.pbrt"), "Include \"b.pbrt\"").unwrap();
+        fs::write(directory.join("b.pbrt"), "Include \"a.pbrt\"").unwrap();
+        let error = Pbrt::read(directory.join("a.pbrt")).unwrap_err();
+        assert!(error.to_string().contains("includes itself"), "{}", error);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
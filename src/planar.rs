@@ -0,0 +1,572 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::objects::{Hit, HitRecord};
+use crate::ray::Ray;
+use crate::vec3::{Point3D, Vec3};
+use std::f64::consts::PI;
+
+// Rays closer than this to parallel with a plane miss it.
+const PARALLEL: f64 = 1e-8;
+
+// A parallelogram with a corner at `origin` and edges `u` and `v`. The front
+// face is on the side u x v points to, and the UVs run from 0 to 1 along the
+// two edges.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    origin: Point3D,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    normal: Vec3<f64>,
+    w: Vec3<f64>,
+    material: Materials,
+}
+
+impl Quad {
+    pub fn new(origin: Point3D, u: Vec3<f64>, v: Vec3<f64>, material: Materials) -> Self {
+        let n = u.cross(&v);
+
+        Quad {
+            origin,
+            u,
+            v,
+            normal: n.unit(),
+            w: n / n.length_sqrd(),
+            material,
+        }
+    }
+
+    pub fn origin(&self) -> Point3D {
+        self.origin
+    }
+
+    pub fn u(&self) -> Vec3<f64> {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3<f64> {
+        self.v
+    }
+
+    pub fn normal(&self) -> Vec3<f64> {
+        self.normal
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.origin + (self.u + self.v) / 2.0
+    }
+}
+
+impl Hit for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.origin - ray.origin())) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let planar = point - self.origin;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(alpha, beta)
+                .set_face_normal(ray, &self.normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&[
+            self.origin,
+            self.origin + self.u,
+            self.origin + self.v,
+            self.origin + self.u + self.v,
+        ]))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn of(&self, vec: Vec3<f64>) -> f64 {
+        match self {
+            Axis::X => vec.x(),
+            Axis::Y => vec.y(),
+            Axis::Z => vec.z(),
+        }
+    }
+
+    pub fn unit(&self) -> Vec3<f64> {
+        match self {
+            Axis::X => Vec3::new(1.0, 0.0, 0.0),
+            Axis::Y => Vec3::new(0.0, 1.0, 0.0),
+            Axis::Z => Vec3::new(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+// An axis-aligned rectangle at `k` along its normal axis, spanning [a0, a1]
+// and [b0, b1] along the other two axes in order. The front face looks along
+// the positive normal axis.
+#[derive(Debug, Clone)]
+pub struct Rect {
+    normal: Axis,
+    a: (f64, f64),
+    b: (f64, f64),
+    k: f64,
+    material: Materials,
+}
+
+impl Rect {
+    pub fn xy(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Materials) -> Self {
+        Rect::new(Axis::Z, (x0, x1), (y0, y1), k, material)
+    }
+
+    pub fn xz(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Materials) -> Self {
+        Rect::new(Axis::Y, (x0, x1), (z0, z1), k, material)
+    }
+
+    pub fn yz(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Materials) -> Self {
+        Rect::new(Axis::X, (y0, y1), (z0, z1), k, material)
+    }
+
+    fn new(normal: Axis, a: (f64, f64), b: (f64, f64), k: f64, material: Materials) -> Self {
+        Rect {
+            normal,
+            a: (a.0.min(a.1), a.0.max(a.1)),
+            b: (b.0.min(b.1), b.0.max(b.1)),
+            k,
+            material,
+        }
+    }
+
+    // The two in-plane axes, in the order of `a` and `b`.
+    fn axes(&self) -> (Axis, Axis) {
+        match self.normal {
+            Axis::X => (Axis::Y, Axis::Z),
+            Axis::Y => (Axis::X, Axis::Z),
+            Axis::Z => (Axis::X, Axis::Y),
+        }
+    }
+
+    fn point(&self, a: f64, b: f64) -> Point3D {
+        let (a_axis, b_axis) = self.axes();
+        a_axis.unit() * a + b_axis.unit() * b + self.normal.unit() * self.k
+    }
+
+    pub fn normal(&self) -> Axis {
+        self.normal
+    }
+
+    // The spans along the two in-plane axes, in order, and the offset along
+    // the normal axis.
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64), f64) {
+        (self.a, self.b, self.k)
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.point((self.a.0 + self.a.1) / 2.0, (self.b.0 + self.b.1) / 2.0)
+    }
+}
+
+impl Hit for Rect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let direction = self.normal.of(ray.direction());
+        if direction.abs() < PARALLEL {
+            return None;
+        }
+
+        let t = (self.k - self.normal.of(ray.origin())) / direction;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let (a_axis, b_axis) = self.axes();
+        let (a, b) = (a_axis.of(point), b_axis.of(point));
+        if a < self.a.0 || a > self.a.1 || b < self.b.0 || b > self.b.1 {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(
+                    (a - self.a.0) / (self.a.1 - self.a.0),
+                    (b - self.b.0) / (self.b.1 - self.b.0),
+                )
+                .set_face_normal(ray, &self.normal.unit()),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.point(self.a.0, self.b.0),
+            self.point(self.a.1, self.b.1),
+        ))
+    }
+}
+
+// A flat disk facing along `normal`. The UVs are polar: u is the angle
+// around the centre and v the distance from it, both from 0 to 1.
+#[derive(Debug, Clone)]
+pub struct Disk {
+    center: Point3D,
+    normal: Vec3<f64>,
+    radius: f64,
+    tangent: Vec3<f64>,
+    bitangent: Vec3<f64>,
+    material: Materials,
+}
+
+impl Disk {
+    pub fn new(center: Point3D, normal: Vec3<f64>, radius: f64, material: Materials) -> Self {
+        let normal = normal.unit();
+        let (tangent, bitangent) = tangent_frame(normal);
+
+        Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.center
+    }
+
+    pub fn normal(&self) -> Vec3<f64> {
+        self.normal
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+}
+
+impl Hit for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.center - ray.origin())) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return None;
+        }
+
+        let angle = offset.dot(&self.bitangent).atan2(offset.dot(&self.tangent));
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv((angle / (2.0 * PI)).rem_euclid(1.0), distance / self.radius)
+                .set_face_normal(ray, &self.normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let half = Vec3::new(
+            extent(self.normal.x()),
+            extent(self.normal.y()),
+            extent(self.normal.z()),
+        );
+
+        Some(Aabb::new(self.center - half, self.center + half))
+    }
+}
+
+// An infinite plane through `point`. It has no bounding box, so anything
+// that partitions space by bounds has to keep planes aside and test them
+// separately. The UVs are planar, tiling once every `uv_scale` units along
+// two axes in the plane.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    point: Point3D,
+    normal: Vec3<f64>,
+    tangent: Vec3<f64>,
+    bitangent: Vec3<f64>,
+    uv_scale: f64,
+    material: Materials,
+}
+
+impl Plane {
+    pub fn new(point: Point3D, normal: Vec3<f64>, material: Materials) -> Self {
+        let normal = normal.unit();
+        let (tangent, bitangent) = tangent_frame(normal);
+
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            uv_scale: 1.0,
+            material,
+        }
+    }
+
+    pub fn set_uv_scale(mut self, uv_scale: f64) -> Self {
+        self.uv_scale = uv_scale;
+        self
+    }
+
+    pub fn point(&self) -> Point3D {
+        self.point
+    }
+
+    pub fn normal(&self) -> Vec3<f64> {
+        self.normal
+    }
+
+    pub fn uv_scale(&self) -> f64 {
+        self.uv_scale
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+}
+
+impl Hit for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.point - ray.origin())) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let offset = point - self.point;
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(
+                    (offset.dot(&self.tangent) / self.uv_scale).rem_euclid(1.0),
+                    (offset.dot(&self.bitangent) / self.uv_scale).rem_euclid(1.0),
+                )
+                .set_face_normal(ray, &self.normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// A box between two opposite corners, made of six outward-facing quads.
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    min: Point3D,
+    max: Point3D,
+    sides: Vec<Quad>,
+}
+
+impl Cuboid {
+    pub fn new(a: Point3D, b: Point3D, material: Materials) -> Self {
+        let bounds = Aabb::new(a, b);
+        let (min, max) = (bounds.min(), bounds.max());
+
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        let side =
+            |origin: Point3D, u: Vec3<f64>, v: Vec3<f64>| Quad::new(origin, u, v, material.clone());
+        let sides = vec![
+            side(Point3D::new(min.x(), min.y(), max.z()), dx, dy),
+            side(Point3D::new(max.x(), min.y(), max.z()), -dz, dy),
+            side(Point3D::new(max.x(), min.y(), min.z()), -dx, dy),
+            side(Point3D::new(min.x(), min.y(), min.z()), dz, dy),
+            side(Point3D::new(min.x(), max.y(), max.z()), dx, -dz),
+            side(Point3D::new(min.x(), min.y(), min.z()), dx, dz),
+        ];
+
+        Cuboid { min, max, sides }
+    }
+
+    pub fn min(&self) -> Point3D {
+        self.min
+    }
+
+    pub fn max(&self) -> Point3D {
+        self.max
+    }
+
+    pub fn material(&self) -> &Materials {
+        self.sides[0].material()
+    }
+
+    pub fn center(&self) -> Point3D {
+        (self.min + self.max) / 2.0
+    }
+}
+
+impl Hit for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+
+        for side in &self.sides {
+            if let Some(record) = side.hit(ray, t_min, closest_so_far) {
+                closest_so_far = record.time();
+                hit_record = Some(record);
+            }
+        }
+        hit_record
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+// Two unit vectors perpendicular to `normal` and each other.
+pub(crate) fn tangent_frame(normal: Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+    let helper = match normal.x().abs() > 0.9 {
+        true => Vec3::new(0.0, 1.0, 0.0),
+        false => Vec3::new(1.0, 0.0, 0.0),
+    };
+    let tangent = helper.cross(&normal).unit();
+    (tangent, normal.cross(&tangent))
+}
+
+#[cfg(test)]
+mod planar_tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::objects::{Object, SceneObjects};
+
+    #[test]
+    fn test_quad_and_rect_agree() {
+        let quad = Quad::new(
+            Point3D::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            grey(),
+        );
+        let rect = Rect::xy(-1.0, 1.0, -1.0, 3.0, -2.0, grey());
+        let ray = Ray::new(Point3D::new(0.5, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        for record in [quad.hit(&ray, 0.001, 10.0), rect.hit(&ray, 0.001, 10.0)].iter() {
+            let record = record.as_ref().unwrap();
+            assert_eq!(2.0, record.time());
+            assert_eq!((0.75, 0.75), record.uv());
+            assert!(record.front_face());
+            assert_eq!(Vec3::new(0.0, 0.0, 1.0), record.normal());
+        }
+
+        let beside = Ray::new(Point3D::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&beside, 0.001, 10.0).is_none());
+        assert!(rect.hit(&beside, 0.001, 10.0).is_none());
+        assert_eq!(quad.bounding_box().unwrap(), rect.bounding_box().unwrap());
+    }
+
+    #[test]
+    fn test_cuboid_faces_outwards() {
+        let cuboid = Cuboid::new(
+            Point3D::new(1.0, 1.0, 1.0),
+            Point3D::new(-1.0, -1.0, -1.0),
+            grey(),
+        );
+
+        let outside = Ray::new(Point3D::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = cuboid.hit(&outside, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(4.0, record.time());
+        assert!(record.front_face());
+        assert_eq!(Vec3::new(0.0, 1.0, 0.0), record.normal());
+
+        let inside = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = cuboid.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(1.0, record.time());
+        assert!(!record.front_face());
+        assert_eq!(Vec3::new(-1.0, 0.0, 0.0), record.normal());
+    }
+
+    #[test]
+    fn test_disk_uv() {
+        let disk = Disk::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            grey(),
+        );
+        let ray = Ray::new(Point3D::new(1.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = disk.hit(&ray, 0.001, 10.0).unwrap();
+        assert_eq!(0.5, record.uv().1);
+
+        let outside = Ray::new(Point3D::new(1.5, 1.0, 1.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(&outside, 0.001, 10.0).is_none());
+        let bounds = disk.bounding_box().unwrap();
+        assert!((bounds.max() - Point3D::new(2.0, 0.0, 2.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_plane_is_unbounded() {
+        let plane = Plane::new(
+            Point3D::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+        )
+        .set_uv_scale(2.0);
+        assert!(plane.bounding_box().is_none());
+
+        let far = Ray::new(Point3D::new(1e6, 0.0, -3.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = plane.hit(&far, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(1.0, record.time());
+        assert_eq!(Vec3::new(0.0, 1.0, 0.0), record.normal());
+        let (u, v) = record.uv();
+        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+
+        let parallel = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&parallel, 0.001, f64::INFINITY).is_none());
+
+        let mut world = SceneObjects::new();
+        world.add(Object::Plane(plane));
+        assert!(world.bounding_box().is_none());
+    }
+}
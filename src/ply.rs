@@ -0,0 +1,358 @@
+use crate::framebuffer::invalid_data;
+use crate::material::Materials;
+use crate::objects::SceneObjects;
+use crate::subdivision::PolygonMesh;
+use crate::vec3::{Point3D, Vec3};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Scalar> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(invalid_data("unknown PLY property type")),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    scalar: Scalar,
+    // The type of the length, for list properties.
+    count: Option<Scalar>,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads a PLY file in ASCII or either binary byte order. Vertices need x, y
+// and z, and may have normals (nx, ny, nz) and UVs under any of the usual
+// names; faces may be any polygon, and there must be at least one. Other
+// elements and properties, such as vertex colours, are skipped.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<PolygonMesh> {
+    from_bytes(&fs::read(path)?)
+}
+
+// The file as one mesh in the given material, ready to add to a scene. PLY
+// files don't describe materials of their own.
+pub fn read_objects<P: AsRef<Path>>(path: P, material: Materials) -> io::Result<SceneObjects> {
+    read(path)?.into_objects(material)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> io::Result<PolygonMesh> {
+    let (format, elements, data) = header(bytes)?;
+    let mut reader = Reader {
+        format,
+        data,
+        position: 0,
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        let names: Vec<&str> = element.properties.iter().map(|p| p.name.as_str()).collect();
+        let find = |options: &[&str]| names.iter().position(|name| options.contains(name));
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            let mut list = Vec::new();
+            for (index, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    Some(count) => {
+                        let length = reader.scalar(count)? as usize;
+                        let items = (0..length)
+                            .map(|_| reader.scalar(property.scalar))
+                            .collect::<io::Result<Vec<f64>>>()?;
+                        if Some(index) == indices {
+                            list = items;
+                        }
+                        values.push(0.0);
+                    }
+                    None => values.push(reader.scalar(property.scalar)?),
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = position.map(|index| index.map(|index| values[index]));
+                    let (x, y, z) = match (x, y, z) {
+                        (Some(x), Some(y), Some(z)) => (x, y, z),
+                        _ => return Err(invalid_data("PLY vertices need x, y and z")),
+                    };
+                    positions.push(Point3D::new(x, y, z));
+                    if let [Some(nx), Some(ny), Some(nz)] = normal {
+                        normals.push(Vec3::new(values[nx], values[ny], values[nz]));
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        uvs.push((values[u], values[v]));
+                    }
+                }
+                "face" => faces.push(
+                    list.iter()
+                        .map(|&index| match index >= 0.0 && index.fract() == 0.0 {
+                            true => Ok(index as usize),
+                            false => Err(invalid_data("PLY face index isn't a whole number")),
+                        })
+                        .collect::<io::Result<_>>()?,
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    let faces: Vec<Vec<usize>> = faces
+        .into_iter()
+        .filter(|face: &Vec<usize>| face.len() >= 3)
+        .collect();
+    if faces
+        .iter()
+        .flatten()
+        .any(|&index| index >= positions.len())
+    {
+        return Err(invalid_data("PLY face refers to a missing vertex"));
+    }
+    if faces.is_empty() {
+        return Err(invalid_data("PLY file has no faces"));
+    }
+    let mut mesh = PolygonMesh::new(positions, faces);
+    if !normals.is_empty() {
+        mesh = mesh.set_normals(normals);
+    }
+    if !uvs.is_empty() {
+        mesh = mesh.set_uvs(uvs);
+    }
+    Ok(mesh)
+}
+
+// The format and elements from the header, and the data following it.
+fn header(bytes: &[u8]) -> io::Result<(Format, Vec<Element>, &[u8])> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut first = true;
+
+    loop {
+        let end = bytes[position..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| invalid_data("truncated PLY header"))?;
+        let line = String::from_utf8_lossy(&bytes[position..position + end]).into_owned();
+        position += end + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        if first {
+            if words != ["ply"] {
+                return Err(invalid_data("not a PLY file"));
+            }
+            first = false;
+            continue;
+        }
+        match words.as_slice() {
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(invalid_data("unknown PLY format")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("invalid PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, scalar, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    scalar: Scalar::parse(scalar)?,
+                    count: Some(Scalar::parse(count)?),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property before any element"))?
+                    .properties
+                    .push(property);
+            }
+            ["property", scalar, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    scalar: Scalar::parse(scalar)?,
+                    count: None,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property before any element"))?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("PLY header has no format"))?;
+    Ok((format, elements, &bytes[position..]))
+}
+
+struct Reader<'a> {
+    format: Format,
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn scalar(&mut self, scalar: Scalar) -> io::Result<f64> {
+        match self.format {
+            Format::Ascii => {
+                while matches!(self.data.get(self.position), Some(byte) if byte.is_ascii_whitespace())
+                {
+                    self.position += 1;
+                }
+                let start = self.position;
+                while matches!(self.data.get(self.position), Some(byte) if !byte.is_ascii_whitespace())
+                {
+                    self.position += 1;
+                }
+                std::str::from_utf8(&self.data[start..self.position])
+                    .ok()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| invalid_data("invalid number in PLY data"))
+            }
+            format => {
+                let size = scalar.size();
+                let bytes = self
+                    .data
+                    .get(self.position..self.position + size)
+                    .ok_or_else(|| invalid_data("truncated PLY data"))?;
+                self.position += size;
+
+                let mut word = [0u8; 8];
+                word[..size].copy_from_slice(bytes);
+                if format == Format::BigEndian {
+                    word[..size].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::I8 => word[0] as i8 as f64,
+                    Scalar::U8 => word[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([word[0], word[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([word[0], word[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(word),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ply_tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary_agree() {
+        let ascii = from_bytes(
+            b"ply\nformat ascii 1.0\ncomment a unit quad\nelement vertex 4\n\
+              property float x\nproperty float y\nproperty float z\n\
+              property uchar red\nproperty float s\nproperty float t\n\
+              element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+              0 0 0 255 0 0\n1 0 0 255 1 0\n1 1 0 255 1 1\n0 1 0 255 0 1\n4 0 1 2 3\n",
+        )
+        .unwrap();
+        assert_eq!(&[vec![0, 1, 2, 3]], ascii.faces());
+        assert_eq!(Point3D::new(1.0, 1.0, 0.0), ascii.positions()[2]);
+        assert_eq!(Some((1.0, 1.0)), ascii.uvs().map(|uvs| uvs[2]));
+
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 4\n\
+              property double x\nproperty double y\nproperty double z\n\
+              property uchar red\nproperty float s\nproperty float t\n\
+              element face 1\nproperty list uchar int vertex_indices\nend_header\n"
+            .to_vec();
+        for (position, uv) in ascii.positions().iter().zip(ascii.uvs().unwrap()) {
+            for value in &[position.x(), position.y(), position.z()] {
+                binary.extend_from_slice(&value.to_be_bytes());
+            }
+            binary.push(255);
+            binary.extend_from_slice(&(uv.0 as f32).to_be_bytes());
+            binary.extend_from_slice(&(uv.1 as f32).to_be_bytes());
+        }
+        binary.push(4);
+        for index in 0..4i32 {
+            binary.extend_from_slice(&index.to_be_bytes());
+        }
+        assert_eq!(ascii, from_bytes(&binary).unwrap());
+
+        assert!(from_bytes(&binary[..binary.len() - 2]).is_err());
+
+        // Points without faces can't be drawn.
+        let points = b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+              property float y\nproperty float z\nend_header\n0 0 0\n";
+        assert!(from_bytes(points).is_err());
+
+        // Indices are read as numbers, so negative and fractional ones have to
+        // be turned away rather than rounded to a vertex.
+        for face in &["3 0 1 -1", "3 0 1 1.5"] {
+            let text = format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                 property float y\nproperty float z\nelement face 1\n\
+                 property list uchar float vertex_indices\nend_header\n\
+                 0 0 0\n1 0 0\n0 1 0\n{}\n",
+                face
+            );
+            assert!(from_bytes(text.as_bytes()).is_err(), "{}", face);
+        }
+    }
+}
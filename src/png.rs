@@ -0,0 +1,128 @@
+use crate::utils::clamp;
+use crate::vec3::Colour;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// The largest payload of a stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK: usize = 65535;
+
+// Writes an 8-bit RGB PNG. Colours are expected to be display-ready, already
+// scaled and gamma corrected into [0, 1) as by `Colour::write_colour`. As
+// with checkpoints, the file is written alongside and renamed into place, so
+// an interrupted write never leaves a truncated image under the final name.
+pub fn write<P: AsRef<Path>>(
+    path: P,
+    width: i32,
+    height: i32,
+    pixels: &[Colour],
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, encode(width, height, pixels))?;
+    fs::rename(tmp_path, path)
+}
+
+pub fn encode(width: i32, height: i32, pixels: &[Colour]) -> Vec<u8> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let byte = |value: f64| clamp(256.0 * value, 0.0, 255.0) as u8;
+
+    // Each scanline starts with its filter type, which is always None here.
+    let mut raw = Vec::with_capacity(pixels.len() * 3 + height as usize);
+    for row in pixels.chunks(width as usize) {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(&[byte(pixel.x()), byte(pixel.y()), byte(pixel.z())]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolour, deflate, adaptive filtering, no interlace.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of stored deflate blocks. Rendered frames are noisy enough
+// that compression buys little, and it keeps the encoder dependency-free.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod png_tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_layout() {
+        let pixels = vec![Colour::new(0.5, 0.0, 0.999); 6];
+        let png = encode(3, 2, &pixels);
+
+        assert_eq!(&SIGNATURE[..], &png[..8]);
+        assert_eq!(&b"IHDR"[..], &png[12..16]);
+        assert_eq!(&[0, 0, 0, 3, 0, 0, 0, 2][..], &png[16..24]);
+
+        // The empty IEND chunk always has the same CRC.
+        let end = &png[png.len() - 12..];
+        assert_eq!(
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82][..],
+            end
+        );
+        assert_eq!(0x11e6_0398, adler32(b"Wikipedia"));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::framebuffer::invalid_data;
+use crate::utils::clamp;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A single-channel image with values normalised to [0, 1], stored top row
+// first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreyImage {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl GreyImage {
+    pub fn new(width: usize, height: usize, values: Vec<f64>) -> Self {
+        assert_eq!(values.len(), width * height);
+        GreyImage {
+            width,
+            height,
+            values,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f64 {
+        self.values[y * self.width + x]
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    // Bilinearly filtered, with texture coordinates running from 0 to 1
+    // across the image and v = 0 along the bottom row. Coordinates outside
+    // that are clamped to the edges.
+    pub fn sample(&self, u: f64, v: f64) -> f64 {
+        let x = clamp(u, 0.0, 1.0) * (self.width - 1) as f64;
+        let y = (1.0 - clamp(v, 0.0, 1.0)) * (self.height - 1) as f64;
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        let top = (1.0 - fx) * self.get(x0, y0) + fx * self.get(x1, y0);
+        let bottom = (1.0 - fx) * self.get(x0, y1) + fx * self.get(x1, y1);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    // Reads ASCII or binary PGM/PPM files (P2, P3, P5, P6) with 8 or 16 bits
+    // per sample. Colour images are converted to luminance.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        GreyImage::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut position = 0;
+        let magic = header_token(bytes, &mut position)?;
+        let width: usize = parse_token(header_token(bytes, &mut position)?)?;
+        let height: usize = parse_token(header_token(bytes, &mut position)?)?;
+        let max_value: u32 = parse_token(header_token(bytes, &mut position)?)?;
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid_data("invalid PNM maximum value"));
+        }
+
+        let channels = match magic.as_str() {
+            "P2" | "P5" => 1,
+            "P3" | "P6" => 3,
+            _ => return Err(invalid_data("unsupported PNM format")),
+        };
+        let count = width * height * channels;
+
+        let samples: Vec<u32> = match magic.as_str() {
+            "P2" | "P3" => (0..count)
+                .map(|_| parse_token(header_token(bytes, &mut position)?))
+                .collect::<io::Result<Vec<u32>>>()?,
+            _ => {
+                // A single whitespace byte separates the header from the data.
+                position += 1;
+                let sample_size = if max_value > 255 { 2 } else { 1 };
+                let data = bytes
+                    .get(position..position + count * sample_size)
+                    .ok_or_else(|| invalid_data("truncated PNM data"))?;
+
+                match sample_size {
+                    1 => data.iter().map(|byte| *byte as u32).collect(),
+                    _ => data
+                        .chunks(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32)
+                        .collect(),
+                }
+            }
+        };
+
+        let scale = 1.0 / max_value as f64;
+        let values = samples
+            .chunks(channels)
+            .map(|pixel| match pixel {
+                [r, g, b] => (0.2126 * *r as f64 + 0.7152 * *g as f64 + 0.0722 * *b as f64) * scale,
+                _ => pixel[0] as f64 * scale,
+            })
+            .collect();
+
+        Ok(GreyImage::new(width, height, values))
+    }
+}
+
+fn header_token(bytes: &[u8], position: &mut usize) -> io::Result<String> {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while !matches!(bytes.get(*position), Some(b'\n') | None) {
+                    *position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("truncated PNM header")),
+        }
+    }
+
+    let start = *position;
+    while matches!(bytes.get(*position), Some(byte) if !byte.is_ascii_whitespace()) {
+        *position += 1;
+    }
+
+    Ok(String::from_utf8_lossy(&bytes[start..*position]).into_owned())
+}
+
+fn parse_token<T: std::str::FromStr>(token: String) -> io::Result<T> {
+    token
+        .parse()
+        .map_err(|_| invalid_data("invalid number in PNM file"))
+}
+
+#[cfg(test)]
+mod pnm_tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary_agree() {
+        let ascii = GreyImage::from_bytes(b"P2\n# comment\n2 2\n255\n0 51\n255 102\n").unwrap();
+
+        let mut binary = b"P5 2 2 65535\n".to_vec();
+        for value in &[0u16, 13107, 65535, 26214] {
+            binary.extend_from_slice(&value.to_be_bytes());
+        }
+        let binary = GreyImage::from_bytes(&binary).unwrap();
+
+        assert_eq!((2, 2), (binary.width(), binary.height()));
+        for (index, expected) in [0.0, 0.2, 1.0, 0.4].iter().enumerate() {
+            assert!((ascii.values()[index] - expected).abs() < 1e-12);
+            assert!((binary.values()[index] - expected).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,804 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::objects::{Hit, HitRecord};
+use crate::planar::tangent_frame;
+use crate::ray::Ray;
+use crate::vec3::{Point3D, Vec3};
+use std::f64::consts::PI;
+
+// Rays closer than this to parallel with a cap, or to a quadric's asymptotic
+// directions, are treated as such.
+const PARALLEL: f64 = 1e-8;
+const BISECTIONS: usize = 80;
+
+// An orthonormal frame with the shape's axis as local y, so every shape here
+// can be solved about the y axis and moved anywhere without scaling.
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    origin: Point3D,
+    x: Vec3<f64>,
+    y: Vec3<f64>,
+    z: Vec3<f64>,
+}
+
+impl Frame {
+    fn new(origin: Point3D, axis: Vec3<f64>) -> Self {
+        let y = axis.unit();
+        let (z, x) = tangent_frame(y);
+        Frame { origin, x, y, z }
+    }
+
+    fn local_vector(&self, vec: Vec3<f64>) -> Vec3<f64> {
+        Vec3::new(vec.dot(&self.x), vec.dot(&self.y), vec.dot(&self.z))
+    }
+
+    fn world_vector(&self, vec: Vec3<f64>) -> Vec3<f64> {
+        vec.x() * self.x + vec.y() * self.y + vec.z() * self.z
+    }
+
+    fn local_ray(&self, ray: &Ray) -> (Vec3<f64>, Vec3<f64>) {
+        (
+            self.local_vector(ray.origin() - self.origin),
+            self.local_vector(ray.direction()),
+        )
+    }
+
+    // World bounds of a box given in local coordinates.
+    fn bounds(&self, min: Vec3<f64>, max: Vec3<f64>) -> Aabb {
+        let mut corners = Vec::with_capacity(8);
+        for &x in &[min.x(), max.x()] {
+            for &y in &[min.y(), max.y()] {
+                for &z in &[min.z(), max.z()] {
+                    corners.push(self.origin + self.world_vector(Vec3::new(x, y, z)));
+                }
+            }
+        }
+        Aabb::from_points(&corners)
+    }
+}
+
+// A surface of revolution about the local y axis whose squared radius is a
+// quadratic in height, r² = a y² + b y + c, for heights in [y0, y1]. The
+// cylinder, cone, paraboloid and hyperboloid are all special cases. Caps are
+// flat disks wherever an end has a non-zero radius.
+#[derive(Debug, Clone)]
+struct Revolution {
+    frame: Frame,
+    coeffs: (f64, f64, f64),
+    heights: (f64, f64),
+    capped: bool,
+    material: Materials,
+}
+
+impl Revolution {
+    fn radius_sqrd(&self, y: f64) -> f64 {
+        let (a, b, c) = self.coeffs;
+        (a * y + b) * y + c
+    }
+
+    fn max_radius(&self) -> f64 {
+        let (a, b, _) = self.coeffs;
+        let (y0, y1) = self.heights;
+        let mut widest = self.radius_sqrd(y0).max(self.radius_sqrd(y1));
+        if a != 0.0 {
+            let vertex = -b / (2.0 * a);
+            if y0 < vertex && vertex < y1 {
+                widest = widest.max(self.radius_sqrd(vertex));
+            }
+        }
+        widest.max(0.0).sqrt()
+    }
+
+    fn center(&self) -> Point3D {
+        let (y0, y1) = self.heights;
+        self.frame.origin + self.frame.y * ((y0 + y1) / 2.0)
+    }
+
+    // The closest hit in local coordinates, as (t, outward normal, uv).
+    fn local_hit(
+        &self,
+        o: Vec3<f64>,
+        d: Vec3<f64>,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, Vec3<f64>, (f64, f64))> {
+        let (a, b, _) = self.coeffs;
+        let (y0, y1) = self.heights;
+        let mut closest = None;
+        let mut t_max = t_max;
+
+        let side = quadratic_roots(
+            d.x() * d.x() + d.z() * d.z() - a * d.y() * d.y(),
+            2.0 * (o.x() * d.x() + o.z() * d.z() - a * o.y() * d.y()) - b * d.y(),
+            o.x() * o.x() + o.z() * o.z() - self.radius_sqrd(o.y()),
+            d.length_sqrd(),
+        );
+        for t in side {
+            let p = o + t * d;
+            if t < t_min || t_max < t || p.y() < y0 || y1 < p.y() {
+                continue;
+            }
+            let normal = Vec3::new(p.x(), -(a * p.y() + b / 2.0), p.z());
+            closest = Some((t, normal, (angle_uv(p), (p.y() - y0) / (y1 - y0))));
+            t_max = t;
+        }
+
+        if self.capped && d.y().abs() > PARALLEL {
+            for &(y, facing) in &[(y0, -1.0), (y1, 1.0)] {
+                let radius_sqrd = self.radius_sqrd(y);
+                let t = (y - o.y()) / d.y();
+                if radius_sqrd <= 0.0 || t < t_min || t_max < t {
+                    continue;
+                }
+                let p = o + t * d;
+                let distance_sqrd = p.x() * p.x() + p.z() * p.z();
+                if distance_sqrd > radius_sqrd {
+                    continue;
+                }
+                let v = (distance_sqrd / radius_sqrd).sqrt();
+                closest = Some((t, Vec3::new(0.0, facing, 0.0), (angle_uv(p), v)));
+                t_max = t;
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius = self.max_radius();
+        let (y0, y1) = self.heights;
+        self.frame.bounds(
+            Vec3::new(-radius, y0, -radius),
+            Vec3::new(radius, y1, radius),
+        )
+    }
+}
+
+impl Hit for Revolution {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(ray);
+        let (t, normal, (u, v)) = self.local_hit(o, d, t_min, t_max)?;
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(ray.at(t))
+                .set_uv(u, v)
+                .set_face_normal(ray, &self.frame.world_vector(normal).unit()),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Revolution::bounding_box(self))
+    }
+}
+
+// A cylinder from `base` to `base + axis`. It is capped unless
+// `set_capped(false)` opens it into a tube. The side's UVs run around the
+// axis and along it; the caps' are polar as on a disk.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    radius: f64,
+    surface: Revolution,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3D, axis: Vec3<f64>, radius: f64, material: Materials) -> Self {
+        Cylinder {
+            radius,
+            surface: Revolution {
+                frame: Frame::new(base, axis),
+                coeffs: (0.0, 0.0, radius * radius),
+                heights: (0.0, axis.length()),
+                capped: true,
+                material,
+            },
+        }
+    }
+
+    pub fn set_capped(mut self, capped: bool) -> Self {
+        self.surface.capped = capped;
+        self
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f64 {
+        self.surface.heights.1
+    }
+
+    pub fn base(&self) -> Point3D {
+        self.surface.frame.origin
+    }
+
+    pub fn axis(&self) -> Vec3<f64> {
+        self.surface.frame.y * self.surface.heights.1
+    }
+
+    pub fn capped(&self) -> bool {
+        self.surface.capped
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.surface.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.surface.center()
+    }
+}
+
+// A cone with a base of `radius` at `base` and its apex at `base + axis`.
+#[derive(Debug, Clone)]
+pub struct Cone {
+    radius: f64,
+    surface: Revolution,
+}
+
+impl Cone {
+    pub fn new(base: Point3D, axis: Vec3<f64>, radius: f64, material: Materials) -> Self {
+        let height = axis.length();
+        let slope = (radius / height) * (radius / height);
+
+        Cone {
+            radius,
+            surface: Revolution {
+                frame: Frame::new(base, axis),
+                coeffs: (slope, -2.0 * slope * height, slope * height * height),
+                heights: (0.0, height),
+                capped: true,
+                material,
+            },
+        }
+    }
+
+    pub fn set_capped(mut self, capped: bool) -> Self {
+        self.surface.capped = capped;
+        self
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f64 {
+        self.surface.heights.1
+    }
+
+    pub fn base(&self) -> Point3D {
+        self.surface.frame.origin
+    }
+
+    pub fn axis(&self) -> Vec3<f64> {
+        self.surface.frame.y * self.surface.heights.1
+    }
+
+    pub fn capped(&self) -> bool {
+        self.surface.capped
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.surface.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.surface.center()
+    }
+}
+
+// A paraboloid with its vertex at `vertex`, opening along `axis` to a rim of
+// `radius` at `vertex + axis`.
+#[derive(Debug, Clone)]
+pub struct Paraboloid {
+    radius: f64,
+    surface: Revolution,
+}
+
+impl Paraboloid {
+    pub fn new(vertex: Point3D, axis: Vec3<f64>, radius: f64, material: Materials) -> Self {
+        let height = axis.length();
+
+        Paraboloid {
+            radius,
+            surface: Revolution {
+                frame: Frame::new(vertex, axis),
+                coeffs: (0.0, radius * radius / height, 0.0),
+                heights: (0.0, height),
+                capped: true,
+                material,
+            },
+        }
+    }
+
+    pub fn set_capped(mut self, capped: bool) -> Self {
+        self.surface.capped = capped;
+        self
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f64 {
+        self.surface.heights.1
+    }
+
+    pub fn base(&self) -> Point3D {
+        self.surface.frame.origin
+    }
+
+    pub fn axis(&self) -> Vec3<f64> {
+        self.surface.frame.y * self.surface.heights.1
+    }
+
+    pub fn capped(&self) -> bool {
+        self.surface.capped
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.surface.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.surface.center()
+    }
+}
+
+// A hyperboloid of one sheet from `base` to `base + axis`, with its narrowest
+// `waist_radius` halfway along and `end_radius` at both ends. An end radius
+// smaller than the waist bulges outwards instead.
+#[derive(Debug, Clone)]
+pub struct Hyperboloid {
+    waist_radius: f64,
+    end_radius: f64,
+    surface: Revolution,
+}
+
+impl Hyperboloid {
+    pub fn new(
+        base: Point3D,
+        axis: Vec3<f64>,
+        waist_radius: f64,
+        end_radius: f64,
+        material: Materials,
+    ) -> Self {
+        let height = axis.length();
+        let (waist_sqrd, end_sqrd) = (waist_radius * waist_radius, end_radius * end_radius);
+        let a = 4.0 * (end_sqrd - waist_sqrd) / (height * height);
+
+        Hyperboloid {
+            waist_radius,
+            end_radius,
+            surface: Revolution {
+                frame: Frame::new(base, axis),
+                coeffs: (a, -a * height, a * height * height / 4.0 + waist_sqrd),
+                heights: (0.0, height),
+                capped: true,
+                material,
+            },
+        }
+    }
+
+    pub fn set_capped(mut self, capped: bool) -> Self {
+        self.surface.capped = capped;
+        self
+    }
+
+    pub fn waist_radius(&self) -> f64 {
+        self.waist_radius
+    }
+
+    pub fn end_radius(&self) -> f64 {
+        self.end_radius
+    }
+
+    pub fn height(&self) -> f64 {
+        self.surface.heights.1
+    }
+
+    pub fn base(&self) -> Point3D {
+        self.surface.frame.origin
+    }
+
+    pub fn axis(&self) -> Vec3<f64> {
+        self.surface.frame.y * self.surface.heights.1
+    }
+
+    pub fn capped(&self) -> bool {
+        self.surface.capped
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.surface.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.surface.center()
+    }
+}
+
+macro_rules! hit_by_surface {
+    ($($shape:ident),*) => {
+        $(
+            impl Hit for $shape {
+                fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+                    self.surface.hit(ray, t_min, t_max)
+                }
+
+                fn bounding_box(&self) -> Option<Aabb> {
+                    Some(self.surface.bounding_box())
+                }
+            }
+        )*
+    };
+}
+
+hit_by_surface!(Cylinder, Cone, Paraboloid, Hyperboloid);
+
+// A ring around `axis` through `center`, with the tube's centre line at
+// `major_radius` and the tube `minor_radius` thick. u runs around the axis
+// and v around the tube, starting from its outer equator.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    frame: Frame,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Materials,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3D,
+        axis: Vec3<f64>,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Materials,
+    ) -> Self {
+        Torus {
+            frame: Frame::new(center, axis),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+
+    pub fn center(&self) -> Point3D {
+        self.frame.origin
+    }
+
+    pub fn axis(&self) -> Vec3<f64> {
+        self.frame.y
+    }
+
+    pub fn major_radius(&self) -> f64 {
+        self.major_radius
+    }
+
+    pub fn minor_radius(&self) -> f64 {
+        self.minor_radius
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+}
+
+impl Hit for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(ray);
+        let (big, small) = (self.major_radius, self.minor_radius);
+
+        // Restrict the search to the bounding sphere, and solve from where
+        // the ray enters it so the quartic's coefficients stay well scaled.
+        let outer = big + small;
+        let enter_exit = quadratic_roots(
+            d.length_sqrd(),
+            2.0 * o.dot(&d),
+            o.length_sqrd() - outer * outer,
+            d.length_sqrd(),
+        );
+        if enter_exit.len() != 2 {
+            return None;
+        }
+        let start = enter_exit[0].max(t_min);
+        let end = enter_exit[1].min(t_max);
+        if start > end {
+            return None;
+        }
+
+        // (|p|² + R² - r²)² = 4R²(x² + z²) along p = o + s d.
+        let o = o + start * d;
+        let a = d.length_sqrd();
+        let b = 2.0 * o.dot(&d);
+        let c = o.length_sqrd() + big * big - small * small;
+        let planar_a = d.x() * d.x() + d.z() * d.z();
+        let planar_b = 2.0 * (o.x() * d.x() + o.z() * d.z());
+        let planar_c = o.x() * o.x() + o.z() * o.z();
+        let ring = 4.0 * big * big;
+        let quartic = [
+            c * c - ring * planar_c,
+            2.0 * b * c - ring * planar_b,
+            b * b + 2.0 * a * c - ring * planar_a,
+            2.0 * a * b,
+            a * a,
+        ];
+
+        let s = *polynomial_roots(&quartic, 0.0, end - start).first()?;
+        let t = start + s;
+        let p = o + s * d;
+
+        let planar = Vec3::new(p.x(), 0.0, p.z());
+        let spine = match planar.length() {
+            length if length > 0.0 => planar * (big / length),
+            _ => planar,
+        };
+        let tube = p - spine;
+        let v = tube.y().atan2(tube.dot(&spine.unit()));
+        let normal = self.frame.world_vector(tube).unit();
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(ray.at(t))
+                .set_uv(angle_uv(p), (v / (2.0 * PI)).rem_euclid(1.0))
+                .set_face_normal(ray, &normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(self.frame.bounds(
+            Vec3::new(-outer, -self.minor_radius, -outer),
+            Vec3::new(outer, self.minor_radius, outer),
+        ))
+    }
+}
+
+// The angle of a local point around the y axis, from 0 to 1.
+fn angle_uv(p: Vec3<f64>) -> f64 {
+    (p.z().atan2(p.x()) / (2.0 * PI)).rem_euclid(1.0)
+}
+
+// Real roots of a t² + b t + c in ascending order. `scale` is the squared
+// length of the ray direction, so that a near-zero `a` is judged relative to
+// it and the equation falls back to being linear.
+fn quadratic_roots(a: f64, b: f64, c: f64, scale: f64) -> Vec<f64> {
+    if a.abs() < PARALLEL * scale {
+        return match b {
+            b if b != 0.0 => vec![-c / b],
+            _ => Vec::new(),
+        };
+    }
+
+    let discrim = b * b - 4.0 * a * c;
+    if discrim < 0.0 {
+        return Vec::new();
+    }
+
+    // Avoids cancellation between b and the square root.
+    let q = -0.5 * (b + b.signum() * discrim.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    let (r0, r1) = (q / a, c / q);
+    vec![r0.min(r1), r0.max(r1)]
+}
+
+// Real roots of the polynomial with `coeffs`, constant term first, in
+// [lo, hi] in ascending order. The polynomial is monotonic between the roots
+// of its derivative, so each of those intervals holds at most one root and
+// bisection finds it reliably, which closed-form quartic solutions don't.
+fn polynomial_roots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    if coeffs.len() < 2 {
+        return Vec::new();
+    }
+
+    let derivative: Vec<f64> = coeffs
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, coeff)| power as f64 * coeff)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots: Vec<f64> = Vec::new();
+    for window in bounds.windows(2) {
+        if let Some(root) = bisect(coeffs, window[0], window[1]) {
+            if roots.last().is_none_or(|last| root > *last) {
+                roots.push(root);
+            }
+        }
+    }
+    roots
+}
+
+fn bisect(coeffs: &[f64], mut lo: f64, mut hi: f64) -> Option<f64> {
+    let evaluate = |x: f64| coeffs.iter().rev().fold(0.0, |sum, coeff| sum * x + coeff);
+    let lo_sign = evaluate(lo).signum();
+    if evaluate(lo) == 0.0 {
+        return Some(lo);
+    }
+    if lo_sign == evaluate(hi).signum() {
+        return None;
+    }
+
+    for _ in 0..BISECTIONS {
+        let mid = 0.5 * (lo + hi);
+        if mid <= lo || mid >= hi {
+            break;
+        }
+        match evaluate(mid).signum() == lo_sign {
+            true => lo = mid,
+            false => hi = mid,
+        }
+    }
+    Some(0.5 * (lo + hi))
+}
+
+#[cfg(test)]
+mod quadrics_tests {
+    use super::*;
+    use crate::material::grey;
+
+    const STEP: f64 = 1e-3;
+
+    type Inside = Box<dyn Fn(Point3D) -> bool>;
+
+    // The first t at which the ray crosses the boundary of `inside`, found by
+    // marching in small steps and bisecting the crossing.
+    fn march(ray: &Ray, t_max: f64, inside: &dyn Fn(Point3D) -> bool) -> Option<f64> {
+        let start = inside(ray.at(0.0));
+        let mut t = 0.0;
+        while t < t_max {
+            if inside(ray.at(t + STEP)) != start {
+                let (mut lo, mut hi) = (t, t + STEP);
+                for _ in 0..40 {
+                    let mid = 0.5 * (lo + hi);
+                    match inside(ray.at(mid)) == start {
+                        true => lo = mid,
+                        false => hi = mid,
+                    }
+                }
+                return Some(hi);
+            }
+            t += STEP;
+        }
+        None
+    }
+
+    #[test]
+    fn test_matches_ray_marched_reference() {
+        let base = Point3D::new(0.5, -0.5, 0.2);
+        let axis = Vec3::new(1.0, 2.0, 0.5).unit() * 2.0;
+        let unit = axis.unit();
+        // Height along the axis and squared distance from it.
+        let local = move |p: Point3D| {
+            let offset = p - base;
+            let y = offset.dot(&unit);
+            (y, offset.length_sqrd() - y * y)
+        };
+        let within = |y: f64| (0.0..=2.0).contains(&y);
+
+        let shapes: Vec<(Box<dyn Hit>, Inside)> = vec![
+            (
+                Box::new(Cylinder::new(base, axis, 0.8, grey())),
+                Box::new(move |p| {
+                    let (y, r2) = local(p);
+                    within(y) && r2 <= 0.64
+                }),
+            ),
+            (
+                Box::new(Cone::new(base, axis, 1.0, grey())),
+                Box::new(move |p| {
+                    let (y, r2) = local(p);
+                    within(y) && r2 <= (1.0 - y / 2.0).powi(2)
+                }),
+            ),
+            (
+                Box::new(Paraboloid::new(base, axis, 1.0, grey())),
+                Box::new(move |p| {
+                    let (y, r2) = local(p);
+                    within(y) && r2 <= y / 2.0
+                }),
+            ),
+            (
+                Box::new(Hyperboloid::new(base, axis, 0.4, 1.0, grey())),
+                Box::new(move |p| {
+                    let (y, r2) = local(p);
+                    within(y) && r2 <= 0.16 + 0.84 * (y - 1.0).powi(2)
+                }),
+            ),
+            (
+                Box::new(Torus::new(base, axis, 1.0, 0.3, grey())),
+                Box::new(move |p| {
+                    let (y, r2) = local(p);
+                    (r2.sqrt() - 1.0).powi(2) + y * y <= 0.09
+                }),
+            ),
+        ];
+
+        for (shape, inside) in &shapes {
+            let bounds = shape.bounding_box().unwrap();
+            let center = (bounds.min() + bounds.max()) / 2.0;
+            let mut hits = 0;
+
+            for i in 0..150 {
+                let k = i as f64;
+                let origin = center
+                    + 4.0
+                        * Vec3::new(
+                            k.sin() * (2.0 * k).cos(),
+                            k.cos(),
+                            k.sin() * (2.0 * k).sin(),
+                        );
+                let target =
+                    center + Vec3::new((1.7 * k).sin(), (2.3 * k).cos(), (0.9 * k).sin()) * 1.2;
+                let ray = Ray::new(origin, (target - origin).unit());
+
+                let expected = march(&ray, 10.0, inside.as_ref());
+                let record = shape.hit(&ray, 0.001, f64::INFINITY);
+                match (expected, record) {
+                    (None, None) => {}
+                    (Some(t), Some(record)) => {
+                        hits += 1;
+                        assert!(
+                            (t - record.time()).abs() < 1e-6,
+                            "{} != {}",
+                            t,
+                            record.time()
+                        );
+                        assert!((record.normal().length() - 1.0).abs() < 1e-9);
+                        assert!(record.front_face());
+                        let (u, v) = record.uv();
+                        assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+                        let point = record.point();
+                        assert!(bounds.min().x() <= point.x() && point.x() <= bounds.max().x());
+                        assert!(bounds.min().y() <= point.y() && point.y() <= bounds.max().y());
+                    }
+                    (expected, record) => panic!(
+                        "ray {}: reference {:?}, hit {:?}",
+                        i,
+                        expected,
+                        record.map(|record| record.time())
+                    ),
+                }
+            }
+            assert!(hits > 20);
+        }
+    }
+
+    #[test]
+    fn test_uncapped_cylinder_is_open() {
+        let cylinder = Cylinder::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            1.0,
+            grey(),
+        )
+        .set_capped(false);
+
+        let down_the_middle = Ray::new(Point3D::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(&down_the_middle, 0.001, 10.0).is_none());
+
+        let from_inside = Ray::new(Point3D::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = cylinder.hit(&from_inside, 0.001, 10.0).unwrap();
+        assert!((record.time() - 1.0).abs() < 1e-12);
+        assert!(!record.front_face());
+        assert!((record.uv().1 - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_polynomial_roots() {
+        // (x + 3)(x - 1)(x - 2)(x - 4)
+        let roots = polynomial_roots(&[-24.0, 34.0, -7.0, -4.0, 1.0], -10.0, 10.0);
+        assert_eq!(4, roots.len());
+        for (root, expected) in roots.iter().zip(&[-3.0, 1.0, 2.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        assert!(polynomial_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::objects::HitRecord;
+use crate::utils::random_double;
 use crate::vec3::{Point3D, Vec3};
-use rand::Rng;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Ray {
@@ -13,7 +13,7 @@ impl Ray {
         Ray { origin, direction }
     }
     pub fn at(&self, t: f64) -> Point3D {
-        return self.origin + self.direction * t;
+        self.origin + self.direction * t
     }
 
     pub fn direction(&self) -> Vec3<f64> {
@@ -40,9 +40,7 @@ impl Ray {
 }
 
 fn reflectance(cos: f64, refraction_ratio: f64) -> bool {
-    let mut rng = rand::thread_rng();
-
     let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
 
-    r0 + (1.0 - r0) * ((1.0 - cos).powi(5)) > rng.gen::<f64>()
+    r0 + (1.0 - r0) * ((1.0 - cos).powi(5)) > random_double()
 }
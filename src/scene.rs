@@ -1,50 +1,397 @@
-use crate::camera::Camera;
+use crate::aov::AovSample;
+use crate::camera::{Cameras, Projection};
 use crate::objects::{Hit, SceneObjects};
 use crate::ray::Ray;
+use crate::scenefile;
+use crate::utils::{fnv1a, random_double, sample_seed, seed_sampler};
 use crate::vec3::Colour;
-use rand::Rng;
+
+// What rays that leave the scene see.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Background {
+    Sky,
+    Uniform(Colour),
+}
+
+impl Background {
+    fn colour(&self, ray: &Ray) -> Colour {
+        match self {
+            Background::Sky => sky_colour(ray),
+            Background::Uniform(colour) => *colour,
+        }
+    }
+}
 
 pub struct Scene {
     world: SceneObjects,
-    camera: Camera<f64>,
+    camera: Cameras,
+    background: Background,
+    seed: u64,
 }
 
 impl Scene {
-    pub fn new(world: SceneObjects, camera: Camera<f64>) -> Scene {
-        Scene { world, camera }
+    pub fn new(world: SceneObjects, camera: Cameras) -> Scene {
+        Scene {
+            world,
+            camera,
+            background: Background::Sky,
+            seed: 0,
+        }
+    }
+
+    pub fn set_seed(mut self, seed: u64) -> Scene {
+        self.seed = seed;
+        self
+    }
+
+    pub fn set_camera(mut self, camera: Cameras) -> Scene {
+        self.camera = camera;
+        self
+    }
+
+    pub fn set_background(mut self, background: Background) -> Scene {
+        self.background = background;
+        self
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    pub fn world(&self) -> &SceneObjects {
+        &self.world
+    }
+
+    pub fn camera(&self) -> &Cameras {
+        &self.camera
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Identifies the scene in checkpoints: the hash of its objects, camera
+    // and background as they'd be saved to a scene file.
+    pub fn hash(&self) -> u64 {
+        let text = format!(
+            "{}{}{}",
+            scenefile::encode_objects(&self.world),
+            scenefile::encode_camera(&self.camera),
+            scenefile::encode_background(self.background)
+        );
+        fnv1a(text.as_bytes())
     }
 
     pub fn render(&self, max_depth: i16, img_width: i32, img_height: i32) -> Vec<Colour> {
-        let pixels = (0..img_height)
-            .rev()
-            .flat_map(|j| (0..img_width).map(move |i| (i, j)));
-        let mut rng = rand::thread_rng();
-
-        pixels
-            .map(|(i, j)| {
-                let u = ((i as f64) + rng.gen::<f64>()) / (img_width - 1) as f64;
-                let v = ((j as f64) + rng.gen::<f64>()) / (img_height - 1) as f64;
-
-                let ray = self.camera.get_ray(u, v);
-                ray_colour(&ray, &self.world, max_depth)
+        self.render_crop(
+            &CropWindow::full(img_width, img_height),
+            max_depth,
+            img_width,
+            img_height,
+        )
+    }
+
+    pub fn render_crop(
+        &self,
+        crop: &CropWindow,
+        max_depth: i16,
+        img_width: i32,
+        img_height: i32,
+    ) -> Vec<Colour> {
+        crop.pixels(img_height)
+            .map(|(_, (i, j))| {
+                self.pixel_sample(i, j, max_depth, img_width, img_height)
+                    .colour()
             })
             .collect::<Vec<Colour>>()
     }
+
+    pub fn render_sample(
+        &self,
+        sample: u64,
+        max_depth: i16,
+        img_width: i32,
+        img_height: i32,
+    ) -> Vec<Colour> {
+        self.render_sample_aovs(sample, max_depth, img_width, img_height)
+            .iter()
+            .map(|aov_sample| aov_sample.colour())
+            .collect::<Vec<Colour>>()
+    }
+
+    pub fn render_sample_aovs(
+        &self,
+        sample: u64,
+        max_depth: i16,
+        img_width: i32,
+        img_height: i32,
+    ) -> Vec<AovSample> {
+        self.render_sample_crop_aovs(
+            sample,
+            &CropWindow::full(img_width, img_height),
+            max_depth,
+            img_width,
+            img_height,
+        )
+    }
+
+    // Renders only the pixels inside `crop`, in the same order as a full
+    // render. Each pixel is seeded by its index in the full image, so a crop
+    // matches the same region of a full render exactly.
+    pub fn render_sample_crop_aovs(
+        &self,
+        sample: u64,
+        crop: &CropWindow,
+        max_depth: i16,
+        img_width: i32,
+        img_height: i32,
+    ) -> Vec<AovSample> {
+        crop.pixels(img_height)
+            .map(|((column, row), (i, j))| {
+                let index = (row * img_width + column) as u64;
+                seed_sampler(sample_seed(self.seed, sample, index));
+                self.pixel_sample(i, j, max_depth, img_width, img_height)
+            })
+            .collect::<Vec<AovSample>>()
+    }
+
+    fn pixel_sample(
+        &self,
+        i: i32,
+        j: i32,
+        max_depth: i16,
+        img_width: i32,
+        img_height: i32,
+    ) -> AovSample {
+        let u = ((i as f64) + random_double()) / (img_width - 1) as f64;
+        let v = ((j as f64) + random_double()) / (img_height - 1) as f64;
+
+        if !self.camera.covers(u, v) {
+            return AovSample::default();
+        }
+
+        let (ray, weight) = self.camera.get_weighted_ray(u, v);
+        trace_path(
+            &ray,
+            &self.world,
+            &self.background,
+            max_depth,
+            weight * self.camera.exposure(),
+        )
+    }
+}
+
+// A rectangle of pixels, with rows counted from the top of the image as in
+// the output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CropWindow {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
 }
 
-fn ray_colour(ray: &Ray, world: &SceneObjects, depth: i16) -> Colour {
-    if depth <= 0 {
-        return Colour::new(0.0, 0.0, 0.0);
+impl CropWindow {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        CropWindow {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn full(img_width: i32, img_height: i32) -> Self {
+        CropWindow::new(0, 0, img_width, img_height)
+    }
+
+    // From the corners as fractions of the image size, rounded outwards to
+    // whole pixels.
+    pub fn from_normalised(
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        img_width: i32,
+        img_height: i32,
+    ) -> Self {
+        let left = (x0 * img_width as f64).floor() as i32;
+        let top = (y0 * img_height as f64).floor() as i32;
+        let right = (x1 * img_width as f64).ceil() as i32;
+        let bottom = (y1 * img_height as f64).ceil() as i32;
+
+        CropWindow::new(left, top, right - left, bottom - top)
+    }
+
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn y(&self) -> i32 {
+        self.y
     }
 
-    if let Some(hit_record) = world.hit(ray, 0.001, std::f64::INFINITY) {
-        if let Some((scattered_ray, attenuation)) = hit_record.scatter_on_mat(ray) {
-            return attenuation * ray_colour(&scattered_ray, world, depth - 1);
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // The part of the window that lies inside the image.
+    pub fn clipped(&self, img_width: i32, img_height: i32) -> Self {
+        let left = self.x.max(0).min(img_width);
+        let top = self.y.max(0).min(img_height);
+        let right = (self.x + self.width).max(left).min(img_width);
+        let bottom = (self.y + self.height).max(top).min(img_height);
+
+        CropWindow::new(left, top, right - left, bottom - top)
+    }
+
+    pub fn is_full(&self, img_width: i32, img_height: i32) -> bool {
+        *self == CropWindow::full(img_width, img_height)
+    }
+
+    // Places cropped values back into a full-size image, filling the rest.
+    pub fn expand<T: Copy>(
+        &self,
+        img_width: i32,
+        img_height: i32,
+        values: &[T],
+        fill: T,
+    ) -> Vec<T> {
+        let mut image = vec![fill; (img_width * img_height) as usize];
+        for (index, value) in values.iter().enumerate() {
+            let column = self.x + index as i32 % self.width;
+            let row = self.y + index as i32 / self.width;
+            image[(row * img_width + column) as usize] = *value;
         }
+        image
+    }
 
-        return Colour::new(0.0, 0.0, 0.0);
+    // Yields each pixel's (column, row) from the top left, along with its
+    // (i, j) camera coordinates, where j counts up from the bottom.
+    fn pixels(&self, img_height: i32) -> impl Iterator<Item = ((i32, i32), (i32, i32))> {
+        let (x, width) = (self.x, self.width);
+        (self.y..self.y + self.height).flat_map(move |row| {
+            (x..x + width).map(move |column| ((column, row), (column, img_height - 1 - row)))
+        })
     }
+}
+
+fn trace_path(
+    ray: &Ray,
+    world: &SceneObjects,
+    background: &Background,
+    max_depth: i16,
+    weight: Colour,
+) -> AovSample {
+    let mut aov_sample = AovSample::default();
+    let mut throughput = weight;
+    let mut ray = *ray;
+
+    for bounce in 0..max_depth.max(0) as usize {
+        match world.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit_record) => {
+                if bounce == 0 {
+                    aov_sample.record_hit(&hit_record, &ray);
+                }
+                let emitted = hit_record.material().emitted(&hit_record);
+                if emitted != Colour::new(0.0, 0.0, 0.0) {
+                    aov_sample.record_emission(
+                        bounce,
+                        hit_record.object_id(),
+                        throughput * emitted,
+                    );
+                }
+
+                match hit_record.scatter_on_mat(&ray) {
+                    Some((scattered_ray, attenuation)) => {
+                        throughput *= attenuation;
+                        ray = scattered_ray;
+                    }
+                    None => break,
+                }
+            }
+            None => {
+                aov_sample.record_background(bounce, throughput * background.colour(&ray));
+                break;
+            }
+        }
+    }
+
+    aov_sample
+}
+
+fn sky_colour(ray: &Ray) -> Colour {
     let unit_direction = ray.direction().unit();
     let t = 0.5 * (unit_direction.y() + 1.0);
     (1.0 - t) * Colour::new(1.0, 1.0, 1.0) + t * Colour::new(0.5, 0.7, 1.0)
 }
+
+#[cfg(test)]
+mod scene_tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::{Lambertian, Materials};
+    use crate::objects::{Object, Sphere};
+    use crate::vec3::{Point3D, Vec3};
+
+    #[test]
+    fn test_crop_matches_full_render() {
+        let mut world = SceneObjects::new();
+        world.add(Object::Sphere(Sphere::new(
+            Point3D::new(0.0, 0.0, -1.0),
+            0.5,
+            Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5))),
+        )));
+        let camera = Camera::new(
+            2.0,
+            90.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.1,
+            1.0,
+        );
+        let scene = Scene::new(world, Cameras::Perspective(camera)).set_seed(7);
+
+        let crop = CropWindow::new(3, 1, 4, 2);
+        let full = scene.render_sample(5, 10, 8, 4);
+        let cropped: Vec<Colour> = scene
+            .render_sample_crop_aovs(5, &crop, 10, 8, 4)
+            .iter()
+            .map(|sample| sample.colour())
+            .collect();
+
+        assert_eq!(8, cropped.len());
+        let black = Colour::new(0.0, 0.0, 0.0);
+        for (index, pixel) in crop.expand(8, 4, &cropped, black).iter().enumerate() {
+            let (column, row) = (index as i32 % 8, index as i32 / 8);
+            match (3..7).contains(&column) && (1..3).contains(&row) {
+                true => assert_eq!(full[index], *pixel),
+                false => assert_eq!(black, *pixel),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+
+        let camera = Camera::new(
+            2.0,
+            90.0,
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.1,
+            1.0,
+        );
+        let scene = || Scene::new(SceneObjects::new(), Cameras::Perspective(camera.clone()));
+        assert_eq!(scene().hash(), scene().set_seed(3).hash());
+        let grey = Background::Uniform(Colour::new(0.5, 0.5, 0.5));
+        assert_ne!(scene().hash(), scene().set_background(grey).hash());
+    }
+}
@@ -0,0 +1,1421 @@
+use crate::camera::{
+    Camera, Cameras, Cubemap, Equirectangular, Fisheye, FisheyeMapping, Ods, Orthographic,
+    Projection, Realistic, Stereo, StereoConvergence, StereoLayout, View, Viewport,
+};
+use crate::csg::{Csg, Operation};
+use crate::curve::{Curve, CurveKind};
+use crate::framebuffer::invalid_data;
+use crate::hair::Hair;
+use crate::heightfield::Heightfield;
+use crate::json::Json;
+use crate::lens::{Aperture, Distortion, LensElement, LensSystem};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Materials, Metal};
+use crate::mesh::Mesh;
+use crate::objects::{Object, SceneObjects, Sphere};
+use crate::planar::{Axis, Cuboid, Disk, Plane, Quad, Rect};
+use crate::pnm::GreyImage;
+use crate::quadrics::{Cone, Cylinder, Hyperboloid, Paraboloid, Torus};
+use crate::scene::{Background, Scene};
+use crate::sdf::{Distance, Sdf};
+use crate::subdivision::PolygonMesh;
+use crate::vec3::{Point3D, Vec3};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Seeds above this can't be written exactly as JSON numbers, so are written
+// as strings instead.
+const MAX_EXACT_SEED: u64 = 1 << 53;
+
+// Each level of subdivision makes four times the faces, so files can't ask
+// for more than this.
+const MAX_SUBDIVISION_LEVELS: usize = 6;
+
+// Our scene files are JSON documents holding the seed, background, camera
+// and a list of objects, each tagged with its "type" and carrying the values
+// its constructor takes, so they can be edited by hand. Reading a file back
+// builds everything through the same constructors, which gives back the
+// same scene and the same render.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Scene> {
+    parse(&fs::read_to_string(path)?)
+}
+
+pub fn parse(text: &str) -> io::Result<Scene> {
+    decode_scene(&Json::parse(text)?)
+}
+
+pub fn write<P: AsRef<Path>>(scene: &Scene, path: P) -> io::Result<()> {
+    fs::write(path, format!("{:#}\n", encode_scene(scene)))
+}
+
+pub fn encode_scene(scene: &Scene) -> Json {
+    let seed = match scene.seed() {
+        seed if seed <= MAX_EXACT_SEED => Json::Number(seed as f64),
+        seed => Json::String(seed.to_string()),
+    };
+    object(vec![
+        ("seed", seed),
+        ("background", encode_background(scene.background())),
+        ("camera", encode_camera(scene.camera())),
+        ("objects", encode_objects(scene.world())),
+    ])
+}
+
+pub fn encode_background(background: Background) -> Json {
+    match background {
+        Background::Sky => Json::String("sky".to_string()),
+        Background::Uniform(colour) => vector(colour),
+    }
+}
+
+pub fn decode_scene(json: &Json) -> io::Result<Scene> {
+    let seed = match json.get("seed") {
+        None => 0,
+        Some(Json::Number(seed)) if *seed >= 0.0 && seed.fract() == 0.0 => *seed as u64,
+        Some(Json::String(seed)) => seed
+            .parse()
+            .map_err(|_| invalid_data("invalid scene seed"))?,
+        Some(_) => return Err(invalid_data("invalid scene seed")),
+    };
+    let background = match json.get("background") {
+        None => Background::Sky,
+        Some(Json::String(name)) if name == "sky" => Background::Sky,
+        Some(_) => Background::Uniform(read_vector(json, "background")?),
+    };
+    let camera = decode_camera(member(json, "camera")?)?;
+    let world = decode_objects(json)?;
+
+    Ok(Scene::new(world, camera)
+        .set_background(background)
+        .set_seed(seed))
+}
+
+pub fn encode_object(object: &Object) -> Json {
+    match object {
+        Object::Sphere(sphere) => tagged(
+            "sphere",
+            vec![
+                ("center", vector(sphere.center())),
+                ("radius", Json::Number(sphere.radius())),
+                ("material", encode_material(sphere.material())),
+            ],
+        ),
+        Object::Quad(quad) => tagged(
+            "quad",
+            vec![
+                ("origin", vector(quad.origin())),
+                ("u", vector(quad.u())),
+                ("v", vector(quad.v())),
+                ("material", encode_material(quad.material())),
+            ],
+        ),
+        Object::Rect(rect) => {
+            let (a, b, k) = rect.bounds();
+            let axis = match rect.normal() {
+                Axis::X => "x",
+                Axis::Y => "y",
+                Axis::Z => "z",
+            };
+            tagged(
+                "rect",
+                vec![
+                    ("axis", Json::String(axis.to_string())),
+                    ("a", numbers(&[a.0, a.1])),
+                    ("b", numbers(&[b.0, b.1])),
+                    ("k", Json::Number(k)),
+                    ("material", encode_material(rect.material())),
+                ],
+            )
+        }
+        Object::Disk(disk) => tagged(
+            "disk",
+            vec![
+                ("center", vector(disk.center())),
+                ("normal", vector(disk.normal())),
+                ("radius", Json::Number(disk.radius())),
+                ("material", encode_material(disk.material())),
+            ],
+        ),
+        Object::Cuboid(cuboid) => tagged(
+            "cuboid",
+            vec![
+                ("min", vector(cuboid.min())),
+                ("max", vector(cuboid.max())),
+                ("material", encode_material(cuboid.material())),
+            ],
+        ),
+        Object::Plane(plane) => tagged(
+            "plane",
+            vec![
+                ("point", vector(plane.point())),
+                ("normal", vector(plane.normal())),
+                ("uv_scale", Json::Number(plane.uv_scale())),
+                ("material", encode_material(plane.material())),
+            ],
+        ),
+        Object::Cylinder(cylinder) => tagged(
+            "cylinder",
+            vec![
+                ("base", vector(cylinder.base())),
+                ("axis", vector(cylinder.axis())),
+                ("radius", Json::Number(cylinder.radius())),
+                ("capped", Json::Bool(cylinder.capped())),
+                ("material", encode_material(cylinder.material())),
+            ],
+        ),
+        Object::Cone(cone) => tagged(
+            "cone",
+            vec![
+                ("base", vector(cone.base())),
+                ("axis", vector(cone.axis())),
+                ("radius", Json::Number(cone.radius())),
+                ("capped", Json::Bool(cone.capped())),
+                ("material", encode_material(cone.material())),
+            ],
+        ),
+        Object::Paraboloid(paraboloid) => tagged(
+            "paraboloid",
+            vec![
+                ("vertex", vector(paraboloid.base())),
+                ("axis", vector(paraboloid.axis())),
+                ("radius", Json::Number(paraboloid.radius())),
+                ("capped", Json::Bool(paraboloid.capped())),
+                ("material", encode_material(paraboloid.material())),
+            ],
+        ),
+        Object::Hyperboloid(hyperboloid) => tagged(
+            "hyperboloid",
+            vec![
+                ("base", vector(hyperboloid.base())),
+                ("axis", vector(hyperboloid.axis())),
+                ("waist_radius", Json::Number(hyperboloid.waist_radius())),
+                ("end_radius", Json::Number(hyperboloid.end_radius())),
+                ("capped", Json::Bool(hyperboloid.capped())),
+                ("material", encode_material(hyperboloid.material())),
+            ],
+        ),
+        Object::Torus(torus) => tagged(
+            "torus",
+            vec![
+                ("center", vector(torus.center())),
+                ("axis", vector(torus.axis())),
+                ("major_radius", Json::Number(torus.major_radius())),
+                ("minor_radius", Json::Number(torus.minor_radius())),
+                ("material", encode_material(torus.material())),
+            ],
+        ),
+        Object::Csg(csg) => {
+            let operation = match csg.operation() {
+                Operation::Union => "union",
+                Operation::Intersection => "intersection",
+                Operation::Difference => "difference",
+            };
+            tagged(
+                "csg",
+                vec![
+                    ("operation", Json::String(operation.to_string())),
+                    ("left", encode_object(csg.left())),
+                    ("right", encode_object(csg.right())),
+                ],
+            )
+        }
+        Object::Sdf(sdf) => tagged(
+            "sdf",
+            vec![
+                ("distance", encode_distance(sdf.distance())),
+                ("material", encode_material(sdf.material())),
+            ],
+        ),
+        Object::Heightfield(heightfield) => {
+            let image = heightfield.image();
+            tagged(
+                "heightfield",
+                vec![
+                    ("corner", vector(heightfield.corner())),
+                    ("size", vector(heightfield.size())),
+                    ("columns", Json::Number(image.width() as f64)),
+                    ("rows", Json::Number(image.height() as f64)),
+                    ("values", numbers(image.values())),
+                    ("material", encode_material(heightfield.material())),
+                ],
+            )
+        }
+        Object::Curve(curve) => {
+            let points = Json::Array(curve.points().iter().map(|&p| vector(p)).collect());
+            let (width0, width1) = curve.widths();
+            let mut members = vec![("points", points), ("widths", numbers(&[width0, width1]))];
+            let kind = match curve.kind() {
+                CurveKind::Cylinder => "cylinder",
+                CurveKind::Ribbon(normal0, normal1) => {
+                    members.push((
+                        "normals",
+                        Json::Array(vec![vector(normal0), vector(normal1)]),
+                    ));
+                    "ribbon"
+                }
+            };
+            members.insert(0, ("kind", Json::String(kind.to_string())));
+            members.push(("material", encode_material(curve.material())));
+            tagged("curve", members)
+        }
+        Object::Mesh(mesh) => tagged(
+            "mesh",
+            vec![
+                (
+                    "positions",
+                    Json::Array(mesh.positions().iter().map(|&p| vector(p)).collect()),
+                ),
+                (
+                    "normals",
+                    Json::Array(mesh.normals().iter().map(|&n| vector(n)).collect()),
+                ),
+                (
+                    "uvs",
+                    Json::Array(mesh.uvs().iter().map(|&(u, v)| numbers(&[u, v])).collect()),
+                ),
+                (
+                    "triangles",
+                    Json::Array(
+                        mesh.triangles()
+                            .iter()
+                            .map(|triangle| {
+                                let indices: Vec<f64> =
+                                    triangle.iter().map(|&index| index as f64).collect();
+                                numbers(&indices)
+                            })
+                            .collect(),
+                    ),
+                ),
+                ("material", encode_material(mesh.material())),
+            ],
+        ),
+        Object::SceneObjects(objects) => {
+            tagged("group", vec![("objects", encode_objects(objects))])
+        }
+    }
+}
+
+pub fn decode_object(json: &Json) -> io::Result<Object> {
+    let material = || decode_material(member(json, "material")?);
+    let capped = || read_bool(json, "capped", true);
+
+    Ok(match read_text(json, "type")? {
+        "sphere" => Object::Sphere(Sphere::new(
+            read_vector(json, "center")?,
+            read_number(json, "radius")?,
+            material()?,
+        )),
+        "quad" => Object::Quad(Quad::new(
+            read_vector(json, "origin")?,
+            read_vector(json, "u")?,
+            read_vector(json, "v")?,
+            material()?,
+        )),
+        "rect" => {
+            let (a0, a1) = read_pair(json, "a")?;
+            let (b0, b1) = read_pair(json, "b")?;
+            let k = read_number(json, "k")?;
+            Object::Rect(match read_text(json, "axis")? {
+                "x" => Rect::yz(a0, a1, b0, b1, k, material()?),
+                "y" => Rect::xz(a0, a1, b0, b1, k, material()?),
+                "z" => Rect::xy(a0, a1, b0, b1, k, material()?),
+                _ => return Err(invalid_data("rect axis must be x, y or z")),
+            })
+        }
+        "disk" => Object::Disk(Disk::new(
+            read_vector(json, "center")?,
+            read_vector(json, "normal")?,
+            read_number(json, "radius")?,
+            material()?,
+        )),
+        "cuboid" => Object::Cuboid(Cuboid::new(
+            read_vector(json, "min")?,
+            read_vector(json, "max")?,
+            material()?,
+        )),
+        "plane" => Object::Plane(
+            Plane::new(
+                read_vector(json, "point")?,
+                read_vector(json, "normal")?,
+                material()?,
+            )
+            .set_uv_scale(read_optional(json, "uv_scale", 1.0)?),
+        ),
+        "cylinder" => Object::Cylinder(
+            Cylinder::new(
+                read_vector(json, "base")?,
+                read_vector(json, "axis")?,
+                read_number(json, "radius")?,
+                material()?,
+            )
+            .set_capped(capped()?),
+        ),
+        "cone" => Object::Cone(
+            Cone::new(
+                read_vector(json, "base")?,
+                read_vector(json, "axis")?,
+                read_number(json, "radius")?,
+                material()?,
+            )
+            .set_capped(capped()?),
+        ),
+        "paraboloid" => Object::Paraboloid(
+            Paraboloid::new(
+                read_vector(json, "vertex")?,
+                read_vector(json, "axis")?,
+                read_number(json, "radius")?,
+                material()?,
+            )
+            .set_capped(capped()?),
+        ),
+        "hyperboloid" => Object::Hyperboloid(
+            Hyperboloid::new(
+                read_vector(json, "base")?,
+                read_vector(json, "axis")?,
+                read_number(json, "waist_radius")?,
+                read_number(json, "end_radius")?,
+                material()?,
+            )
+            .set_capped(capped()?),
+        ),
+        "torus" => Object::Torus(Torus::new(
+            read_vector(json, "center")?,
+            read_vector(json, "axis")?,
+            read_number(json, "major_radius")?,
+            read_number(json, "minor_radius")?,
+            material()?,
+        )),
+        "csg" => {
+            let operation = match read_text(json, "operation")? {
+                "union" => Operation::Union,
+                "intersection" => Operation::Intersection,
+                "difference" => Operation::Difference,
+                _ => return Err(invalid_data("unknown CSG operation")),
+            };
+            Object::Csg(Csg::new(
+                operation,
+                decode_object(member(json, "left")?)?,
+                decode_object(member(json, "right")?)?,
+            ))
+        }
+        "sdf" => Object::Sdf(Sdf::new(
+            decode_distance(member(json, "distance")?)?,
+            material()?,
+        )),
+        "heightfield" => {
+            let image = read_image(json)?;
+            if image.width() < 2 || image.height() < 2 {
+                return Err(invalid_data("a heightfield needs at least 2x2 values"));
+            }
+            Object::Heightfield(Heightfield::new(
+                &image,
+                read_vector(json, "corner")?,
+                read_vector(json, "size")?,
+                material()?,
+            ))
+        }
+        "curve" => {
+            let points = read_list(json, "points", read_point)?;
+            let points = match points[..] {
+                [p0, p1, p2, p3] => [p0, p1, p2, p3],
+                _ => return Err(invalid_data("a curve needs four points")),
+            };
+            let (width0, width1) = read_pair(json, "widths")?;
+            match read_text(json, "kind")? {
+                "cylinder" => Object::Curve(Curve::cylinder(points, width0, width1, material()?)),
+                "ribbon" => match read_list(json, "normals", read_point)?[..] {
+                    [normal0, normal1] => Object::Curve(Curve::ribbon(
+                        points,
+                        width0,
+                        width1,
+                        normal0,
+                        normal1,
+                        material()?,
+                    )),
+                    _ => return Err(invalid_data("a ribbon needs two normals")),
+                },
+                _ => return Err(invalid_data("curve kind must be cylinder or ribbon")),
+            }
+        }
+        "mesh" => decode_mesh(json, material()?)?,
+        "group" => Object::SceneObjects(decode_objects(json)?),
+        kind => return Err(invalid_data(&format!("unknown object type {}", kind))),
+    })
+}
+
+pub fn encode_material(material: &Materials) -> Json {
+    match material {
+        Materials::Lambertian(lambertian) => {
+            tagged("lambertian", vec![("albedo", vector(lambertian.albedo()))])
+        }
+        Materials::Metal(metal) => tagged(
+            "metal",
+            vec![
+                ("albedo", vector(metal.albedo())),
+                ("fuzz", Json::Number(metal.fuzz())),
+            ],
+        ),
+        Materials::Dielectric(dielectric) => tagged(
+            "dielectric",
+            vec![(
+                "refraction_index",
+                Json::Number(dielectric.refraction_index()),
+            )],
+        ),
+        Materials::Hair(hair) => {
+            let (beta_m, beta_n) = hair.roughness();
+            tagged(
+                "hair",
+                vec![
+                    ("sigma_a", vector(hair.sigma_a())),
+                    ("eta", Json::Number(hair.eta())),
+                    ("roughness", numbers(&[beta_m, beta_n])),
+                    ("scale_angle", Json::Number(hair.scale_angle())),
+                    ("albedo", vector(hair.albedo())),
+                ],
+            )
+        }
+        Materials::DiffuseLight(light) => tagged(
+            "diffuse_light",
+            vec![
+                ("emit", vector(light.emit())),
+                ("two_sided", Json::Bool(light.two_sided())),
+            ],
+        ),
+    }
+}
+
+pub fn decode_material(json: &Json) -> io::Result<Materials> {
+    Ok(match read_text(json, "type")? {
+        "lambertian" => Materials::Lambertian(Lambertian::new(read_vector(json, "albedo")?)),
+        "metal" => Materials::Metal(Metal::new(
+            read_vector(json, "albedo")?,
+            read_optional(json, "fuzz", 0.0)?,
+        )),
+        "dielectric" => {
+            Materials::Dielectric(Dielectric::new(read_number(json, "refraction_index")?))
+        }
+        "hair" => {
+            let hair = Hair::new(read_vector(json, "sigma_a")?);
+            let (beta_m, beta_n) = match json.get("roughness") {
+                Some(_) => read_pair(json, "roughness")?,
+                None => hair.roughness(),
+            };
+            let albedo = match json.get("albedo") {
+                Some(_) => read_vector(json, "albedo")?,
+                None => hair.albedo(),
+            };
+            let eta = read_optional(json, "eta", hair.eta())?;
+            let scale_angle = read_optional(json, "scale_angle", hair.scale_angle())?;
+            Materials::Hair(
+                hair.set_eta(eta)
+                    .set_roughness(beta_m, beta_n)
+                    .set_scale_angle(scale_angle)
+                    .set_albedo(albedo),
+            )
+        }
+        "diffuse_light" => Materials::DiffuseLight(
+            DiffuseLight::new(read_vector(json, "emit")?).set_two_sided(read_bool(
+                json,
+                "two_sided",
+                false,
+            )?),
+        ),
+        kind => return Err(invalid_data(&format!("unknown material type {}", kind))),
+    })
+}
+
+pub fn encode_camera(camera: &Cameras) -> Json {
+    match camera {
+        Cameras::Perspective(camera) => tagged("perspective", perspective_members(camera)),
+        Cameras::Orthographic(orthographic) => {
+            let mut members = vec![
+                ("aspect_ratio", Json::Number(orthographic.aspect_ratio())),
+                (
+                    "view_height",
+                    Json::Number(orthographic.viewport().height()),
+                ),
+            ];
+            members.extend(view_members(orthographic.view()));
+            tagged("orthographic", members)
+        }
+        Cameras::Equirectangular(equirectangular) => {
+            tagged("equirectangular", view_members(equirectangular.view()))
+        }
+        Cameras::Fisheye(fisheye) => {
+            let mapping = match fisheye.mapping() {
+                FisheyeMapping::Equidistant => "equidistant",
+                FisheyeMapping::Equisolid => "equisolid",
+            };
+            let mut members = vec![
+                ("aspect_ratio", Json::Number(fisheye.aspect_ratio())),
+                ("fov", Json::Number(fisheye.fov())),
+                ("mapping", Json::String(mapping.to_string())),
+            ];
+            members.extend(view_members(fisheye.view()));
+            tagged("fisheye", members)
+        }
+        Cameras::Cubemap(cubemap) => tagged("cubemap", view_members(cubemap.view())),
+        Cameras::Stereo(stereo) => {
+            let convergence = match stereo.convergence() {
+                StereoConvergence::ToeIn(distance) => {
+                    tagged("toe_in", vec![("distance", Json::Number(distance))])
+                }
+                StereoConvergence::OffAxis(distance) => {
+                    tagged("off_axis", vec![("distance", Json::Number(distance))])
+                }
+            };
+            tagged(
+                "stereo",
+                vec![
+                    ("camera", object(perspective_members(stereo.camera()))),
+                    ("interocular", Json::Number(stereo.interocular())),
+                    ("convergence", convergence),
+                    ("layout", encode_layout(stereo.layout())),
+                ],
+            )
+        }
+        Cameras::Ods(ods) => {
+            let mut members = view_members(ods.view());
+            members.push(("interocular", Json::Number(ods.interocular())));
+            members.push(("layout", encode_layout(ods.layout())));
+            tagged("ods", members)
+        }
+        Cameras::Realistic(realistic) => {
+            let lens = realistic
+                .lens()
+                .elements()
+                .iter()
+                .map(|element| {
+                    numbers(&[
+                        element.radius(),
+                        element.thickness(),
+                        element.ior(),
+                        element.aperture(),
+                    ])
+                })
+                .collect();
+            let film = realistic.film();
+            let mut members = vec![
+                ("lens", Json::Array(lens)),
+                ("film", numbers(&[film.width(), film.height()])),
+                ("focus_dist", Json::Number(realistic.focus_dist())),
+            ];
+            members.extend(view_members(realistic.view()));
+            tagged("realistic", members)
+        }
+    }
+}
+
+pub fn decode_camera(json: &Json) -> io::Result<Cameras> {
+    let view = || read_view(json);
+
+    Ok(match read_text(json, "type")? {
+        "perspective" => Cameras::Perspective(decode_perspective(json)?),
+        "orthographic" => {
+            let view = view()?;
+            Cameras::Orthographic(Orthographic::new(
+                read_number(json, "aspect_ratio")?,
+                read_number(json, "view_height")?,
+                view.origin(),
+                view.target(),
+                view.up(),
+            ))
+        }
+        "equirectangular" => {
+            let view = view()?;
+            Cameras::Equirectangular(Equirectangular::new(
+                view.origin(),
+                view.target(),
+                view.up(),
+            ))
+        }
+        "fisheye" => {
+            let mapping = match read_text(json, "mapping")? {
+                "equidistant" => FisheyeMapping::Equidistant,
+                "equisolid" => FisheyeMapping::Equisolid,
+                _ => {
+                    return Err(invalid_data(
+                        "fisheye mapping must be equidistant or equisolid",
+                    ))
+                }
+            };
+            let view = view()?;
+            Cameras::Fisheye(Fisheye::new(
+                read_number(json, "aspect_ratio")?,
+                read_number(json, "fov")?,
+                mapping,
+                view.origin(),
+                view.target(),
+                view.up(),
+            ))
+        }
+        "cubemap" => {
+            let view = view()?;
+            Cameras::Cubemap(Cubemap::new(view.origin(), view.target(), view.up()))
+        }
+        "stereo" => {
+            let convergence = member(json, "convergence")?;
+            let distance = read_number(convergence, "distance")?;
+            let convergence = match read_text(convergence, "type")? {
+                "toe_in" => StereoConvergence::ToeIn(distance),
+                "off_axis" => StereoConvergence::OffAxis(distance),
+                _ => {
+                    return Err(invalid_data(
+                        "stereo convergence must be toe_in or off_axis",
+                    ))
+                }
+            };
+            Cameras::Stereo(Stereo::new(
+                decode_perspective(member(json, "camera")?)?,
+                read_number(json, "interocular")?,
+                convergence,
+                decode_layout(json)?,
+            ))
+        }
+        "ods" => {
+            let view = view()?;
+            Cameras::Ods(Ods::new(
+                view.origin(),
+                view.target(),
+                view.up(),
+                read_number(json, "interocular")?,
+                decode_layout(json)?,
+            ))
+        }
+        "realistic" => {
+            let elements = read_list(json, "lens", |element| {
+                match element.as_numbers().as_deref() {
+                    Some(&[radius, thickness, ior, aperture]) => {
+                        Ok(LensElement::new(radius, thickness, ior, aperture))
+                    }
+                    _ => Err(invalid_data(
+                        "lens surfaces need radius, thickness, ior and aperture",
+                    )),
+                }
+            })?;
+            if elements.is_empty() {
+                return Err(invalid_data("a lens needs at least one surface"));
+            }
+            let (width, height) = read_pair(json, "film")?;
+            let view = view()?;
+            Cameras::Realistic(Realistic::new(
+                LensSystem::new(elements),
+                Viewport::new(width, height),
+                read_number(json, "focus_dist")?,
+                view.origin(),
+                view.target(),
+                view.up(),
+            ))
+        }
+        kind => return Err(invalid_data(&format!("unknown camera type {}", kind))),
+    })
+}
+
+fn perspective_members(camera: &Camera<f64>) -> Vec<(&'static str, Json)> {
+    let iris = match camera.aperture() {
+        Aperture::Circular => tagged("circular", Vec::new()),
+        Aperture::Polygon(blades, rotation) => tagged(
+            "polygon",
+            vec![
+                ("blades", Json::Number(*blades as f64)),
+                ("rotation", Json::Number(rotation.to_degrees())),
+            ],
+        ),
+        Aperture::Mask(mask) => tagged(
+            "mask",
+            vec![
+                ("columns", Json::Number(mask.image().width() as f64)),
+                ("rows", Json::Number(mask.image().height() as f64)),
+                ("values", numbers(mask.image().values())),
+            ],
+        ),
+    };
+
+    let mut members = vec![
+        ("aspect_ratio", Json::Number(camera.aspect_ratio())),
+        ("vertical_fov", Json::Number(camera.vertical_fov())),
+    ];
+    members.extend(view_members(camera.view()));
+    members.extend(vec![
+        ("aperture", Json::Number(camera.aperture_diameter())),
+        ("focus_dist", Json::Number(camera.focus_dist())),
+        ("exposure", Json::Number(camera.exposure())),
+        ("iris", iris),
+        ("cat_eye", Json::Number(camera.cat_eye())),
+        (
+            "chromatic_aberration",
+            Json::Number(camera.chromatic_aberration()),
+        ),
+        ("distortion", numbers(&camera.distortion().coefficients())),
+        ("shift", numbers(&[camera.shift().0, camera.shift().1])),
+        ("tilt", numbers(&[camera.tilt().0, camera.tilt().1])),
+    ]);
+    members
+}
+
+// Everything but the view and field of view is optional, for cameras written
+// by hand.
+fn decode_perspective(json: &Json) -> io::Result<Camera<f64>> {
+    let view = read_view(json)?;
+    let iris = match json.get("iris") {
+        None => Aperture::Circular,
+        Some(iris) => match read_text(iris, "type")? {
+            "circular" => Aperture::Circular,
+            "polygon" => Aperture::polygon(
+                read_number(iris, "blades")? as u32,
+                read_optional(iris, "rotation", 0.0)?,
+            ),
+            "mask" => Aperture::from_image(read_image(iris)?)?,
+            _ => return Err(invalid_data("iris must be circular, polygon or mask")),
+        },
+    };
+    let distortion = match json.get("distortion").map(Json::as_numbers) {
+        None => Distortion::default(),
+        Some(Some(coefficients)) if coefficients.len() == 4 => Distortion::new(
+            coefficients[0],
+            coefficients[1],
+            coefficients[2],
+            coefficients[3],
+        ),
+        Some(_) => return Err(invalid_data("distortion needs k1, k2, p1 and p2")),
+    };
+    let optional_pair = |key: &str| match json.get(key) {
+        Some(_) => read_pair(json, key),
+        None => Ok((0.0, 0.0)),
+    };
+    let shift = optional_pair("shift")?;
+    let tilt = optional_pair("tilt")?;
+
+    Ok(Camera::new(
+        read_optional(json, "aspect_ratio", 1.0)?,
+        read_number(json, "vertical_fov")?,
+        view.origin(),
+        view.target(),
+        view.up(),
+        read_optional(json, "aperture", 0.0)?,
+        read_optional(json, "focus_dist", 1.0)?,
+    )
+    .set_exposure(read_optional(json, "exposure", 1.0)?)
+    .set_aperture(iris)
+    .set_cat_eye(read_optional(json, "cat_eye", 0.0)?)
+    .set_chromatic_aberration(read_optional(json, "chromatic_aberration", 0.0)?)
+    .set_distortion(distortion)
+    .set_shift(shift.0, shift.1)
+    .set_tilt(tilt.0, tilt.1))
+}
+
+fn view_members(view: View) -> Vec<(&'static str, Json)> {
+    vec![
+        ("origin", vector(view.origin())),
+        ("target", vector(view.target())),
+        ("up", vector(view.up())),
+    ]
+}
+
+fn read_view(json: &Json) -> io::Result<View> {
+    let up = match json.get("up") {
+        Some(_) => read_vector(json, "up")?,
+        None => Vec3::new(0.0, 1.0, 0.0),
+    };
+    Ok(View::new(
+        read_vector(json, "origin")?,
+        read_vector(json, "target")?,
+        up,
+    ))
+}
+
+fn encode_layout(layout: StereoLayout) -> Json {
+    Json::String(
+        match layout {
+            StereoLayout::SideBySide => "side_by_side",
+            StereoLayout::TopBottom => "top_bottom",
+        }
+        .to_string(),
+    )
+}
+
+fn decode_layout(json: &Json) -> io::Result<StereoLayout> {
+    match json.get("layout").and_then(Json::as_str) {
+        None | Some("side_by_side") => Ok(StereoLayout::SideBySide),
+        Some("top_bottom") => Ok(StereoLayout::TopBottom),
+        Some(_) => Err(invalid_data("layout must be side_by_side or top_bottom")),
+    }
+}
+
+// Ids, names, tags, parents and hiding are kept alongside each object's own
+// members, the last four left out when unset. Every object is written with
+// its id, which may leave gaps where objects were removed, and parents refer
+// to those ids.
+pub fn encode_objects(objects: &SceneObjects) -> Json {
+    Json::Array(
+        objects
+            .iter()
+            .map(|(id, object)| {
+                let mut json = encode_object(object);
+                if let Json::Object(members) = &mut json {
+                    let mut add = |key: &str, value: Json| members.push((key.to_string(), value));
+                    add("id", Json::Number(id as f64));
+                    if let Some(name) = objects.name(id) {
+                        add("name", Json::String(name.to_string()));
+                    }
+                    if !objects.tags(id).is_empty() {
+                        let tags = objects.tags(id).iter().cloned().map(Json::String);
+                        add("tags", Json::Array(tags.collect()));
+                    }
+                    if let Some(parent) = objects.parent(id) {
+                        add("parent", Json::Number(parent as f64));
+                    }
+                    if objects.is_hidden(id) {
+                        add("hidden", Json::Bool(true));
+                    }
+                }
+                json
+            })
+            .collect(),
+    )
+}
+
+// Objects keep the ids they're saved with, so parents and the object_id pass
+// refer to the same objects after reading a file back. Objects without an id
+// are given the next one along.
+fn decode_objects(json: &Json) -> io::Result<SceneObjects> {
+    let list = member(json, "objects")?
+        .as_array()
+        .ok_or_else(|| invalid_data("objects must be a list"))?;
+    let mut objects = SceneObjects::new();
+    let mut ids = Vec::with_capacity(list.len());
+    for item in list {
+        let object = decode_object(item)?;
+        let id = match item.get("id") {
+            Some(_) => read_count(item, "id")?,
+            None => ids.last().unwrap_or(&0) + 1,
+        };
+        if !objects.add_with_id(id, object) {
+            return Err(invalid_data(
+                "object ids must be counting numbers, rising through the list",
+            ));
+        }
+        ids.push(id);
+    }
+
+    for (&id, item) in ids.iter().zip(list) {
+        if let Some(name) = item.get("name") {
+            let name = name
+                .as_str()
+                .ok_or_else(|| invalid_data("object names must be strings"))?;
+            if !objects.set_name(id, Some(name)) {
+                return Err(invalid_data(&format!(
+                    "more than one object named {}",
+                    name
+                )));
+            }
+        }
+        if item.get("tags").is_some() {
+            let tags = read_list(item, "tags", |tag| {
+                tag.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid_data("object tags must be strings"))
+            })?;
+            for tag in tags {
+                objects.tag(id, &tag);
+            }
+        }
+        if item.get("parent").is_some()
+            && !objects.set_parent(id, Some(read_count(item, "parent")?))
+        {
+            return Err(invalid_data(
+                "object parents must be other objects, without loops",
+            ));
+        }
+        if read_bool(item, "hidden", false)? {
+            objects.set_hidden(id, true);
+        }
+    }
+    Ok(objects)
+}
+
+fn encode_distance(distance: &Distance) -> Json {
+    let pair =
+        |a: &Distance, b: &Distance| Json::Array(vec![encode_distance(a), encode_distance(b)]);
+    match distance {
+        Distance::Sphere(radius) => tagged("sphere", vec![("radius", Json::Number(*radius))]),
+        Distance::Cuboid(half_extents) => {
+            tagged("cuboid", vec![("half_extents", vector(*half_extents))])
+        }
+        Distance::Torus(major_radius, minor_radius) => tagged(
+            "torus",
+            vec![
+                ("major_radius", Json::Number(*major_radius)),
+                ("minor_radius", Json::Number(*minor_radius)),
+            ],
+        ),
+        Distance::Translate(offset, shape) => tagged(
+            "translate",
+            vec![
+                ("offset", vector(*offset)),
+                ("shape", encode_distance(shape)),
+            ],
+        ),
+        Distance::Union(a, b) => tagged("union", vec![("shapes", pair(a, b))]),
+        Distance::SmoothUnion(a, b, k) => tagged(
+            "smooth_union",
+            vec![("shapes", pair(a, b)), ("k", Json::Number(*k))],
+        ),
+        Distance::Twist(rate, shape) => tagged(
+            "twist",
+            vec![
+                ("rate", Json::Number(*rate)),
+                ("shape", encode_distance(shape)),
+            ],
+        ),
+        Distance::Repeat(period, shape) => tagged(
+            "repeat",
+            vec![
+                ("period", vector(*period)),
+                ("shape", encode_distance(shape)),
+            ],
+        ),
+        Distance::Displace(amplitude, frequency, shape) => tagged(
+            "displace",
+            vec![
+                ("amplitude", Json::Number(*amplitude)),
+                ("frequency", Json::Number(*frequency)),
+                ("shape", encode_distance(shape)),
+            ],
+        ),
+    }
+}
+
+fn decode_distance(json: &Json) -> io::Result<Distance> {
+    let shape = || decode_distance(member(json, "shape")?);
+    let pair = || match read_list(json, "shapes", decode_distance)?.as_slice() {
+        [a, b] => Ok((a.clone(), b.clone())),
+        _ => Err(invalid_data("distance unions need two shapes")),
+    };
+
+    Ok(match read_text(json, "type")? {
+        "sphere" => Distance::sphere(read_number(json, "radius")?),
+        "cuboid" => Distance::cuboid(read_vector(json, "half_extents")?),
+        "torus" => Distance::torus(
+            read_number(json, "major_radius")?,
+            read_number(json, "minor_radius")?,
+        ),
+        "translate" => shape()?.translate(read_vector(json, "offset")?),
+        "union" => {
+            let (a, b) = pair()?;
+            a.union(b)
+        }
+        "smooth_union" => {
+            let (a, b) = pair()?;
+            a.smooth_union(b, read_number(json, "k")?)
+        }
+        "twist" => shape()?.twist(read_number(json, "rate")?),
+        "repeat" => shape()?.repeat(read_vector(json, "period")?),
+        "displace" => shape()?.displace(
+            read_number(json, "amplitude")?,
+            read_number(json, "frequency")?,
+        ),
+        kind => return Err(invalid_data(&format!("unknown distance type {}", kind))),
+    })
+}
+
+// Normals and UVs may be left out, to be worked out from the triangles or
+// set to zero. Instead of triangles, a mesh may be given as polygon "faces",
+// such as a cage of quads, to be smoothed with "subdivision_levels" and
+// pushed along its normals by a "displacement" image, all as it's read;
+// saving the scene again writes the triangles that result.
+fn decode_mesh(json: &Json, material: Materials) -> io::Result<Object> {
+    let positions = read_list(json, "positions", read_point)?;
+    let polygons = json.get("faces").is_some();
+    let key = match polygons {
+        true => "faces",
+        false => "triangles",
+    };
+    let faces = read_list(json, key, |face| {
+        match face.as_array().map(|indices| {
+            indices
+                .iter()
+                .map(Json::as_usize)
+                .collect::<Option<Vec<usize>>>()
+        }) {
+            Some(Some(indices)) if indices.len() == 3 || polygons && indices.len() > 3 => {
+                Ok(indices)
+            }
+            _ if polygons => Err(invalid_data("mesh faces need three or more vertex indices")),
+            _ => Err(invalid_data("mesh triangles need three vertex indices")),
+        }
+    })?;
+    if faces.is_empty() {
+        return Err(invalid_data("a mesh needs at least one face"));
+    }
+    if faces
+        .iter()
+        .flatten()
+        .any(|&index| index >= positions.len())
+    {
+        return Err(invalid_data("mesh face refers to a missing vertex"));
+    }
+
+    let count = positions.len();
+    let normals = match json.get("normals") {
+        Some(_) => Some(read_list(json, "normals", read_point)?),
+        None => None,
+    };
+    if normals
+        .as_ref()
+        .is_some_and(|normals| normals.len() != count)
+    {
+        return Err(invalid_data("a mesh needs one normal per vertex"));
+    }
+    let uvs = match json.get("uvs") {
+        Some(_) => Some(read_list(json, "uvs", |uv| {
+            match uv.as_numbers().as_deref() {
+                Some(&[u, v]) => Ok((u, v)),
+                _ => Err(invalid_data("mesh UVs need two numbers")),
+            }
+        })?),
+        None => None,
+    };
+    if uvs.as_ref().is_some_and(|uvs| uvs.len() != count) {
+        return Err(invalid_data("a mesh needs one UV per vertex"));
+    }
+
+    let levels = match json.get("subdivision_levels") {
+        Some(_) => read_count(json, "subdivision_levels")?,
+        None => 0,
+    };
+    if levels > MAX_SUBDIVISION_LEVELS {
+        return Err(invalid_data(&format!(
+            "meshes can be subdivided at most {} times",
+            MAX_SUBDIVISION_LEVELS
+        )));
+    }
+    let displacement = match json.get("displacement") {
+        Some(displacement) => {
+            let image = read_image(displacement)?;
+            if image.width() == 0 || image.height() == 0 {
+                return Err(invalid_data("a displacement image needs values"));
+            }
+            Some((image, read_number(displacement, "scale")?))
+        }
+        None => None,
+    };
+
+    // Plain triangles are kept exactly as given, so saved scenes read back
+    // unchanged.
+    if !polygons && levels == 0 && displacement.is_none() {
+        let triangles = faces
+            .iter()
+            .map(|face| [face[0], face[1], face[2]])
+            .collect();
+        let mut mesh = Mesh::new(positions, triangles, material);
+        if let Some(normals) = normals {
+            mesh = mesh.set_normals(normals);
+        }
+        if let Some(uvs) = uvs {
+            mesh = mesh.set_uvs(uvs);
+        }
+        return Ok(Object::Mesh(mesh));
+    }
+
+    let mut cage = PolygonMesh::new(positions, faces);
+    if let Some(normals) = normals {
+        cage = cage.set_normals(normals);
+    }
+    if let Some(uvs) = uvs {
+        cage = cage.set_uvs(uvs);
+    }
+    let mut refined = cage.subdivide(levels);
+    if let Some((image, scale)) = displacement {
+        refined = refined
+            .displace(&image, scale)
+            .map_err(|_| invalid_data("a displaced mesh needs UVs"))?;
+    }
+    // There's always at least one face, checked above.
+    Ok(Object::Mesh(refined.triangulate(material).unwrap()))
+}
+
+// A greyscale image written as its size and values in rows from the top.
+fn read_image(json: &Json) -> io::Result<GreyImage> {
+    let (columns, rows) = (read_count(json, "columns")?, read_count(json, "rows")?);
+    let values = member(json, "values")?
+        .as_numbers()
+        .ok_or_else(|| invalid_data("image values must be numbers"))?;
+    match values.len() == columns * rows {
+        true => Ok(GreyImage::new(columns, rows, values)),
+        false => Err(invalid_data("image needs columns times rows values")),
+    }
+}
+
+fn tagged(kind: &str, members: Vec<(&str, Json)>) -> Json {
+    let mut tagged = vec![("type", Json::String(kind.to_string()))];
+    tagged.extend(members);
+    object(tagged)
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn vector(v: Vec3<f64>) -> Json {
+    numbers(&[v.x(), v.y(), v.z()])
+}
+
+fn numbers(values: &[f64]) -> Json {
+    Json::Array(values.iter().map(|&value| Json::Number(value)).collect())
+}
+
+fn member<'a>(json: &'a Json, key: &str) -> io::Result<&'a Json> {
+    json.get(key)
+        .ok_or_else(|| invalid_data(&format!("missing {}", key)))
+}
+
+fn read_number(json: &Json, key: &str) -> io::Result<f64> {
+    member(json, key)?
+        .as_f64()
+        .ok_or_else(|| invalid_data(&format!("{} must be a number", key)))
+}
+
+fn read_optional(json: &Json, key: &str, default: f64) -> io::Result<f64> {
+    match json.get(key) {
+        Some(_) => read_number(json, key),
+        None => Ok(default),
+    }
+}
+
+fn read_count(json: &Json, key: &str) -> io::Result<usize> {
+    member(json, key)?
+        .as_usize()
+        .ok_or_else(|| invalid_data(&format!("{} must be a whole number", key)))
+}
+
+fn read_bool(json: &Json, key: &str, default: bool) -> io::Result<bool> {
+    match json.get(key) {
+        Some(value) => value
+            .as_bool()
+            .ok_or_else(|| invalid_data(&format!("{} must be true or false", key))),
+        None => Ok(default),
+    }
+}
+
+fn read_text<'a>(json: &'a Json, key: &str) -> io::Result<&'a str> {
+    member(json, key)?
+        .as_str()
+        .ok_or_else(|| invalid_data(&format!("{} must be a string", key)))
+}
+
+fn read_pair(json: &Json, key: &str) -> io::Result<(f64, f64)> {
+    match member(json, key)?.as_numbers().as_deref() {
+        Some(&[a, b]) => Ok((a, b)),
+        _ => Err(invalid_data(&format!("{} must be two numbers", key))),
+    }
+}
+
+fn read_point(json: &Json) -> io::Result<Point3D> {
+    match json.as_numbers().as_deref() {
+        Some(&[x, y, z]) => Ok(Vec3::new(x, y, z)),
+        _ => Err(invalid_data("vectors must be three numbers")),
+    }
+}
+
+fn read_vector(json: &Json, key: &str) -> io::Result<Vec3<f64>> {
+    read_point(member(json, key)?)
+        .map_err(|_| invalid_data(&format!("{} must be three numbers", key)))
+}
+
+fn read_list<T, F>(json: &Json, key: &str, read: F) -> io::Result<Vec<T>>
+where
+    F: Fn(&Json) -> io::Result<T>,
+{
+    member(json, key)?
+        .as_array()
+        .ok_or_else(|| invalid_data(&format!("{} must be a list", key)))?
+        .iter()
+        .map(read)
+        .collect()
+}
+
+#[cfg(test)]
+mod scenefile_tests {
+    use super::*;
+    use crate::utils::seed_sampler;
+    use crate::vec3::Colour;
+
+    fn scene() -> Scene {
+        let matte = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.3, 0.2)));
+        let mirror = Materials::Metal(Metal::new(Colour::new(0.8, 0.8, 0.9), 0.1));
+        let glass = Materials::Dielectric(Dielectric::new(1.5));
+        let lamp = Materials::DiffuseLight(DiffuseLight::new(Colour::new(4.0, 4.0, 4.0)));
+
+        let mut world = SceneObjects::new();
+        let removed = world.add(Object::Sphere(Sphere::new(
+            Point3D::new(0.0, -5.0, 0.0),
+            1.0,
+            matte.clone(),
+        )));
+        world.remove(removed);
+        world.add(Object::Plane(Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            matte.clone(),
+        )));
+        let ball = world
+            .add_named(
+                "ball",
+                Object::Sphere(Sphere::new(Point3D::new(0.0, 1.0, 0.0), 1.0, glass)),
+            )
+            .unwrap();
+        world.add(Object::Cylinder(
+            Cylinder::new(
+                Point3D::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.5, 0.0),
+                0.5,
+                mirror.clone(),
+            )
+            .set_capped(true),
+        ));
+        let light = world.add(Object::Rect(Rect::xz(-1.0, 1.0, -1.0, 1.0, 4.0, lamp)));
+        world.tag(light, "lights");
+        let hidden = world.add(Object::Sphere(Sphere::new(
+            Point3D::new(0.0, 1.0, 3.0),
+            0.5,
+            Materials::Metal(Metal::new(Colour::new(0.9, 0.9, 0.9), 0.0)),
+        )));
+        world.set_parent(hidden, Some(ball));
+        world.set_hidden(hidden, true);
+        world.add(Object::Csg(Csg::new(
+            Operation::Difference,
+            Object::Cuboid(Cuboid::new(
+                Point3D::new(-3.0, 0.0, -1.0),
+                Point3D::new(-2.0, 1.0, 0.0),
+                mirror,
+            )),
+            Object::Sdf(Sdf::new(
+                Distance::sphere(0.6).translate(Vec3::new(-2.5, 0.5, -0.5)),
+                matte.clone(),
+            )),
+        )));
+        world.add(Object::Mesh(Mesh::new(
+            vec![
+                Point3D::new(-1.0, 0.0, 2.0),
+                Point3D::new(1.0, 0.0, 2.0),
+                Point3D::new(0.0, 1.0, 2.0),
+            ],
+            vec![[0, 1, 2]],
+            matte,
+        )));
+
+        let camera = Camera::new(
+            1.5,
+            40.0,
+            Point3D::new(0.0, 2.0, 8.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.1,
+            8.0,
+        )
+        .set_aperture(Aperture::polygon(6, 0.0))
+        .set_shift(0.1, 0.0)
+        .set_tilt(2.0, 0.0);
+        Scene::new(world, Cameras::Perspective(camera))
+            .set_background(Background::Uniform(Colour::new(0.1, 0.1, 0.2)))
+            .set_seed(42)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = scene();
+        let json = encode_scene(&original);
+        let text = format!("{:#}", json);
+        let read = parse(&text).unwrap();
+        assert_eq!(json, encode_scene(&read));
+        assert_eq!(42, read.seed());
+        let ids = |scene: &Scene| scene.world().ids().collect::<Vec<_>>();
+        assert_eq!(ids(&original), ids(&read));
+        assert_eq!(Some(3), read.world().parent(6));
+
+        seed_sampler(7);
+        let expected = original.render(8, 24, 16);
+        seed_sampler(7);
+        assert_eq!(expected, read.render(8, 24, 16));
+    }
+
+    #[test]
+    fn test_invalid_scenes() {
+        let camera = r#""camera": {"type": "perspective", "vertical_fov": 40,
+            "origin": [0, 0, 5], "target": [0, 0, 0]}"#;
+        let mesh = r#"{"type": "mesh", "positions": [[0, 0, 0]], "triangles": [[0, 1, 2]],
+            "material": {"type": "lambertian", "albedo": [1, 1, 1]}}"#;
+        let scene = |objects: &str| format!("{{{}, \"objects\": [{}]}}", camera, objects);
+
+        assert!(parse(&scene("")).is_ok());
+        assert!(parse(&scene(r#"{"type": "teapot"}"#)).is_err());
+        assert!(parse(&scene(mesh)).is_err());
+        let ball = |id: u32| {
+            format!(
+                r#"{{"type": "sphere", "id": {}, "center": [0, 0, 0], "radius": 1,
+                "material": {{"type": "lambertian", "albedo": [1, 1, 1]}}}}"#,
+                id
+            )
+        };
+        assert!(parse(&scene(&format!("{}, {}", ball(2), ball(5)))).is_ok());
+        assert!(parse(&scene(&format!("{}, {}", ball(5), ball(2)))).is_err());
+        assert!(parse(&scene(&ball(0))).is_err());
+
+        let masked = |columns: u32, rows: u32, values: &str| {
+            format!(
+                r#"{{"camera": {{"type": "perspective", "vertical_fov": 40,
+                "origin": [0, 0, 5], "target": [0, 0, 0], "iris": {{"type": "mask",
+                "columns": {}, "rows": {}, "values": [{}]}}}}, "objects": []}}"#,
+                columns, rows, values
+            )
+        };
+        assert!(parse(&masked(2, 1, "0, 1")).is_ok());
+        assert!(parse(&masked(2, 1, "0, 0")).is_err());
+        assert!(parse(&masked(0, 0, "")).is_err());
+    }
+
+    #[test]
+    fn test_mesh_subdivision() {
+        let camera = r#""camera": {"type": "perspective", "vertical_fov": 40,
+            "origin": [0, 0, 5], "target": [0, 0, 0]}"#;
+        let quad = |extra: &str| {
+            format!(
+                r#"{{{}, "objects": [{{"type": "mesh",
+                "positions": [[-1, -1, 0], [1, -1, 0], [1, 1, 0], [-1, 1, 0]],
+                "faces": [[0, 1, 2, 3]], {}
+                "material": {{"type": "lambertian", "albedo": [1, 1, 1]}}}}]}}"#,
+                camera, extra
+            )
+        };
+        let triangles = |scene: &Scene| match encode_scene(scene).get("objects") {
+            Some(Json::Array(objects)) => objects[0]
+                .get("triangles")
+                .and_then(Json::as_array)
+                .unwrap()
+                .len(),
+            _ => panic!("scene has no objects"),
+        };
+
+        assert_eq!(2, triangles(&parse(&quad("")).unwrap()));
+        let uvs = r#""uvs": [[0, 0], [1, 0], [1, 1], [0, 1]],"#;
+        let smooth = parse(&quad(&format!(r#"{} "subdivision_levels": 2,"#, uvs))).unwrap();
+        assert_eq!(32, triangles(&smooth));
+
+        let bump = r#""displacement": {"columns": 1, "rows": 1, "values": [1], "scale": 0.5},"#;
+        let displaced = parse(&quad(&format!("{} {}", uvs, bump))).unwrap();
+        seed_sampler(3);
+        let flat = parse(&quad(uvs)).unwrap().render(4, 8, 8);
+        seed_sampler(3);
+        assert_ne!(flat, displaced.render(4, 8, 8));
+
+        assert!(parse(&quad(bump)).is_err());
+        assert!(parse(&quad(r#""subdivision_levels": 7,"#)).is_err());
+    }
+}
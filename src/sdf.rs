@@ -0,0 +1,357 @@
+use crate::aabb::Aabb;
+use crate::material::Materials;
+use crate::objects::{Hit, HitRecord};
+use crate::ray::Ray;
+use crate::utils::clamp;
+use crate::vec3::{Point3D, Vec3};
+use std::f64::consts::PI;
+
+const MAX_STEPS: usize = 512;
+// Sphere tracing stops this close to the surface.
+const EPSILON: f64 = 1e-4;
+const NORMAL_STEP: f64 = 1e-4;
+// How far rays march through unbounded fields, such as repetitions.
+const FAR: f64 = 1e4;
+
+// A signed distance function, negative inside, built up as a tree. Shapes
+// start at the origin and are moved with `translate`.
+#[derive(Debug, Clone)]
+pub enum Distance {
+    Sphere(f64),
+    // Half of each side's length.
+    Cuboid(Vec3<f64>),
+    // Major and minor radii, around the y axis.
+    Torus(f64, f64),
+    Translate(Vec3<f64>, Box<Distance>),
+    Union(Box<Distance>, Box<Distance>),
+    // Blends the two within about `k` of where they meet.
+    SmoothUnion(Box<Distance>, Box<Distance>, f64),
+    // Turns the shape about the y axis by `rate` radians per unit of height.
+    Twist(f64, Box<Distance>),
+    // Repeats the shape every period along each axis; a period of zero
+    // leaves that axis alone.
+    Repeat(Vec3<f64>, Box<Distance>),
+    // Adds sin(fx) sin(fy) sin(fz) ripples with an amplitude and frequency.
+    Displace(f64, f64, Box<Distance>),
+}
+
+impl Distance {
+    pub fn sphere(radius: f64) -> Self {
+        Distance::Sphere(radius)
+    }
+
+    pub fn cuboid(half_extents: Vec3<f64>) -> Self {
+        Distance::Cuboid(half_extents)
+    }
+
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Self {
+        Distance::Torus(major_radius, minor_radius)
+    }
+
+    pub fn translate(self, offset: Vec3<f64>) -> Self {
+        Distance::Translate(offset, Box::new(self))
+    }
+
+    pub fn union(self, other: Distance) -> Self {
+        Distance::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: Distance, k: f64) -> Self {
+        Distance::SmoothUnion(Box::new(self), Box::new(other), k)
+    }
+
+    pub fn twist(self, rate: f64) -> Self {
+        Distance::Twist(rate, Box::new(self))
+    }
+
+    pub fn repeat(self, period: Vec3<f64>) -> Self {
+        Distance::Repeat(period, Box::new(self))
+    }
+
+    pub fn displace(self, amplitude: f64, frequency: f64) -> Self {
+        Distance::Displace(amplitude, frequency, Box::new(self))
+    }
+
+    pub fn evaluate(&self, p: Point3D) -> f64 {
+        match self {
+            Distance::Sphere(radius) => p.length() - radius,
+            Distance::Cuboid(half) => {
+                let q = Vec3::new(
+                    p.x().abs() - half.x(),
+                    p.y().abs() - half.y(),
+                    p.z().abs() - half.z(),
+                );
+                let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+                outside.length() + q.x().max(q.y()).max(q.z()).min(0.0)
+            }
+            Distance::Torus(major, minor) => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major;
+                (ring * ring + p.y() * p.y()).sqrt() - minor
+            }
+            Distance::Translate(offset, shape) => shape.evaluate(p - *offset),
+            Distance::Union(a, b) => a.evaluate(p).min(b.evaluate(p)),
+            Distance::SmoothUnion(a, b, k) => {
+                let (a, b) = (a.evaluate(p), b.evaluate(p));
+                let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+                b + (a - b) * h - k * h * (1.0 - h)
+            }
+            Distance::Twist(rate, shape) => {
+                let (sin, cos) = (rate * p.y()).sin_cos();
+                shape.evaluate(Point3D::new(
+                    cos * p.x() + sin * p.z(),
+                    p.y(),
+                    cos * p.z() - sin * p.x(),
+                ))
+            }
+            Distance::Repeat(period, shape) => {
+                let wrap = |x: f64, period: f64| match period > 0.0 {
+                    true => x - period * (x / period).round(),
+                    false => x,
+                };
+                shape.evaluate(Point3D::new(
+                    wrap(p.x(), period.x()),
+                    wrap(p.y(), period.y()),
+                    wrap(p.z(), period.z()),
+                ))
+            }
+            Distance::Displace(amplitude, frequency, shape) => {
+                let ripple = (frequency * p.x()).sin()
+                    * (frequency * p.y()).sin()
+                    * (frequency * p.z()).sin();
+                shape.evaluate(p) + amplitude * ripple
+            }
+        }
+    }
+
+    // None when the field repeats forever.
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Distance::Sphere(radius) => {
+                let extent = Vec3::new(*radius, *radius, *radius);
+                Some(Aabb::new(-extent, extent))
+            }
+            Distance::Cuboid(half) => Some(Aabb::new(-*half, *half)),
+            Distance::Torus(major, minor) => {
+                let extent = Vec3::new(major + minor, *minor, major + minor);
+                Some(Aabb::new(-extent, extent))
+            }
+            Distance::Translate(offset, shape) => {
+                let bounds = shape.bounds()?;
+                Some(Aabb::new(bounds.min() + *offset, bounds.max() + *offset))
+            }
+            Distance::Union(a, b) => Some(a.bounds()?.surrounding(&b.bounds()?)),
+            Distance::SmoothUnion(a, b, k) => {
+                // The blend never adds more than k / 4 to the closer shape.
+                Some(pad(&a.bounds()?.surrounding(&b.bounds()?), k / 4.0))
+            }
+            Distance::Twist(_, shape) => {
+                let bounds = shape.bounds()?;
+                let radius = twist_radius(&bounds);
+                Some(Aabb::new(
+                    Point3D::new(-radius, bounds.min().y(), -radius),
+                    Point3D::new(radius, bounds.max().y(), radius),
+                ))
+            }
+            Distance::Repeat(..) => None,
+            Distance::Displace(amplitude, _, shape) => Some(pad(&shape.bounds()?, amplitude.abs())),
+        }
+    }
+
+    // An upper bound on how fast the field changes with distance. Twists and
+    // displacements stretch it past 1, so sphere tracing divides its steps by
+    // this to avoid stepping through the surface.
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            Distance::Sphere(_) | Distance::Cuboid(_) | Distance::Torus(..) => 1.0,
+            Distance::Translate(_, shape) | Distance::Repeat(_, shape) => shape.lipschitz(),
+            Distance::Union(a, b) | Distance::SmoothUnion(a, b, _) => {
+                a.lipschitz().max(b.lipschitz())
+            }
+            Distance::Twist(rate, shape) => {
+                let radius = shape.bounds().map_or(1.0, |bounds| twist_radius(&bounds));
+                shape.lipschitz() * (1.0 + (rate * radius).powi(2)).sqrt()
+            }
+            Distance::Displace(amplitude, frequency, shape) => {
+                shape.lipschitz() + (amplitude * frequency).abs() * 3f64.sqrt()
+            }
+        }
+    }
+}
+
+// The furthest a box reaches from the y axis.
+fn twist_radius(bounds: &Aabb) -> f64 {
+    let x = bounds.min().x().abs().max(bounds.max().x().abs());
+    let z = bounds.min().z().abs().max(bounds.max().z().abs());
+    (x * x + z * z).sqrt()
+}
+
+fn pad(bounds: &Aabb, amount: f64) -> Aabb {
+    let amount = Vec3::new(amount, amount, amount);
+    Aabb::new(bounds.min() - amount, bounds.max() + amount)
+}
+
+// A shape given by a distance field and intersected by sphere tracing, with
+// normals from the field's gradient. Its UVs are spherical, from the normal.
+#[derive(Debug, Clone)]
+pub struct Sdf {
+    distance: Distance,
+    bounds: Option<Aabb>,
+    lipschitz: f64,
+    material: Materials,
+}
+
+impl Sdf {
+    pub fn new(distance: Distance, material: Materials) -> Self {
+        Sdf {
+            bounds: distance.bounds(),
+            lipschitz: distance.lipschitz(),
+            distance,
+            material,
+        }
+    }
+
+    pub fn distance(&self) -> &Distance {
+        &self.distance
+    }
+
+    pub fn material(&self) -> &Materials {
+        &self.material
+    }
+
+    pub fn center(&self) -> Point3D {
+        match self.bounds {
+            Some(bounds) => bounds.center(),
+            None => Point3D::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Central differences on a tetrahedron, which needs four samples.
+    fn gradient(&self, p: Point3D) -> Vec3<f64> {
+        [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .map(|&k| k * self.distance.evaluate(p + k * NORMAL_STEP))
+        .sum()
+    }
+}
+
+impl Hit for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let speed = ray.direction().length();
+        let (start, end) = match self.bounds {
+            Some(bounds) => bounds.hit_span(ray, t_min, t_max)?,
+            None => (t_min, t_max.min(FAR / speed)),
+        };
+
+        // March on whichever side of the surface the ray starts, so rays
+        // refracted into the shape find their way out again. A ray leaving
+        // the surface it starts on is not a hit until it has moved.
+        let side = self.distance.evaluate(ray.at(start)).signum();
+        let mut t = start;
+        let mut hit = None;
+        for step in 0..MAX_STEPS {
+            let distance = side * self.distance.evaluate(ray.at(t)) / self.lipschitz;
+            if distance < EPSILON && (step > 0 || t > t_min) {
+                hit = Some(t);
+                break;
+            }
+            t += distance.max(EPSILON) / speed;
+            if t > end {
+                break;
+            }
+        }
+
+        let t = hit?;
+        let point = ray.at(t);
+        let normal = self.gradient(point).unit();
+        let u = (-normal.z()).atan2(normal.x()) + PI;
+        let v = clamp(-normal.y(), -1.0, 1.0).acos();
+
+        Some(
+            HitRecord::new(&self.material)
+                .set_time(t)
+                .set_point(point)
+                .set_uv(u / (2.0 * PI), v / PI)
+                .set_face_normal(ray, &normal),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod sdf_tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::objects::{Object, SceneObjects, Sphere};
+
+    #[test]
+    fn test_matches_analytic_sphere_in_one_scene() {
+        let mut world = SceneObjects::new();
+        world.add(Object::Sphere(Sphere::new(
+            Point3D::new(-1.5, 0.0, 0.0),
+            1.0,
+            grey(),
+        )));
+        world.add(Object::Sdf(Sdf::new(
+            Distance::sphere(1.0).translate(Vec3::new(1.5, 0.0, 0.0)),
+            grey(),
+        )));
+
+        for &x in &[-1.5, 1.5] {
+            for &(dx, dy) in &[(0.0, 0.0), (0.3, -0.4), (-0.7, 0.5)] {
+                let ray = Ray::new(Point3D::new(x, 0.0, 5.0), Vec3::new(dx, dy, -5.0));
+                let record = world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+                let expected = Sphere::new(Point3D::new(x, 0.0, 0.0), 1.0, grey());
+                let analytic = expected.hit(&ray, 0.001, f64::INFINITY).unwrap();
+
+                assert_eq!(if x < 0.0 { 1 } else { 2 }, record.object_id());
+                assert!((record.time() - analytic.time()).abs() < 1e-4);
+                assert!((record.normal() - analytic.normal()).length() < 1e-3);
+                assert!((record.uv().0 - analytic.uv().0).abs() < 1e-3);
+            }
+        }
+
+        // A ray refracted into the shape finds the far side.
+        let sdf = Sdf::new(Distance::sphere(1.0), grey());
+        let inside = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        let record = sdf.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((record.time() - 0.5).abs() < 1e-4);
+        assert!(!record.front_face());
+    }
+
+    #[test]
+    fn test_operators() {
+        let sphere = Distance::sphere(1.0);
+        let moved = Distance::sphere(1.0).translate(Vec3::new(1.5, 0.0, 0.0));
+        let p = Point3D::new(0.75, 0.9, 0.0);
+        let blended = sphere.clone().smooth_union(moved.clone(), 0.5);
+        assert!(blended.evaluate(p) < sphere.clone().union(moved).evaluate(p));
+
+        let period = Vec3::new(3.0, 0.0, 3.0);
+        let tiles = Distance::cuboid(Vec3::new(0.5, 0.5, 0.5)).repeat(period);
+        let q = Point3D::new(0.2, 0.4, -0.1);
+        assert!((tiles.evaluate(q) - tiles.evaluate(q + period * 7.0)).abs() < 1e-9);
+        assert!(tiles.bounds().is_none());
+
+        // Twisted and rippled fields still trace onto their surfaces.
+        let twisted = Distance::cuboid(Vec3::new(0.5, 1.0, 0.5))
+            .twist(1.5)
+            .displace(0.05, 8.0);
+        assert!(twisted.lipschitz() > 1.0);
+        let sdf = Sdf::new(twisted, grey());
+        for i in 0..20 {
+            let y = -0.9 + 0.09 * i as f64;
+            let ray = Ray::new(Point3D::new(3.0, y, 0.1), Vec3::new(-1.0, 0.0, 0.0));
+            let record = sdf.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!(sdf.distance().evaluate(record.point()).abs() < 1e-3);
+            assert!(record.front_face());
+        }
+    }
+}
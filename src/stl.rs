@@ -0,0 +1,134 @@
+use crate::framebuffer::invalid_data;
+use crate::material::Materials;
+use crate::objects::SceneObjects;
+use crate::subdivision::PolygonMesh;
+use crate::vec3::Point3D;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Binary files have an 80 byte header and a triangle count before 50 bytes
+// per triangle.
+const HEADER_SIZE: usize = 84;
+const TRIANGLE_SIZE: usize = 50;
+
+// Reads an ASCII or binary STL file. Each facet keeps its own three
+// vertices, so parts keep their hard edges rather than being smoothed across
+// them; `PolygonMesh::weld` joins them up, as is needed before subdividing.
+// The stored facet normals are ignored in favour of the vertex order.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<PolygonMesh> {
+    from_bytes(&fs::read(path)?)
+}
+
+// The file as one mesh in the given material, ready to add to a scene.
+pub fn read_objects<P: AsRef<Path>>(path: P, material: Materials) -> io::Result<SceneObjects> {
+    read(path)?.into_objects(material)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> io::Result<PolygonMesh> {
+    // Binary files may also start with "solid", so their size decides.
+    let count = bytes
+        .get(80..HEADER_SIZE)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize);
+    let positions = match count {
+        Some(count) if bytes.len() == HEADER_SIZE + count * TRIANGLE_SIZE => binary(bytes, count),
+        _ if bytes.starts_with(b"solid") => ascii(bytes)?,
+        _ => return Err(invalid_data("not an STL file")),
+    };
+
+    if positions.is_empty() {
+        return Err(invalid_data("STL file has no facets"));
+    }
+    let faces = (0..positions.len() / 3)
+        .map(|facet| vec![3 * facet, 3 * facet + 1, 3 * facet + 2])
+        .collect();
+    Ok(PolygonMesh::new(positions, faces))
+}
+
+fn binary(bytes: &[u8], count: usize) -> Vec<Point3D> {
+    let float = |offset: usize| {
+        f32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]) as f64
+    };
+
+    let mut positions = Vec::with_capacity(3 * count);
+    for facet in 0..count {
+        // Skipping the normal, then three vertices.
+        let start = HEADER_SIZE + facet * TRIANGLE_SIZE + 12;
+        for vertex in 0..3 {
+            let offset = start + 12 * vertex;
+            positions.push(Point3D::new(
+                float(offset),
+                float(offset + 4),
+                float(offset + 8),
+            ));
+        }
+    }
+    positions
+}
+
+fn ascii(bytes: &[u8]) -> io::Result<Vec<Point3D>> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut words = text.split_whitespace();
+    let mut positions = Vec::new();
+    while let Some(word) = words.next() {
+        if word != "vertex" {
+            continue;
+        }
+        let mut coordinate = || -> io::Result<f64> {
+            words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid_data("invalid STL vertex"))
+        };
+        positions.push(Point3D::new(coordinate()?, coordinate()?, coordinate()?));
+    }
+
+    match positions.len() % 3 {
+        0 => Ok(positions),
+        _ => Err(invalid_data("STL facet without three vertices")),
+    }
+}
+
+#[cfg(test)]
+mod stl_tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary_agree() {
+        let ascii = from_bytes(
+            b"solid part\n\
+              facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 1 0 0\n  vertex 1 1 0\n \
+              endloop\nendfacet\n\
+              facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 1 1 0\n  vertex 0 1.5e0 0\n \
+              endloop\nendfacet\nendsolid part\n",
+        )
+        .unwrap();
+        assert_eq!(2, ascii.faces().len());
+        assert_eq!(Point3D::new(0.0, 1.5, 0.0), ascii.positions()[5]);
+
+        // A binary header that happens to start with "solid" as well.
+        let mut binary = b"solid".to_vec();
+        binary.resize(80, 0);
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for facet in ascii.positions().chunks(3) {
+            binary.extend_from_slice(&[0; 12]);
+            for vertex in facet {
+                for value in &[vertex.x(), vertex.y(), vertex.z()] {
+                    binary.extend_from_slice(&(*value as f32).to_le_bytes());
+                }
+            }
+            binary.extend_from_slice(&[0; 2]);
+        }
+        assert_eq!(ascii, from_bytes(&binary).unwrap());
+
+        // The facets share an edge once their vertices are welded.
+        let welded = ascii.weld();
+        assert_eq!(4, welded.positions().len());
+        assert_eq!(&[vec![0, 1, 2], vec![0, 2, 3]], welded.faces());
+    }
+}
@@ -0,0 +1,417 @@
+use crate::framebuffer::invalid_data;
+use crate::material::Materials;
+use crate::mesh::{unit_or_zero, Mesh};
+use crate::objects::{Object, SceneObjects};
+use crate::pnm::GreyImage;
+use crate::vec3::{Point3D, Vec3};
+use std::collections::HashMap;
+use std::io;
+
+// A mesh of polygons as modelled, such as a low-poly cage, to be refined and
+// displaced before it's split into triangles for rendering. Faces list their
+// vertices counter-clockwise seen from outside. Normals and UVs are
+// optional, one per vertex. Normals are only kept until the mesh is
+// subdivided or displaced, after which they're worked out again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonMesh {
+    positions: Vec<Point3D>,
+    faces: Vec<Vec<usize>>,
+    normals: Option<Vec<Vec3<f64>>>,
+    uvs: Option<Vec<(f64, f64)>>,
+}
+
+// An edge between two vertices, smallest index first, and the faces on it.
+struct Edge {
+    vertices: (usize, usize),
+    faces: Vec<usize>,
+}
+
+impl PolygonMesh {
+    pub fn new(positions: Vec<Point3D>, faces: Vec<Vec<usize>>) -> Self {
+        assert!(
+            faces.iter().all(|face| face.len() >= 3),
+            "polygons need at least three vertices"
+        );
+        assert!(
+            faces.iter().flatten().all(|&index| index < positions.len()),
+            "polygons refer to missing vertices"
+        );
+        PolygonMesh {
+            positions,
+            faces,
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    pub fn set_normals(mut self, normals: Vec<Vec3<f64>>) -> Self {
+        assert_eq!(normals.len(), self.positions.len());
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn set_uvs(mut self, uvs: Vec<(f64, f64)>) -> Self {
+        assert_eq!(uvs.len(), self.positions.len());
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn positions(&self) -> &[Point3D] {
+        &self.positions
+    }
+
+    pub fn faces(&self) -> &[Vec<usize>] {
+        &self.faces
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3<f64>]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[(f64, f64)]> {
+        self.uvs.as_deref()
+    }
+
+    // Merges vertices with the same position, normal and UV, so polygons
+    // that were stored apart, as in STL files, share their edges and can be
+    // subdivided smoothly. Vertices split along UV seams stay apart.
+    pub fn weld(&self) -> PolygonMesh {
+        let bits = |value: f64| (value + 0.0).to_bits();
+        let mut lookup = HashMap::new();
+        let mut kept = Vec::new();
+        let remap: Vec<usize> = (0..self.positions.len())
+            .map(|index| {
+                let point = self.positions[index];
+                let mut key = vec![bits(point.x()), bits(point.y()), bits(point.z())];
+                if let Some(normals) = &self.normals {
+                    let normal = normals[index];
+                    key.extend(&[bits(normal.x()), bits(normal.y()), bits(normal.z())]);
+                }
+                if let Some(uvs) = &self.uvs {
+                    key.extend(&[bits(uvs[index].0), bits(uvs[index].1)]);
+                }
+                *lookup.entry(key).or_insert_with(|| {
+                    kept.push(index);
+                    kept.len() - 1
+                })
+            })
+            .collect();
+
+        PolygonMesh {
+            positions: kept.iter().map(|&index| self.positions[index]).collect(),
+            faces: self
+                .faces
+                .iter()
+                .map(|face| face.iter().map(|&index| remap[index]).collect())
+                .collect(),
+            normals: self
+                .normals
+                .as_ref()
+                .map(|normals| kept.iter().map(|&index| normals[index]).collect()),
+            uvs: self
+                .uvs
+                .as_ref()
+                .map(|uvs| kept.iter().map(|&index| uvs[index]).collect()),
+        }
+    }
+
+    fn edges(&self) -> (Vec<Edge>, HashMap<(usize, usize), usize>) {
+        let mut edges: Vec<Edge> = Vec::new();
+        let mut lookup = HashMap::new();
+        for (index, face) in self.faces.iter().enumerate() {
+            for (&a, &b) in face.iter().zip(face.iter().cycle().skip(1)) {
+                let key = (a.min(b), a.max(b));
+                let edge = *lookup.entry(key).or_insert_with(|| {
+                    edges.push(Edge {
+                        vertices: key,
+                        faces: Vec::new(),
+                    });
+                    edges.len() - 1
+                });
+                edges[edge].faces.push(index);
+            }
+        }
+        (edges, lookup)
+    }
+
+    // Catmull-Clark subdivision, applied `levels` times, leaving only quads.
+    // Edges with a face on one side only are treated as creases, so open
+    // meshes keep their borders. UVs are interpolated linearly rather than
+    // smoothed, so they stay where they were painted.
+    pub fn subdivide(&self, levels: usize) -> PolygonMesh {
+        (0..levels).fold(self.clone(), |mesh, _| mesh.subdivide_once())
+    }
+
+    fn subdivide_once(&self) -> PolygonMesh {
+        let (edges, lookup) = self.edges();
+        let average = |points: &mut dyn Iterator<Item = Point3D>| {
+            let (sum, count) = points
+                .fold((Vec3::new(0.0, 0.0, 0.0), 0.0), |(sum, count), point| {
+                    (sum + point, count + 1.0)
+                });
+            sum / count
+        };
+
+        let face_points: Vec<Point3D> = self
+            .faces
+            .iter()
+            .map(|face| average(&mut face.iter().map(|&index| self.positions[index])))
+            .collect();
+        let midpoint =
+            |edge: &Edge| (self.positions[edge.vertices.0] + self.positions[edge.vertices.1]) / 2.0;
+        let edge_points: Vec<Point3D> = edges
+            .iter()
+            .map(|edge| match edge.faces.as_slice() {
+                [a, b] => (2.0 * midpoint(edge) + face_points[*a] + face_points[*b]) / 4.0,
+                _ => midpoint(edge),
+            })
+            .collect();
+
+        let mut around: Vec<Vec<&Edge>> = vec![Vec::new(); self.positions.len()];
+        for edge in &edges {
+            around[edge.vertices.0].push(edge);
+            around[edge.vertices.1].push(edge);
+        }
+        let vertex_points: Vec<Point3D> = self
+            .positions
+            .iter()
+            .zip(&around)
+            .map(|(&point, edges)| {
+                let creases: Vec<&&Edge> =
+                    edges.iter().filter(|edge| edge.faces.len() != 2).collect();
+                match creases.len() {
+                    0 if !edges.is_empty() => {
+                        let valence = edges.len() as f64;
+                        let mut faces: Vec<usize> =
+                            edges.iter().flat_map(|edge| edge.faces.clone()).collect();
+                        faces.sort_unstable();
+                        faces.dedup();
+                        let q = average(&mut faces.iter().map(|&face| face_points[face]));
+                        let r = average(&mut edges.iter().map(|edge| midpoint(edge)));
+                        (q + 2.0 * r + (valence - 3.0) * point) / valence
+                    }
+                    // Along a crease the neighbours either side weigh 1/8
+                    // each. Corners, and vertices where creases meet, stay put.
+                    2 if edges.len() > 2 => {
+                        (2.0 * point + midpoint(creases[0]) + midpoint(creases[1])) / 4.0
+                    }
+                    _ => point,
+                }
+            })
+            .collect();
+
+        // New vertices are the moved old ones, then one per edge, then one
+        // per face.
+        let (edge_base, face_base) = (self.positions.len(), self.positions.len() + edges.len());
+        let edge_index = |a: usize, b: usize| edge_base + lookup[&(a.min(b), a.max(b))];
+        let mut faces = Vec::new();
+        for (index, face) in self.faces.iter().enumerate() {
+            for (corner, &vertex) in face.iter().enumerate() {
+                let next = face[(corner + 1) % face.len()];
+                let previous = face[(corner + face.len() - 1) % face.len()];
+                faces.push(vec![
+                    vertex,
+                    edge_index(vertex, next),
+                    face_base + index,
+                    edge_index(previous, vertex),
+                ]);
+            }
+        }
+
+        let uvs = self.uvs.as_ref().map(|uvs| {
+            let mean = |indices: &mut dyn Iterator<Item = usize>| {
+                let (u, v, count) = indices.fold((0.0, 0.0, 0.0), |(u, v, count), index| {
+                    (u + uvs[index].0, v + uvs[index].1, count + 1.0)
+                });
+                (u / count, v / count)
+            };
+            let mut subdivided = uvs.clone();
+            subdivided.extend(
+                edges
+                    .iter()
+                    .map(|edge| mean(&mut [edge.vertices.0, edge.vertices.1].iter().cloned())),
+            );
+            subdivided.extend(
+                self.faces
+                    .iter()
+                    .map(|face| mean(&mut face.iter().cloned())),
+            );
+            subdivided
+        });
+
+        let mut positions = vertex_points;
+        positions.extend(edge_points);
+        positions.extend(face_points);
+        PolygonMesh {
+            positions,
+            faces,
+            normals: None,
+            uvs,
+        }
+    }
+
+    // Area-weighted, from each polygon's normal by Newell's method, so
+    // non-planar quads are handled.
+    pub fn vertex_normals(&self) -> Vec<Vec3<f64>> {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()];
+        for face in &self.faces {
+            let normal = face
+                .iter()
+                .zip(face.iter().cycle().skip(1))
+                .map(|(&a, &b)| self.positions[a].cross(&self.positions[b]))
+                .sum::<Vec3<f64>>()
+                / 2.0;
+            for &index in face {
+                normals[index] += normal;
+            }
+        }
+        normals.into_iter().map(unit_or_zero).collect()
+    }
+
+    // Moves each vertex along its normal by the image's value at its UV
+    // times `scale`, so a value of 0 leaves the surface where it is.
+    // Subdivide first so there are enough vertices to carry the detail.
+    // Meshes without UVs can't be displaced.
+    pub fn displace(&self, image: &GreyImage, scale: f64) -> io::Result<PolygonMesh> {
+        let uvs = self
+            .uvs
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "displacement needs UVs"))?;
+        let positions = self
+            .positions
+            .iter()
+            .zip(self.vertex_normals())
+            .zip(uvs)
+            .map(|((&point, normal), &(u, v))| point + image.sample(u, v) * scale * normal)
+            .collect();
+        Ok(PolygonMesh {
+            positions,
+            faces: self.faces.clone(),
+            normals: None,
+            uvs: self.uvs.clone(),
+        })
+    }
+
+    // Splits each polygon into a fan of triangles. Unless the normals were
+    // given, the mesh works them out again from the triangles. None if there
+    // are no polygons to split.
+    pub fn triangulate(&self, material: Materials) -> Option<Mesh> {
+        if self.faces.is_empty() {
+            return None;
+        }
+        let triangles = self
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+        let mut mesh = Mesh::new(self.positions.clone(), triangles, material);
+        if let Some(normals) = &self.normals {
+            mesh = mesh.set_normals(normals.clone());
+        }
+        Some(match &self.uvs {
+            Some(uvs) => mesh.set_uvs(uvs.clone()),
+            None => mesh,
+        })
+    }
+
+    // The mesh as one object in the given material, ready to add to a scene.
+    pub fn into_objects(self, material: Materials) -> io::Result<SceneObjects> {
+        let mesh = self
+            .triangulate(material)
+            .ok_or_else(|| invalid_data("mesh has no faces"))?;
+        let mut objects = SceneObjects::new();
+        objects.add(Object::Mesh(mesh));
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod subdivision_tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::objects::Hit;
+    use crate::ray::Ray;
+    use crate::vec3::Colour;
+
+    fn cube() -> PolygonMesh {
+        let positions = (0..8)
+            .map(|i| {
+                let side = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+                Point3D::new(side(1), side(2), side(4))
+            })
+            .collect();
+        let faces = vec![
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+        ];
+        PolygonMesh::new(positions, faces)
+    }
+
+    #[test]
+    fn test_cube_rounds_towards_sphere() {
+        let once = cube().subdivide(1);
+        assert_eq!((26, 24), (once.positions().len(), once.faces().len()));
+        // A corner of valence three moves to (Q + 2R) / 3 = 5/9 of the way.
+        let corner = once.positions()[7];
+        assert!((corner - Point3D::new(5.0, 5.0, 5.0) / 9.0).length() < 1e-12);
+
+        let smooth = cube().subdivide(3);
+        assert_eq!(6 * 4usize.pow(3), smooth.faces().len());
+        let radii: Vec<f64> = smooth
+            .positions()
+            .iter()
+            .map(|point| point.length())
+            .collect();
+        let low = radii.iter().cloned().fold(f64::INFINITY, f64::min);
+        let high = radii.iter().cloned().fold(0.0, f64::max);
+        assert!(high < 1.0 && high / low < 1.2, "{} to {}", low, high);
+
+        // Normals point outwards, as the faces were wound.
+        for (point, normal) in smooth.positions().iter().zip(smooth.vertex_normals()) {
+            assert!(point.unit().dot(&normal) > 0.9);
+        }
+    }
+
+    #[test]
+    fn test_displaced_plane() {
+        let square = PolygonMesh::new(
+            vec![
+                Point3D::new(0.0, 0.0, 0.0),
+                Point3D::new(0.0, 0.0, 1.0),
+                Point3D::new(1.0, 0.0, 1.0),
+                Point3D::new(1.0, 0.0, 0.0),
+            ],
+            vec![vec![0, 1, 2, 3]],
+        )
+        .set_uvs(vec![(0.0, 1.0), (0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+
+        // The border is a crease, so the open square stays flat and square.
+        let fine = square.subdivide(2);
+        assert_eq!(16, fine.faces().len());
+        for (point, uv) in fine.positions().iter().zip(fine.uvs().unwrap()) {
+            assert_eq!(0.0, point.y());
+            assert_eq!((point.x(), 1.0 - point.z()), *uv);
+        }
+
+        // A ramp rising along u tilts the surface, and the triangles' normals
+        // follow it.
+        let ramp = GreyImage::new(2, 1, vec![0.0, 1.0]);
+        let grey = Materials::Lambertian(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
+        let bare = PolygonMesh::new(square.positions().to_vec(), square.faces().to_vec());
+        assert!(bare.displace(&ramp, 0.5).is_err());
+        let mesh = fine
+            .displace(&ramp, 0.5)
+            .unwrap()
+            .triangulate(grey)
+            .unwrap();
+        let ray = Ray::new(Point3D::new(0.6, 2.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        let record = mesh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.point().y() - 0.3).abs() < 1e-9);
+        let expected = Vec3::new(-0.5, 1.0, 0.0).unit();
+        assert!((record.normal() - expected).length() < 1e-9);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::vec3::{Point3D, Vec3};
+
+// An affine transform as a 4x4 matrix, stored column by column as glTF and
+// PBRT files write them. Combining `a.then(b)` gives a transform that applies
+// `b` first and then `a`, as the nested transforms of a scene file do.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    m: [f64; 16],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform::scale(1.0, 1.0, 1.0)
+    }
+
+    pub fn from_columns(m: [f64; 16]) -> Self {
+        Transform { m }
+    }
+
+    pub fn translate(offset: Vec3<f64>) -> Self {
+        let mut transform = Transform::identity();
+        transform.m[12..15].copy_from_slice(&[offset.x(), offset.y(), offset.z()]);
+        transform
+    }
+
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        Transform::from_columns([
+            x, 0.0, 0.0, 0.0, 0.0, y, 0.0, 0.0, 0.0, 0.0, z, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+
+    // Anticlockwise by `degrees` looking down the axis towards the origin.
+    pub fn rotate(degrees: f64, axis: Vec3<f64>) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        Transform::from_columns([
+            x * x + (1.0 - x * x) * cos,
+            x * y * (1.0 - cos) + z * sin,
+            x * z * (1.0 - cos) - y * sin,
+            0.0,
+            x * y * (1.0 - cos) - z * sin,
+            y * y + (1.0 - y * y) * cos,
+            y * z * (1.0 - cos) + x * sin,
+            0.0,
+            x * z * (1.0 - cos) + y * sin,
+            y * z * (1.0 - cos) - x * sin,
+            z * z + (1.0 - z * z) * cos,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ])
+    }
+
+    // From a unit quaternion (x, y, z, w).
+    pub fn from_quaternion(x: f64, y: f64, z: f64, w: f64) -> Self {
+        Transform::from_columns([
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+            0.0,
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+            0.0,
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ])
+    }
+
+    pub fn columns(&self) -> [f64; 16] {
+        self.m
+    }
+
+    pub fn then(&self, inner: &Transform) -> Transform {
+        let mut product = [0.0; 16];
+        for column in 0..4 {
+            for row in 0..4 {
+                product[column * 4 + row] = (0..4)
+                    .map(|k| self.m[k * 4 + row] * inner.m[column * 4 + k])
+                    .sum();
+            }
+        }
+        Transform { m: product }
+    }
+
+    // By Gauss-Jordan elimination with partial pivoting. None if the
+    // transform squashes space flat.
+    pub fn inverse(&self) -> Option<Transform> {
+        let mut a = self.m;
+        let mut inverse = Transform::identity().m;
+        let at = |column: usize, row: usize| column * 4 + row;
+        for column in 0..4 {
+            let pivot = (column..4).max_by(|&r, &s| {
+                a[at(column, r)]
+                    .abs()
+                    .partial_cmp(&a[at(column, s)].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+            if a[at(column, pivot)].abs() < 1e-300 {
+                return None;
+            }
+            for c in 0..4 {
+                a.swap(at(c, column), at(c, pivot));
+                inverse.swap(at(c, column), at(c, pivot));
+            }
+
+            let scale = 1.0 / a[at(column, column)];
+            for c in 0..4 {
+                a[at(c, column)] *= scale;
+                inverse[at(c, column)] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = a[at(column, row)];
+                for c in 0..4 {
+                    a[at(c, row)] -= factor * a[at(c, column)];
+                    inverse[at(c, row)] -= factor * inverse[at(c, column)];
+                }
+            }
+        }
+        Some(Transform { m: inverse })
+    }
+
+    pub fn vector(&self, v: Vec3<f64>) -> Vec3<f64> {
+        let m = &self.m;
+        Vec3::new(
+            m[0] * v.x() + m[4] * v.y() + m[8] * v.z(),
+            m[1] * v.x() + m[5] * v.y() + m[9] * v.z(),
+            m[2] * v.x() + m[6] * v.y() + m[10] * v.z(),
+        )
+    }
+
+    pub fn point(&self, p: Point3D) -> Point3D {
+        self.vector(p) + Vec3::new(self.m[12], self.m[13], self.m[14])
+    }
+
+    // By the inverse transpose, up to scale, so normals stay perpendicular
+    // to the surface under non-uniform scaling.
+    pub fn normal(&self, n: Vec3<f64>) -> Vec3<f64> {
+        let [x, y, z] = self.axes();
+        let normal = n.x() * y.cross(&z) + n.y() * z.cross(&x) + n.z() * x.cross(&y);
+        match self.determinant() < 0.0 {
+            true => -normal,
+            false => normal,
+        }
+    }
+
+    // Negative for transforms that mirror space, turning surfaces inside out.
+    pub fn determinant(&self) -> f64 {
+        let [x, y, z] = self.axes();
+        x.dot(&y.cross(&z))
+    }
+
+    fn axes(&self) -> [Vec3<f64>; 3] {
+        let column = |i: usize| Vec3::new(self.m[4 * i], self.m[4 * i + 1], self.m[4 * i + 2]);
+        [column(0), column(1), column(2)]
+    }
+}
+
+#[cfg(test)]
+mod transform_tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_and_invert() {
+        let transform = Transform::translate(Vec3::new(1.0, 2.0, 3.0))
+            .then(&Transform::rotate(90.0, Vec3::new(0.0, 0.0, 1.0)))
+            .then(&Transform::scale(2.0, 1.0, 1.0));
+
+        // Scaled, then a quarter turn taking x to y, then moved.
+        let point = transform.point(Point3D::new(1.0, 0.0, 0.0));
+        assert!((point - Point3D::new(1.0, 4.0, 3.0)).length() < 1e-12);
+        let quarter = Transform::from_quaternion(0.0, 0.0, 0.5f64.sqrt(), 0.5f64.sqrt());
+        let rotation = Transform::rotate(90.0, Vec3::new(0.0, 0.0, 2.0));
+        for (a, b) in quarter.columns().iter().zip(&rotation.columns()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+
+        let inverse = transform.inverse().unwrap();
+        let back = inverse.point(point);
+        assert!((back - Point3D::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!(Transform::scale(1.0, 0.0, 1.0).inverse().is_none());
+
+        // A normal to the plane x = y stays perpendicular to it once
+        // stretched along x.
+        let stretch = Transform::scale(2.0, 1.0, 1.0);
+        let normal = stretch.normal(Vec3::new(1.0, -1.0, 0.0));
+        assert!(normal.dot(&stretch.vector(Vec3::new(1.0, 1.0, 0.0))).abs() < 1e-12);
+        assert!(Transform::scale(-1.0, 1.0, 1.0).determinant() < 0.0);
+    }
+}
@@ -1,5 +1,11 @@
 use crate::vec3::Vec3;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static SAMPLER: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
 
 pub fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
     if val < min {
@@ -11,6 +17,37 @@ pub fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
     }
 }
 
+pub fn seed_sampler(seed: u64) {
+    SAMPLER.with(|sampler| *sampler.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// Mixes the render seed, sample index and pixel index so every pixel sample
+// draws from its own reproducible stream, independent of thread scheduling.
+pub fn sample_seed(seed: u64, sample: u64, pixel: u64) -> u64 {
+    let mut z = seed
+        ^ sample.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ pixel.wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// 64-bit FNV-1a, for hashes that must stay the same from one build or
+// platform to the next, unlike the standard library's.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn random_double() -> f64 {
+    SAMPLER.with(|sampler| sampler.borrow_mut().gen::<f64>())
+}
+
+pub fn random_range(min: f64, max: f64) -> f64 {
+    min + (max - min) * random_double()
+}
+
 pub fn random_unit_sphere() -> Vec3<f64> {
     let mut point = Vec3::random_from_range(-1.0, 1.0);
 
@@ -26,12 +63,10 @@ pub fn random_unit_vec() -> Vec3<f64> {
 }
 
 pub fn randon_unit_disk() -> Vec3<f64> {
-    let mut rng = rand::thread_rng();
-
-    let mut vec = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+    let mut vec = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
 
     while vec.length_sqrd() >= 1.0 {
-        vec = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+        vec = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
     }
 
     vec
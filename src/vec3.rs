@@ -1,5 +1,4 @@
-use crate::utils::clamp;
-use rand::{thread_rng, Rng};
+use crate::utils::{clamp, random_range};
 use std::fmt;
 use std::iter::Sum;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
@@ -86,23 +85,15 @@ impl Vec3<f64> {
     }
 
     pub fn random_from_range(min: f64, max: f64) -> Self {
-        let mut rng = thread_rng();
-
         Vec3 {
-            x: rng.gen_range(min..=max),
-            y: rng.gen_range(min..=max),
-            z: rng.gen_range(min..=max),
+            x: random_range(min, max),
+            y: random_range(min, max),
+            z: random_range(min, max),
         }
     }
 
     pub fn random() -> Self {
-        let mut rng = thread_rng();
-
-        Vec3 {
-            x: rng.gen_range(0.0..=1.0),
-            y: rng.gen_range(0.0..=1.0),
-            z: rng.gen_range(0.0..=1.0),
-        }
+        Vec3::random_from_range(0.0, 1.0)
     }
 
     pub fn near_zero(&self) -> bool {
@@ -271,7 +262,7 @@ impl Transpose for Vec<Vec<Vec3<f64>>> {
         (0..self[0].len())
             .map(|i| {
                 self.iter()
-                    .map(|inner| inner[i].clone())
+                    .map(|inner| inner[i])
                     .collect::<Vec<Vec3<f64>>>()
             })
             .collect()
@@ -298,12 +289,12 @@ mod vec3_tests {
         let test = Vec3::new(0.0, 1.0, 2.0);
         let t2 = Vec3::new(1.0, 2.0, 3.0);
         let t3 = Vec3::new(2.0, 3.0, 4.0);
-        let test_vec = vec![vec![test, test, test], vec![test, test, test]];
+        let test_vec = vec![vec![test, t2, t3], vec![test, t2, t3]];
 
         assert_eq!(
-            vec![test, t2],
+            vec![test * 2.0, t2 * 2.0, t3 * 2.0],
             test_vec
-                .clone()
+                .transpose()
                 .into_iter()
                 .map(|x| x.into_iter().sum::<Colour>())
                 .collect::<Vec<Vec3<f64>>>()